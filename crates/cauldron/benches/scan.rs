@@ -0,0 +1,107 @@
+use cauldron::mem::scan::{Pattern, SignatureSet};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+// 32 MiB of noise with the pattern planted at the end, roughly the size of a game's .text
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+const PATTERN: &str = "48 8B C4 4C 89 40 ? 55 53 57 41 54 48 8D A8 58 FE FF FF";
+const PLANTED: [u8; 19] = [
+    0x48, 0x8B, 0xC4, 0x4C, 0x89, 0x40, 0x18, 0x55, 0x53, 0x57, 0x41, 0x54, 0x48, 0x8D, 0xA8, 0x58,
+    0xFE, 0xFF, 0xFF,
+];
+
+fn image() -> Vec<u8> {
+    let mut state = 0x2545F4914F6CDD1Du64;
+    let mut image: Vec<u8> = (0..IMAGE_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    image[IMAGE_SIZE - PLANTED.len()..].copy_from_slice(&PLANTED);
+    image
+}
+
+/// The scan `find_pattern` used before [Pattern].
+fn naive_find(data: &[u8], pattern: &[(u8, bool)]) -> Option<usize> {
+    data.windows(pattern.len()).position(|pos| {
+        pos.iter()
+            .enumerate()
+            .all(|(i, b)| pattern[i].1 || pattern[i].0.eq(b))
+    })
+}
+
+fn scan(c: &mut Criterion) {
+    let image = image();
+    let parsed: Vec<(u8, bool)> = cauldron::mem::offset::parse_pattern(PATTERN)
+        .unwrap()
+        .iter()
+        .map(|b| (b.value, b.is_wildcard()))
+        .collect();
+    let pattern = Pattern::new(PATTERN).unwrap();
+    assert_eq!(naive_find(&image, &parsed), pattern.find(&image));
+
+    let mut group = c.benchmark_group("scan");
+    group.sample_size(20);
+    group.bench_function("naive", |b| {
+        b.iter(|| naive_find(black_box(&image), black_box(&parsed)))
+    });
+    group.bench_function("horspool", |b| b.iter(|| pattern.find(black_box(&image))));
+    group.bench_function("horspool_find_all", |b| {
+        b.iter(|| pattern.find_all(black_box(&image)).count())
+    });
+    group.finish();
+}
+
+/// 32 patterns taken from all over the image, so every one of them is found.
+fn signatures(image: &[u8]) -> Vec<String> {
+    (0..32)
+        .map(|i| {
+            let start = (i + 1) * (IMAGE_SIZE / 33);
+            image[start..start + 16]
+                .iter()
+                .enumerate()
+                .map(|(j, b)| {
+                    if j % 5 == 4 {
+                        String::from("?")
+                    } else {
+                        format!("{b:02X}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+fn batch(c: &mut Criterion) {
+    let image = image();
+    let signatures = signatures(&image);
+    let patterns: Vec<Pattern> = signatures
+        .iter()
+        .map(|s| Pattern::new(s).unwrap())
+        .collect();
+    let mut set = SignatureSet::new();
+    for (i, signature) in signatures.iter().enumerate() {
+        set.add(&i.to_string(), signature).unwrap();
+    }
+    assert_eq!(set.scan(&image).missing().count(), 0);
+
+    let mut group = c.benchmark_group("batch");
+    group.sample_size(10);
+    group.bench_function("separate", |b| {
+        b.iter(|| {
+            patterns
+                .iter()
+                .map(|p| p.find(black_box(&image)))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("signature_set", |b| b.iter(|| set.scan(black_box(&image))));
+    group.finish();
+}
+
+criterion_group!(benches, scan, batch);
+criterion_main!(benches);
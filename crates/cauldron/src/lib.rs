@@ -1,21 +1,181 @@
 use crate::log::LogLevel;
-use std::ffi::{CString, c_char, c_void};
+use crate::mem::module::Module;
+use crate::registry::{
+    ChangeCallback, EntryCallback, NamespaceCallback, RegistryChange, RegistryEntry, RegistryValue,
+};
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::panic::{AssertUnwindSafe, catch_unwind};
 
 pub mod log;
 pub mod mem;
 pub mod mod_info;
+pub mod registry;
 
+pub use cauldron_macros::sig;
+
+// lets `sig!`'s `::cauldron` paths resolve inside this crate too
+extern crate self as cauldron;
+
+/// Current [CauldronApi] version, bumped whenever fields are appended to it.
+pub const CAULDRON_API_VERSION: u32 = 7;
+
+/// The api table the loader passes to `CauldronMod_Load`.
+///
+/// Fields are only ever appended, so a table with a higher [`version`](CauldronApi::version) than
+/// the one a mod was built against is still compatible with it.
+///
+/// Every mod gets its own table, which the loader uses to tell mods apart. Always use the table
+/// through the pointer given to `CauldronMod_Load`, never a copy of it.
 #[repr(C)]
 pub struct CauldronApi {
+    /// Size of this struct in bytes, as built by the loader.
+    pub size: u32,
+    /// Version of this table, see [CAULDRON_API_VERSION].
+    pub version: u32,
+
     pub query_ptr: extern "C" fn(namespace: *const c_char, name: *const c_char) -> *const c_void,
     pub register_ptr:
         extern "C" fn(namespace: *const c_char, name: *const c_char, ptr: *const c_void) -> bool,
 
     /// Your bog-standard logging function.
     pub log: extern "C" fn(level: LogLevel, target: *const c_char, message: *const c_char),
+
+    /// Added in v2, see [CauldronApi::hook_install].
+    pub hook_install_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        target: *const c_void,
+        detour: *const c_void,
+        priority: i32,
+    ) -> *const *const c_void,
+    pub hook_enable_ptr: extern "C" fn(api: *const CauldronApi, target: *const c_void) -> bool,
+    pub hook_disable_ptr: extern "C" fn(api: *const CauldronApi, target: *const c_void) -> bool,
+    pub hook_remove_ptr: extern "C" fn(api: *const CauldronApi, target: *const c_void) -> bool,
+
+    /// Added in v3, see [CauldronApi::patch].
+    pub patch_apply_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        address: *mut c_void,
+        expected: *const u8,
+        data: *const u8,
+        len: usize,
+    ) -> u64,
+    pub patch_restore_ptr: extern "C" fn(api: *const CauldronApi, id: u64) -> bool,
+
+    /// Added in v4, see [CauldronApi::register].
+    pub registry_register_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        namespace: *const c_char,
+        name: *const c_char,
+        ptr: *const c_void,
+    ) -> bool,
+    pub registry_unregister_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        namespace: *const c_char,
+        name: *const c_char,
+    ) -> bool,
+    pub registry_replace_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        namespace: *const c_char,
+        name: *const c_char,
+        ptr: *const c_void,
+        priority: i32,
+    ) -> bool,
+
+    /// Added in v5, see [CauldronApi::register_typed].
+    pub registry_register_typed_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        namespace: *const c_char,
+        name: *const c_char,
+        ptr: *const c_void,
+        descriptor: *const c_char,
+    ) -> bool,
+    pub registry_replace_typed_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        namespace: *const c_char,
+        name: *const c_char,
+        ptr: *const c_void,
+        priority: i32,
+        descriptor: *const c_char,
+    ) -> bool,
+    pub registry_query_typed_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        namespace: *const c_char,
+        name: *const c_char,
+        descriptor: *const c_char,
+    ) -> *const c_void,
+
+    /// Added in v6, see [CauldronApi::namespaces].
+    pub registry_namespaces_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        callback: NamespaceCallback,
+        context: *mut c_void,
+    ) -> usize,
+    pub registry_names_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        namespace: *const c_char,
+        callback: EntryCallback,
+        context: *mut c_void,
+    ) -> usize,
+    pub registry_glob_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        pattern: *const c_char,
+        callback: EntryCallback,
+        context: *mut c_void,
+    ) -> usize,
+
+    /// Added in v7, see [CauldronApi::subscribe].
+    pub registry_subscribe_ptr: extern "C" fn(
+        api: *const CauldronApi,
+        pattern: *const c_char,
+        callback: ChangeCallback,
+        context: *mut c_void,
+    ) -> u64,
+    pub registry_unsubscribe_ptr: extern "C" fn(api: *const CauldronApi, id: u64) -> bool,
+}
+
+const _: () = assert!(std::mem::offset_of!(CauldronApi, size) == 0x0);
+const _: () = assert!(std::mem::offset_of!(CauldronApi, version) == 0x4);
+
+/// The unversioned api table, given to mods built before [CauldronApi] had a header, i.e. without
+/// [CauldronMod_ApiHeader].
+#[repr(C)]
+pub struct CauldronApiV0 {
+    pub query_ptr: extern "C" fn(namespace: *const c_char, name: *const c_char) -> *const c_void,
+    pub register_ptr:
+        extern "C" fn(namespace: *const c_char, name: *const c_char, ptr: *const c_void) -> bool,
+    pub log: extern "C" fn(level: LogLevel, target: *const c_char, message: *const c_char),
+}
+
+/// The [CAULDRON_API_VERSION] a mod is built against.
+///
+/// Exported by every mod using this crate, so the loader gives them a [CauldronApi] even if they
+/// don't [export a minimum version](export_api_version).
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+pub static CauldronMod_ApiHeader: u32 = CAULDRON_API_VERSION;
+
+/// Export `CauldronMod_ApiVersion`, the minimum [CauldronApi] version a mod needs.
+///
+/// That's the newest version whose fields the mod uses without checking
+/// [supports](CauldronApi::supports) first, not the [CAULDRON_API_VERSION] it's built against.
+/// The loader refuses to load mods that need a newer version than it provides.
+#[macro_export]
+macro_rules! export_api_version {
+    ($version:expr) => {
+        #[unsafe(no_mangle)]
+        #[allow(non_snake_case)]
+        pub extern "C-unwind" fn CauldronMod_ApiVersion() -> u32 {
+            $version
+        }
+    };
 }
 
 impl CauldronApi {
+    /// Whether this table has everything added up to and including `version`.
+    pub fn supports(&self, version: u32) -> bool {
+        self.version >= version
+    }
+
     pub fn query(&self, namespace: &str, name: &str) -> Option<*const c_void> {
         let c_namespace = CString::new(namespace).unwrap();
         let c_name = CString::new(name).unwrap();
@@ -24,12 +184,388 @@ impl CauldronApi {
         if result.is_null() { None } else { Some(result) }
     }
 
+    /// Add `ptr` to the registry as `namespace`/`name`, owned by this mod. Fails if the name is
+    /// already taken, the loader logs who by.
     pub fn register(&self, namespace: &str, name: &str, ptr: *const c_void) -> bool {
         let c_namespace = CString::new(namespace).unwrap();
         let c_name = CString::new(name).unwrap();
 
+        if self.supports(4) {
+            return (self.registry_register_ptr)(self, c_namespace.as_ptr(), c_name.as_ptr(), ptr);
+        }
         (self.register_ptr)(c_namespace.into_raw(), c_name.into_raw(), ptr)
     }
+
+    /// Remove this mod's entry for `namespace`/`name`, which uncovers the entry it
+    /// [replaced](CauldronApi::replace), if any.
+    pub fn unregister(&self, namespace: &str, name: &str) -> bool {
+        let c_namespace = CString::new(namespace).unwrap();
+        let c_name = CString::new(name).unwrap();
+
+        self.supports(4)
+            && (self.registry_unregister_ptr)(self, c_namespace.as_ptr(), c_name.as_ptr())
+    }
+
+    /// Make `ptr` what `namespace`/`name` is queried as, registering it if nobody has.
+    ///
+    /// Registered entries have priority 0, an entry can only be replaced by its owner or with a
+    /// higher `priority` than it has. Entries replaced by others come back when they're
+    /// unregistered.
+    pub fn replace(&self, namespace: &str, name: &str, ptr: *const c_void, priority: i32) -> bool {
+        let c_namespace = CString::new(namespace).unwrap();
+        let c_name = CString::new(name).unwrap();
+
+        self.supports(4)
+            && (self.registry_replace_ptr)(
+                self,
+                c_namespace.as_ptr(),
+                c_name.as_ptr(),
+                ptr,
+                priority,
+            )
+    }
+
+    /// [register](CauldronApi::register) `value` tagged with its
+    /// [descriptor](crate::registry::RegistryType::descriptor), so typed queries and replacements
+    /// with another type fail. Registered untyped if the loader is too old.
+    pub fn register_typed<T: RegistryValue>(&self, namespace: &str, name: &str, value: T) -> bool {
+        if !self.supports(5) {
+            return self.register(namespace, name, value.into_ptr());
+        }
+
+        let c_namespace = CString::new(namespace).unwrap();
+        let c_name = CString::new(name).unwrap();
+        let c_descriptor = CString::new(T::descriptor()).unwrap();
+        (self.registry_register_typed_ptr)(
+            self,
+            c_namespace.as_ptr(),
+            c_name.as_ptr(),
+            value.into_ptr(),
+            c_descriptor.as_ptr(),
+        )
+    }
+
+    /// [replace](CauldronApi::replace) with a typed entry, fails if the entry it replaces was
+    /// registered with another type.
+    pub fn replace_typed<T: RegistryValue>(
+        &self,
+        namespace: &str,
+        name: &str,
+        value: T,
+        priority: i32,
+    ) -> bool {
+        if !self.supports(5) {
+            return self.replace(namespace, name, value.into_ptr(), priority);
+        }
+
+        let c_namespace = CString::new(namespace).unwrap();
+        let c_name = CString::new(name).unwrap();
+        let c_descriptor = CString::new(T::descriptor()).unwrap();
+        (self.registry_replace_typed_ptr)(
+            self,
+            c_namespace.as_ptr(),
+            c_name.as_ptr(),
+            value.into_ptr(),
+            priority,
+            c_descriptor.as_ptr(),
+        )
+    }
+
+    /// [query](CauldronApi::query) an entry as a `T`, e.g.
+    /// `api.query_typed::<extern "C" fn(u32) -> *mut c_void>(...)`.
+    ///
+    /// Returns `None` if the entry was registered as another type, the loader logs both types.
+    /// Entries registered untyped can't be checked and are returned as they are.
+    ///
+    /// # Safety
+    /// Untyped entries, and every entry if the loader is too old, must be a valid `T`.
+    pub unsafe fn query_typed<T: RegistryValue>(&self, namespace: &str, name: &str) -> Option<T> {
+        if !self.supports(5) {
+            return self
+                .query(namespace, name)
+                .map(|ptr| unsafe { T::from_ptr(ptr) });
+        }
+
+        let c_namespace = CString::new(namespace).unwrap();
+        let c_name = CString::new(name).unwrap();
+        let c_descriptor = CString::new(T::descriptor()).unwrap();
+        let result = (self.registry_query_typed_ptr)(
+            self,
+            c_namespace.as_ptr(),
+            c_name.as_ptr(),
+            c_descriptor.as_ptr(),
+        );
+        if result.is_null() {
+            None
+        } else {
+            Some(unsafe { T::from_ptr(result) })
+        }
+    }
+
+    /// Every namespace with registry entries, sorted. Empty if the loader is too old.
+    pub fn namespaces(&self) -> impl Iterator<Item = String> + use<> {
+        extern "C" fn collect(context: *mut c_void, namespace: *const c_char) {
+            let namespaces = unsafe { &mut *(context as *mut Vec<String>) };
+            namespaces.push(
+                unsafe { CStr::from_ptr(namespace) }
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+
+        let mut namespaces: Vec<String> = Vec::new();
+        if self.supports(6) {
+            (self.registry_namespaces_ptr)(
+                self,
+                collect,
+                &mut namespaces as *mut Vec<String> as *mut c_void,
+            );
+        }
+        namespaces.into_iter()
+    }
+
+    /// Every entry in `namespace`, sorted by name.
+    pub fn names(&self, namespace: &str) -> impl Iterator<Item = RegistryEntry> + use<> {
+        let c_namespace = CString::new(namespace).unwrap();
+        self.list_entries(|callback, context| {
+            (self.registry_names_ptr)(self, c_namespace.as_ptr(), callback, context)
+        })
+    }
+
+    /// Every entry whose `namespace/name` matches the [glob](registry::glob) `pattern`, sorted,
+    /// e.g. `libdecima/game/functions/*Player*`.
+    pub fn glob(&self, pattern: &str) -> impl Iterator<Item = RegistryEntry> + use<> {
+        let c_pattern = CString::new(pattern).unwrap();
+        self.list_entries(|callback, context| {
+            (self.registry_glob_ptr)(self, c_pattern.as_ptr(), callback, context)
+        })
+    }
+
+    /// Every entry whose `namespace/name` starts with `prefix`, sorted.
+    pub fn prefix(&self, prefix: &str) -> impl Iterator<Item = RegistryEntry> + use<> {
+        self.glob(&registry::prefix_pattern(prefix))
+    }
+
+    fn list_entries(
+        &self,
+        list: impl FnOnce(EntryCallback, *mut c_void) -> usize,
+    ) -> std::vec::IntoIter<RegistryEntry> {
+        extern "C" fn collect(
+            context: *mut c_void,
+            namespace: *const c_char,
+            name: *const c_char,
+            ptr: *const c_void,
+        ) {
+            let entries = unsafe { &mut *(context as *mut Vec<RegistryEntry>) };
+            entries.push(RegistryEntry {
+                namespace: unsafe { CStr::from_ptr(namespace) }
+                    .to_string_lossy()
+                    .into_owned(),
+                name: unsafe { CStr::from_ptr(name) }
+                    .to_string_lossy()
+                    .into_owned(),
+                ptr,
+            });
+        }
+
+        let mut entries: Vec<RegistryEntry> = Vec::new();
+        if self.supports(6) {
+            list(
+                collect,
+                &mut entries as *mut Vec<RegistryEntry> as *mut c_void,
+            );
+        }
+        entries.into_iter()
+    }
+
+    /// Call `callback` whenever what a name matching the [glob](registry::glob) `pattern` is
+    /// queried as changes, i.e. it's registered, replaced or removed. Use a plain
+    /// `namespace/name` for a single entry, or a [prefix pattern](registry::prefix_pattern) for a
+    /// whole namespace.
+    ///
+    /// Every matching entry there already is is passed to `callback` as
+    /// [Registered](RegistryChange::Registered) before this returns, so it sees the same entries
+    /// whether their mods loaded before or after this one. Returns `None` if the loader is too old.
+    pub fn subscribe(
+        &self,
+        pattern: &str,
+        callback: impl Fn(RegistryChange, &RegistryEntry) + Send + Sync + 'static,
+    ) -> Option<Subscription<'_>> {
+        type Callback = Box<dyn Fn(RegistryChange, &RegistryEntry) + Send + Sync>;
+
+        extern "C" fn dispatch(
+            context: *mut c_void,
+            change: RegistryChange,
+            namespace: *const c_char,
+            name: *const c_char,
+            ptr: *const c_void,
+        ) {
+            let callback = unsafe { &*(context as *const Callback) };
+            let entry = RegistryEntry {
+                namespace: unsafe { CStr::from_ptr(namespace) }
+                    .to_string_lossy()
+                    .into_owned(),
+                name: unsafe { CStr::from_ptr(name) }
+                    .to_string_lossy()
+                    .into_owned(),
+                ptr,
+            };
+            let result = catch_unwind(AssertUnwindSafe(|| callback(change, &entry)));
+            if result.is_err() {
+                ::log::error!(
+                    "A registry subscription panicked on {}/{}.",
+                    entry.namespace,
+                    entry.name
+                );
+            }
+        }
+
+        if !self.supports(7) {
+            return None;
+        }
+
+        // never freed, the loader might still be calling it on another thread when unsubscribing
+        let callback: Box<Callback> = Box::new(Box::new(callback));
+        let callback: &'static Callback = Box::leak(callback);
+        let c_pattern = CString::new(pattern).unwrap();
+        let id = (self.registry_subscribe_ptr)(
+            self,
+            c_pattern.as_ptr(),
+            dispatch,
+            callback as *const Callback as *mut c_void,
+        );
+        if id == 0 {
+            None
+        } else {
+            Some(Subscription { api: self, id })
+        }
+    }
+
+    /// Add a hook on `target` to the loader's hook chain for it, disabled until
+    /// [hook_enable](CauldronApi::hook_enable) is called.
+    ///
+    /// Hooks with a higher `priority` run first, hooks with the same priority run in install
+    /// order. Returns a slot holding the next function in the chain, which `detour` has to read
+    /// every time it calls through, as it changes whenever other mods' hooks are added or removed.
+    /// The loader writes it atomically, read it through
+    /// [AtomicPtr::from_ptr](std::sync::atomic::AtomicPtr::from_ptr). Returns `None` if this mod
+    /// has already hooked `target`, or the loader is too old.
+    ///
+    /// # Safety
+    /// `detour` must have the same signature as `target`.
+    pub unsafe fn hook_install(
+        &self,
+        target: *const c_void,
+        detour: *const c_void,
+        priority: i32,
+    ) -> Option<*const *const c_void> {
+        if !self.supports(2) {
+            return None;
+        }
+
+        let slot = (self.hook_install_ptr)(self, target, detour, priority);
+        if slot.is_null() { None } else { Some(slot) }
+    }
+
+    /// [hook_install](CauldronApi::hook_install) on the function `module` exports as `export`.
+    /// Returns `None` if it has no such export, or it's forwarded to another module.
+    ///
+    /// # Safety
+    /// `detour` must have the same signature as the export.
+    pub unsafe fn hook_install_export(
+        &self,
+        module: &Module,
+        export: &str,
+        detour: *const c_void,
+        priority: i32,
+    ) -> Option<*const *const c_void> {
+        let target = module.export(export)?;
+        unsafe { self.hook_install(target.as_ptr(), detour, priority) }
+    }
+
+    pub fn hook_enable(&self, target: *const c_void) -> bool {
+        self.supports(2) && (self.hook_enable_ptr)(self, target)
+    }
+
+    pub fn hook_disable(&self, target: *const c_void) -> bool {
+        self.supports(2) && (self.hook_disable_ptr)(self, target)
+    }
+
+    /// Remove this mod's hook on `target`, the slot returned by
+    /// [hook_install](CauldronApi::hook_install) must not be used afterwards.
+    pub fn hook_remove(&self, target: *const c_void) -> bool {
+        self.supports(2) && (self.hook_remove_ptr)(self, target)
+    }
+
+    /// Replace `expected` at `address` with `data`, through the loader's patch ledger.
+    ///
+    /// Nothing is written if the bytes at `address` aren't `expected` or another mod has patched
+    /// any of them. Returns `None` on failure, the loader logs why.
+    ///
+    /// # Safety
+    /// `address` must be valid for reads and writes of `data.len()` bytes.
+    pub unsafe fn patch(
+        &self,
+        address: *mut c_void,
+        expected: &[u8],
+        data: &[u8],
+    ) -> Option<PatchHandle<'_>> {
+        if !self.supports(3) || expected.len() != data.len() {
+            return None;
+        }
+
+        let id =
+            (self.patch_apply_ptr)(self, address, expected.as_ptr(), data.as_ptr(), data.len());
+        if id == 0 {
+            None
+        } else {
+            Some(PatchHandle { api: self, id })
+        }
+    }
+
+    /// [patch](CauldronApi::patch) `offset` bytes from the start of `module`. Returns `None` if
+    /// any of the patched bytes are outside of the module.
+    ///
+    /// # Safety
+    /// `data` has to be valid in place of `expected` to whatever runs or reads it.
+    pub unsafe fn patch_module(
+        &self,
+        module: &Module,
+        offset: u32,
+        expected: &[u8],
+        data: &[u8],
+    ) -> Option<PatchHandle<'_>> {
+        let start = module.rva(offset).ok()?;
+        let last = u32::try_from(data.len().checked_sub(1)?).ok()?;
+        start.checked_add(last).ok()?;
+        unsafe { self.patch(start.to_va().as_ptr(), expected, data) }
+    }
+}
+
+/// A patch applied with [CauldronApi::patch], which stays in place unless restored.
+pub struct PatchHandle<'a> {
+    api: &'a CauldronApi,
+    id: u64,
+}
+
+impl PatchHandle<'_> {
+    /// Write back the original bytes, fails if they've been patched over since.
+    pub fn restore(self) -> bool {
+        (self.api.patch_restore_ptr)(self.api, self.id)
+    }
+}
+
+/// A subscription made with [CauldronApi::subscribe], which stays until unsubscribed.
+pub struct Subscription<'a> {
+    api: &'a CauldronApi,
+    id: u64,
+}
+
+impl Subscription<'_> {
+    /// Stop calling the subscription's callback.
+    pub fn unsubscribe(self) -> bool {
+        (self.api.registry_unsubscribe_ptr)(self.api, self.id)
+    }
 }
 
 pub mod prelude {
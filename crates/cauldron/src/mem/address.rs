@@ -0,0 +1,301 @@
+//! Addresses tied to the module they point into.
+//!
+//! A [Va] is an absolute address in the process, an [Rva] is an offset from the start of a module.
+//! Both carry the [Module] they belong to, so converting between them always uses the right base,
+//! and both print as `module+0x…`.
+
+use crate::mem::instruction::{self, MAX_INSTRUCTION_LENGTH, ResolveError};
+use crate::mem::module::Module;
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum AddressError {
+    #[error("{address:#X} is outside of {module}")]
+    OutOfModule {
+        module: &'static str,
+        address: usize,
+    },
+    #[error("{module}+{offset:#X} is outside of {module}")]
+    OffsetOutOfModule { module: &'static str, offset: usize },
+    #[error("{0} and {1} are different modules")]
+    DifferentModules(&'static str, &'static str),
+    #[error("{0} isn't loaded")]
+    ModuleNotFound(String),
+}
+
+fn same_module(a: &Module, b: &Module) -> Result<(), AddressError> {
+    if a == b {
+        Ok(())
+    } else {
+        Err(AddressError::DifferentModules(a.name(), b.name()))
+    }
+}
+
+/// An absolute address inside of a [Module].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Va {
+    module: Module,
+    address: usize,
+}
+
+impl Va {
+    pub fn new(module: Module, address: usize) -> Result<Va, AddressError> {
+        if module.contains(address) {
+            Ok(Va { module, address })
+        } else {
+            Err(AddressError::OutOfModule {
+                module: module.name(),
+                address,
+            })
+        }
+    }
+
+    pub fn module(&self) -> Module {
+        self.module
+    }
+
+    pub fn get(&self) -> usize {
+        self.address
+    }
+
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.address as *mut T
+    }
+
+    pub fn to_rva(self) -> Rva {
+        Rva {
+            module: self.module,
+            offset: (self.address - self.module.base()) as u32,
+        }
+    }
+
+    pub fn checked_add(self, offset: usize) -> Result<Va, AddressError> {
+        let address = self
+            .address
+            .checked_add(offset)
+            .ok_or(AddressError::OutOfModule {
+                module: self.module.name(),
+                address: self.address.wrapping_add(offset),
+            })?;
+        self.module.va(address)
+    }
+
+    pub fn checked_sub(self, offset: usize) -> Result<Va, AddressError> {
+        let address = self
+            .address
+            .checked_sub(offset)
+            .ok_or(AddressError::OutOfModule {
+                module: self.module.name(),
+                address: self.address.wrapping_sub(offset),
+            })?;
+        self.module.va(address)
+    }
+
+    /// The distance from `origin` to this address, both have to be in the same module.
+    pub fn offset_from(self, origin: Va) -> Result<isize, AddressError> {
+        same_module(&self.module, &origin.module)?;
+        Ok(self.address.wrapping_sub(origin.address) as isize)
+    }
+
+    /// Decode the instruction here and follow its rip-relative operand, which has to point into
+    /// the same module.
+    pub fn rip_relative(self) -> Result<Va, ResolveError> {
+        let target = instruction::rip_relative(self.code(), self.address)?;
+        Ok(self.module.va(target)?)
+    }
+
+    /// Decode the instruction here and follow its relative `call`, `jmp` or `jcc`, which has to
+    /// land in the same module.
+    pub fn branch_target(self) -> Result<Va, ResolveError> {
+        let target = instruction::branch_target(self.code(), self.address)?;
+        Ok(self.module.va(target)?)
+    }
+
+    /// The instruction here, read from the module's image so nothing past its end is read.
+    fn code(&self) -> &'static [u8] {
+        let code = &self.module.image()[self.to_rva().get() as usize..];
+        &code[..code.len().min(MAX_INSTRUCTION_LENGTH)]
+    }
+}
+
+impl Display for Va {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.to_rva(), f)
+    }
+}
+
+/// An offset from the start of a [Module].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Rva {
+    module: Module,
+    offset: u32,
+}
+
+impl Rva {
+    pub fn new(module: Module, offset: u32) -> Result<Rva, AddressError> {
+        if offset < module.size() {
+            Ok(Rva { module, offset })
+        } else {
+            Err(AddressError::OffsetOutOfModule {
+                module: module.name(),
+                offset: offset as usize,
+            })
+        }
+    }
+
+    pub fn module(&self) -> Module {
+        self.module
+    }
+
+    pub fn get(&self) -> u32 {
+        self.offset
+    }
+
+    pub fn to_va(self) -> Va {
+        Va {
+            module: self.module,
+            address: self.module.base() + self.offset as usize,
+        }
+    }
+
+    pub fn checked_add(self, offset: u32) -> Result<Rva, AddressError> {
+        let sum = self.offset as usize + offset as usize;
+        let offset = u32::try_from(sum).map_err(|_| AddressError::OffsetOutOfModule {
+            module: self.module.name(),
+            offset: sum,
+        })?;
+        self.module.rva(offset)
+    }
+
+    pub fn checked_sub(self, offset: u32) -> Result<Rva, AddressError> {
+        let offset = self
+            .offset
+            .checked_sub(offset)
+            .ok_or(AddressError::OffsetOutOfModule {
+                module: self.module.name(),
+                offset: (self.offset as usize).wrapping_sub(offset as usize),
+            })?;
+        self.module.rva(offset)
+    }
+
+    /// The distance from `origin` to this offset, both have to be in the same module.
+    pub fn offset_from(self, origin: Rva) -> Result<i64, AddressError> {
+        same_module(&self.module, &origin.module)?;
+        Ok(self.offset as i64 - origin.offset as i64)
+    }
+}
+
+impl Display for Rva {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{:#x}", self.module.name(), self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // never read from
+    const GAME: Module = unsafe { Module::new("game.exe", 0x1_4000_0000, 0x1000) };
+    const OTHER: Module = unsafe { Module::new("other.dll", 0x7FF0_0000_0000, 0x1000) };
+
+    #[test]
+    fn conversions() {
+        let va = GAME.va(0x1_4000_0123).unwrap();
+        let rva = va.to_rva();
+        assert_eq!(rva.get(), 0x123);
+        assert_eq!(rva.module(), GAME);
+        assert_eq!(rva.to_va(), va);
+        assert_eq!(GAME.rva(0x123).unwrap().to_va().get(), 0x1_4000_0123);
+    }
+
+    #[test]
+    fn bounds() {
+        assert!(GAME.va(0x1_4000_0000).is_ok());
+        assert!(GAME.va(0x1_4000_0FFF).is_ok());
+        assert_eq!(
+            GAME.va(0x1_4000_1000),
+            Err(AddressError::OutOfModule {
+                module: "game.exe",
+                address: 0x1_4000_1000
+            })
+        );
+        assert!(GAME.va(0x1_3FFF_FFFF).is_err());
+        assert!(GAME.va(0).is_err());
+        assert!(GAME.rva(0xFFF).is_ok());
+        assert_eq!(
+            GAME.rva(0x1000),
+            Err(AddressError::OffsetOutOfModule {
+                module: "game.exe",
+                offset: 0x1000
+            })
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let va = GAME.va(0x1_4000_0100).unwrap();
+        assert_eq!(va.checked_add(0x10).unwrap().get(), 0x1_4000_0110);
+        assert_eq!(va.checked_sub(0x100).unwrap().get(), 0x1_4000_0000);
+        assert!(va.checked_sub(0x101).is_err());
+        assert!(va.checked_add(0xF00).is_err());
+        assert!(va.checked_add(usize::MAX).is_err());
+
+        let rva = GAME.rva(0x100).unwrap();
+        assert_eq!(rva.checked_add(0x10).unwrap().get(), 0x110);
+        assert_eq!(rva.checked_sub(0x100).unwrap().get(), 0);
+        assert!(rva.checked_sub(0x101).is_err());
+        assert!(rva.checked_add(u32::MAX).is_err());
+
+        let end = GAME.va(0x1_4000_0FF0).unwrap();
+        assert_eq!(end.offset_from(va), Ok(0xEF0));
+        assert_eq!(va.offset_from(end), Ok(-0xEF0));
+        assert_eq!(end.to_rva().offset_from(rva), Ok(0xEF0));
+        assert_eq!(
+            va.offset_from(OTHER.va(0x7FF0_0000_0100).unwrap()),
+            Err(AddressError::DifferentModules("game.exe", "other.dll"))
+        );
+    }
+
+    #[test]
+    fn resolved_from_the_image() {
+        // lea rax, [rip+0x10]; call -0xC, the call's target is past the start of the image
+        let code: &'static [u8] = vec![
+            0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00, 0xE8, 0xF4, 0xFF, 0xFF, 0xFF, 0xE8, 0x00,
+        ]
+        .leak();
+        let module = unsafe { Module::new("code.exe", code.as_ptr() as usize, code.len() as u32) };
+
+        let lea = module.va(module.base()).unwrap();
+        assert_eq!(
+            lea.rip_relative(),
+            Err(ResolveError::Address(AddressError::OutOfModule {
+                module: "code.exe",
+                address: module.base() + 0x17
+            }))
+        );
+        let call = lea.checked_add(7).unwrap();
+        assert_eq!(call.branch_target().unwrap(), lea);
+        assert!(matches!(
+            call.rip_relative(),
+            Err(ResolveError::NotRipRelative { .. })
+        ));
+
+        // cut off by the end of the image
+        assert_eq!(
+            call.checked_add(5).unwrap().branch_target(),
+            Err(ResolveError::InvalidInstruction {
+                address: module.base() + 12
+            })
+        );
+    }
+
+    #[test]
+    fn display() {
+        let va = GAME.va(0x1_4000_0ABC).unwrap();
+        assert_eq!(va.to_string(), "game.exe+0xabc");
+        assert_eq!(va.to_rva().to_string(), "game.exe+0xabc");
+        assert_eq!(OTHER.rva(0).unwrap().to_string(), "other.dll+0x0");
+    }
+}
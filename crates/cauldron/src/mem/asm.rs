@@ -0,0 +1,270 @@
+//! Assembling code at runtime, for code caves and mid-function hooks.
+//!
+//! Built on iced's assembler, re-exported with its registers and memory operands as [code_asm].
+//! Code is always assembled for the address it'll run at, so relative branches and rip-relative
+//! operands reach the right targets, and instructions moved elsewhere are re-encoded to still
+//! reach theirs. Everything here only produces bytes, write them with a
+//! [CodeCave](crate::mem::cave::CodeCave) and [CauldronApi::patch](crate::CauldronApi::patch).
+
+use iced_x86::code_asm::CodeAssembler;
+use iced_x86::{
+    BlockEncoder, BlockEncoderOptions, Decoder, DecoderError, DecoderOptions, IcedError,
+    Instruction, InstructionBlock,
+};
+use thiserror::Error;
+
+pub use iced_x86::code_asm;
+
+/// The length of a `jmp rel32`.
+pub const JMP_LENGTH: usize = 5;
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum AsmError {
+    #[error("{target:#X} is out of rel32 range of {from:#X}")]
+    OutOfRange { from: usize, target: usize },
+    #[error("invalid instruction at {address:#X}")]
+    InvalidInstruction { address: usize },
+    #[error("{len} bytes of whole instructions are needed at {address:#X}, only {available} given")]
+    TooShort {
+        address: usize,
+        len: usize,
+        available: usize,
+    },
+    #[error("failed to encode: {0}")]
+    Encode(String),
+}
+
+impl From<IcedError> for AsmError {
+    fn from(error: IcedError) -> Self {
+        AsmError::Encode(error.to_string())
+    }
+}
+
+/// A new 64-bit assembler.
+pub fn assembler() -> CodeAssembler {
+    CodeAssembler::new(64).unwrap()
+}
+
+/// Assemble everything added to `assembler` to run at `address`.
+pub fn assemble(assembler: &mut CodeAssembler, address: usize) -> Result<Vec<u8>, AsmError> {
+    Ok(assembler.assemble(address as u64)?)
+}
+
+/// A `jmp rel32` at `from` to `target`.
+pub fn jmp(from: usize, target: usize) -> Result<[u8; JMP_LENGTH], AsmError> {
+    let next = from.wrapping_add(JMP_LENGTH);
+    let displacement = i32::try_from(target.wrapping_sub(next) as isize)
+        .map_err(|_| AsmError::OutOfRange { from, target })?;
+
+    let mut jmp = [0xE9, 0, 0, 0, 0];
+    jmp[1..].copy_from_slice(&displacement.to_le_bytes());
+    Ok(jmp)
+}
+
+/// Decode whole instructions from the start of `code`, read from `address`, until they cover at
+/// least `len` bytes.
+pub fn whole_instructions(
+    code: &[u8],
+    address: usize,
+    len: usize,
+) -> Result<Vec<Instruction>, AsmError> {
+    let mut decoder = Decoder::with_ip(64, code, address as u64, DecoderOptions::NONE);
+    let mut instructions = Vec::new();
+    let mut covered = 0;
+    while covered < len {
+        let instruction = decoder.decode();
+        if decoder.last_error() == DecoderError::NoMoreBytes {
+            return Err(AsmError::TooShort {
+                address,
+                len,
+                available: code.len(),
+            });
+        }
+        if instruction.is_invalid() {
+            return Err(AsmError::InvalidInstruction {
+                address: instruction.ip() as usize,
+            });
+        }
+        covered += instruction.len();
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+/// Re-encode `instructions`, decoded where they were read from, to run at `address`.
+///
+/// Relative branches and rip-relative operands are fixed up to reach the same targets, short
+/// branches become near ones if they have to. Fails if a target is out of rel32 range.
+pub fn relocate(instructions: &[Instruction], address: usize) -> Result<Vec<u8>, AsmError> {
+    let block = InstructionBlock::new(instructions, address as u64);
+    Ok(BlockEncoder::encode(64, block, BlockEncoderOptions::NONE)?.code_buffer)
+}
+
+/// The bytes for a mid-function hook, which jumps from `target` to a code cave, runs the hook's
+/// code, then the instructions the jump replaced, and jumps back.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CaveHook {
+    /// Where the hook jumps from.
+    pub target: usize,
+    /// The whole instructions at `target` the jump replaces, what to expect there when patching.
+    pub original: Vec<u8>,
+    /// The jump to the cave, padded with `nop`s to the length of [original](CaveHook::original).
+    pub patch: Vec<u8>,
+    /// Where the cave is.
+    pub cave: usize,
+    /// The code to write to the cave.
+    pub code: Vec<u8>,
+}
+
+impl CaveHook {
+    /// Build a hook at `target` running `hook` in a cave at `cave`. `code` is read from `target`,
+    /// and has to hold enough whole instructions for a [jmp].
+    ///
+    /// The replaced instructions run after `hook`, with the registers and flags it leaves, so it
+    /// has to preserve anything they use.
+    pub fn new(
+        target: usize,
+        code: &[u8],
+        cave: usize,
+        hook: &mut CodeAssembler,
+    ) -> Result<Self, AsmError> {
+        let replaced = whole_instructions(code, target, JMP_LENGTH)?;
+        let replaced_len = replaced.iter().map(Instruction::len).sum();
+
+        let mut cave_code = assemble(hook, cave)?;
+        cave_code.extend(relocate(&replaced, cave + cave_code.len())?);
+        cave_code.extend(jmp(cave + cave_code.len(), target + replaced_len)?);
+
+        let mut patch = jmp(target, cave)?.to_vec();
+        patch.resize(replaced_len, 0x90);
+
+        Ok(CaveHook {
+            target,
+            original: code[..replaced_len].to_vec(),
+            patch,
+            cave,
+            code: cave_code,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::instruction;
+    use code_asm::{ptr, rax, rcx};
+    use iced_x86::Mnemonic;
+
+    const TARGET: usize = 0x1_4000_1000;
+    const CAVE: usize = 0x1_3FFF_0000;
+
+    fn mnemonics(code: &[u8], address: usize) -> Vec<Mnemonic> {
+        let decoder = Decoder::with_ip(64, code, address as u64, DecoderOptions::NONE);
+        decoder.into_iter().map(|i| i.mnemonic()).collect()
+    }
+
+    #[test]
+    fn jmp_rel32() {
+        assert_eq!(jmp(0x1000, 0x1005).unwrap(), [0xE9, 0, 0, 0, 0]);
+        assert_eq!(jmp(0x1000, 0x1000).unwrap(), [0xE9, 0xFB, 0xFF, 0xFF, 0xFF]);
+        let jmp_bytes = jmp(TARGET, CAVE).unwrap();
+        assert_eq!(instruction::branch_target(&jmp_bytes, TARGET), Ok(CAVE));
+
+        assert_eq!(
+            jmp(0x1000, 0x1_0000_1000),
+            Err(AsmError::OutOfRange {
+                from: 0x1000,
+                target: 0x1_0000_1000
+            })
+        );
+    }
+
+    #[test]
+    fn assemble_at_address() {
+        let mut a = assembler();
+        a.call(0x1_4000_2000u64).unwrap();
+        let mut label = a.create_label();
+        a.lea(rax, ptr(label)).unwrap();
+        a.set_label(&mut label).unwrap();
+        a.ret().unwrap();
+
+        let code = assemble(&mut a, TARGET).unwrap();
+        assert_eq!(instruction::branch_target(&code, TARGET), Ok(0x1_4000_2000));
+        let ret = TARGET + code.len() - 1;
+        assert_eq!(instruction::rip_relative(&code[5..], TARGET + 5), Ok(ret));
+    }
+
+    #[test]
+    fn whole() {
+        // push rbx; sub rsp, 0x20; mov rbx, rcx
+        let code = [0x40, 0x53, 0x48, 0x83, 0xEC, 0x20, 0x48, 0x8B, 0xD9];
+        let instructions = whole_instructions(&code, TARGET, JMP_LENGTH).unwrap();
+        assert_eq!(instructions.iter().map(Instruction::len).sum::<usize>(), 6);
+
+        assert_eq!(
+            whole_instructions(&code[..4], TARGET, JMP_LENGTH),
+            Err(AsmError::TooShort {
+                address: TARGET,
+                len: JMP_LENGTH,
+                available: 4
+            })
+        );
+    }
+
+    #[test]
+    fn relocate_relative_operands() {
+        // lea rax, [rip+0x100]; jmp short +0x10
+        let code = [0x48, 0x8D, 0x05, 0x00, 0x01, 0x00, 0x00, 0xEB, 0x10];
+        let instructions = whole_instructions(&code, TARGET, code.len()).unwrap();
+
+        let moved = relocate(&instructions, CAVE).unwrap();
+        assert_eq!(
+            instruction::rip_relative(&moved, CAVE),
+            Ok(TARGET + 7 + 0x100)
+        );
+        let jmp_at = CAVE + 7;
+        assert_eq!(
+            instruction::branch_target(&moved[7..], jmp_at),
+            Ok(TARGET + 9 + 0x10)
+        );
+
+        let far = relocate(&instructions, 0x7FF0_0000_0000);
+        assert!(matches!(far, Err(AsmError::Encode(_))));
+    }
+
+    #[test]
+    fn cave_hook() {
+        // mov [rsp+8], rbx; push rdi; sub rsp, 0x20
+        let code = [0x48, 0x89, 0x5C, 0x24, 0x08, 0x57, 0x48, 0x83, 0xEC, 0x20];
+        let mut hook = assembler();
+        hook.inc(rcx).unwrap();
+
+        let built = CaveHook::new(TARGET, &code, CAVE, &mut hook).unwrap();
+        assert_eq!(built.original, &code[..5]);
+        assert_eq!(built.patch.len(), 5);
+        assert_eq!(instruction::branch_target(&built.patch, TARGET), Ok(CAVE));
+
+        assert_eq!(
+            mnemonics(&built.code, CAVE),
+            [Mnemonic::Inc, Mnemonic::Mov, Mnemonic::Jmp]
+        );
+        let back = CAVE + built.code.len() - JMP_LENGTH;
+        assert_eq!(
+            instruction::branch_target(&built.code[built.code.len() - JMP_LENGTH..], back),
+            Ok(TARGET + 5)
+        );
+    }
+
+    #[test]
+    fn cave_hook_pads_with_nops() {
+        // push rbx; sub rsp, 0x20
+        let code = [0x40, 0x53, 0x48, 0x83, 0xEC, 0x20];
+        let built = CaveHook::new(TARGET, &code, CAVE, &mut assembler()).unwrap();
+        assert_eq!(built.original, code);
+        assert_eq!(built.patch[5], 0x90);
+        assert_eq!(
+            mnemonics(&built.code, CAVE),
+            [Mnemonic::Push, Mnemonic::Sub, Mnemonic::Jmp]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Executable memory allocated close to a module, so code in it reaches the module with rel32
+//! jumps and rip-relative operands, and the other way around.
+
+use crate::mem::module::Module;
+use crate::mem::patch::{Memory, PatchError, ProcessMemory};
+use crate::mem::platform;
+use std::ops::Range;
+use thiserror::Error;
+
+/// How far a rel32 reaches, with some slack for the instruction's own length.
+const REL32_REACH: usize = i32::MAX as usize - 0x1000;
+
+/// Where allocations can start, always 64KiB on Windows.
+const ALLOCATION_GRANULARITY: usize = 0x1_0000;
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum CaveError {
+    #[error("cave sizes can't be 0")]
+    Empty,
+    #[error("no free memory for {size} bytes within rel32 range of {near:#X}..{near_end:#X}")]
+    NoSpace {
+        near: usize,
+        near_end: usize,
+        size: usize,
+    },
+}
+
+/// Executable memory, freed when dropped.
+pub struct CodeCave {
+    address: usize,
+    size: usize,
+}
+
+impl CodeCave {
+    /// Allocate `size` bytes reachable from anywhere in `module`.
+    pub fn allocate_near(module: &Module, size: usize) -> Result<Self, CaveError> {
+        Self::allocate_within_reach(module.base()..module.base() + module.size() as usize, size)
+    }
+
+    /// Allocate `size` bytes reachable from `address`.
+    pub fn allocate_near_address(address: usize, size: usize) -> Result<Self, CaveError> {
+        Self::allocate_within_reach(address..address + 1, size)
+    }
+
+    fn allocate_within_reach(near: Range<usize>, size: usize) -> Result<Self, CaveError> {
+        if size == 0 {
+            return Err(CaveError::Empty);
+        }
+
+        candidates(&near, size, ALLOCATION_GRANULARITY)
+            .find_map(|address| platform::allocate(address, size))
+            .map(|address| CodeCave { address, size })
+            .ok_or(CaveError::NoSpace {
+                near: near.start,
+                near_end: near.end,
+                size,
+            })
+    }
+
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Write `data` `offset` bytes into the cave, e.g. code from
+    /// [asm::assemble](crate::mem::asm::assemble) assembled for `address() + offset`.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), PatchError> {
+        match offset.checked_add(data.len()) {
+            // the cave is ours, and nothing runs in it until it's been written
+            Some(end) if end <= self.size => {
+                unsafe { ProcessMemory::new() }.write(self.address + offset, data)
+            }
+            _ => Err(PatchError::OutOfBounds {
+                address: self.address.wrapping_add(offset),
+                end: self.address.wrapping_add(offset).wrapping_add(data.len()),
+            }),
+        }
+    }
+
+    /// Keep the cave allocated for good, e.g. when code in it stays hooked in.
+    pub fn leak(self) -> usize {
+        std::mem::ManuallyDrop::new(self).address
+    }
+}
+
+impl Drop for CodeCave {
+    fn drop(&mut self) {
+        if let Err(e) = platform::free(self.address, self.size) {
+            log::error!("Failed to free the code cave at {:#X}: {e}", self.address);
+        }
+    }
+}
+
+/// Where a cave of `size` bytes could start, nearest to `near` first, so every byte of it is in
+/// rel32 range of every byte of `near`.
+fn candidates(near: &Range<usize>, size: usize, granularity: usize) -> impl Iterator<Item = usize> {
+    let lowest = near.end.saturating_sub(REL32_REACH);
+    let highest = near.start.saturating_add(REL32_REACH).saturating_sub(size);
+
+    // below `near`, going down
+    let below_start = near.start.saturating_sub(size) / granularity * granularity;
+    let below = (0..)
+        .map(move |i| below_start.checked_sub(i * granularity))
+        .take_while(move |address| address.is_some_and(|a| a >= lowest && a > 0))
+        .flatten();
+
+    // above `near`, going up
+    let above_start = near.end.div_ceil(granularity) * granularity;
+    let above = (0..)
+        .map(move |i| above_start + i * granularity)
+        .take_while(move |address| *address <= highest);
+
+    interleave(below, above)
+}
+
+/// Alternate between `a` and `b`, until both run out.
+fn interleave<T>(
+    a: impl Iterator<Item = T>,
+    b: impl Iterator<Item = T>,
+) -> impl Iterator<Item = T> {
+    let mut a = a.fuse();
+    let mut b = b.fuse();
+    let mut take_a = false;
+    std::iter::from_fn(move || {
+        take_a = !take_a;
+        if take_a {
+            a.next().or_else(|| b.next())
+        } else {
+            b.next().or_else(|| a.next())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: Range<usize> = 0x1_4000_0000..0x1_4800_0000;
+
+    fn in_reach(address: usize, size: usize) -> bool {
+        let reaches = |a: usize, b: usize| a.abs_diff(b) <= i32::MAX as usize;
+        reaches(address, MODULE.start)
+            && reaches(address, MODULE.end)
+            && reaches(address + size, MODULE.start)
+            && reaches(address + size, MODULE.end)
+    }
+
+    #[test]
+    fn nearest_first() {
+        let first: Vec<usize> = candidates(&MODULE, 0x1000, 0x1_0000).take(4).collect();
+        assert_eq!(
+            first,
+            [0x1_3FFF_0000, 0x1_4800_0000, 0x1_3FFE_0000, 0x1_4801_0000]
+        );
+    }
+
+    #[test]
+    fn every_candidate_is_in_reach() {
+        let all: Vec<usize> = candidates(&MODULE, 0x1000, 0x1_0000).collect();
+        assert!(all.iter().all(|address| in_reach(*address, 0x1000)));
+        assert!(all.iter().all(|address| address % 0x1_0000 == 0));
+        assert!(all.iter().all(|address| !MODULE.contains(address)));
+
+        // the whole range either side, minus the module and the slack
+        let (below, above): (Vec<usize>, Vec<usize>) =
+            all.iter().partition(|address| **address < MODULE.start);
+        assert_eq!(
+            *below.iter().min().unwrap(),
+            0xC800_1000_usize.div_ceil(0x1_0000) * 0x1_0000
+        );
+        assert!(above.iter().max().unwrap() + 0x1000 <= MODULE.start + REL32_REACH);
+    }
+
+    #[test]
+    fn low_addresses() {
+        // nothing below the null page
+        let near = 0x10_0000..0x10_1000;
+        let below: Vec<usize> = candidates(&near, 0x1000, 0x1_0000)
+            .filter(|address| *address < near.start)
+            .collect();
+        assert_eq!(below.len(), 15);
+        assert!(!below.contains(&0));
+    }
+
+    #[test]
+    fn interleaved() {
+        let mixed: Vec<u32> = interleave([1, 3].into_iter(), [2, 4, 6, 8].into_iter()).collect();
+        assert_eq!(mixed, [1, 2, 3, 4, 6, 8]);
+    }
+
+    #[test]
+    fn allocate_in_reach() {
+        let near = allocate_in_reach as fn() as usize;
+        let mut cave = CodeCave::allocate_near_address(near, 0x100).unwrap();
+        assert!(cave.address().abs_diff(near) <= REL32_REACH);
+        assert_eq!(cave.len(), 0x100);
+
+        cave.write(0xFF, &[0xC3]).unwrap();
+        assert_eq!(unsafe { *((cave.address() + 0xFF) as *const u8) }, 0xC3);
+        assert!(matches!(
+            cave.write(0xFF, &[0xC3, 0xC3]),
+            Err(PatchError::OutOfBounds { .. })
+        ));
+        assert_eq!(
+            CodeCave::allocate_near_address(near, 0).err(),
+            Some(CaveError::Empty)
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Where signatures are scanned for, the live game or a copy of its executable on disk.
+//!
+//! A [FileImage] is mapped the same way the game's module is, so signatures can be checked
+//! offline, e.g. on Linux, against the executable named by [GAME_EXECUTABLE_VAR].
+
+use crate::mem::module::Module;
+use crate::mem::offset::PatternSearchError;
+use crate::mem::pattern::Signature;
+use crate::mem::pe::{self, PeError};
+use crate::mem::scan::{Pattern, SignatureMatches, SignatureSet};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use thiserror::Error;
+
+/// The env var offline signature tests read the path of the game's executable from, they're
+/// skipped if it isn't set.
+pub const GAME_EXECUTABLE_VAR: &str = "CAULDRON_GAME_EXECUTABLE";
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("couldn't read the image: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid image: {0}")]
+    Pe(#[from] PeError),
+}
+
+/// An image laid out the way it's mapped, to scan for signatures in.
+pub trait ImageSource {
+    /// The whole image.
+    fn image(&self) -> &[u8];
+
+    /// Where the image starts, its preferred base if it isn't loaded.
+    fn base(&self) -> usize;
+
+    /// The address of the first match of `pattern`.
+    fn find<S: Signature + ?Sized>(&self, pattern: &S) -> Result<usize, PatternSearchError>
+    where
+        Self: Sized,
+    {
+        Pattern::from_signature(pattern)?
+            .find(self.image())
+            .map(|offset| self.base() + offset)
+            .ok_or(PatternSearchError::NotFound)
+    }
+
+    /// Every match of every signature in `signatures`, as offsets from the image base.
+    fn scan(&self, signatures: &SignatureSet) -> SignatureMatches {
+        signatures.scan(self.image())
+    }
+
+    /// Check every signature in `signatures` is found exactly once.
+    fn check(&self, signatures: &SignatureSet) -> Result<(), SignatureReport> {
+        let matches = self.scan(signatures);
+        let report = SignatureReport {
+            missing: matches.missing().map(String::from).collect(),
+            ambiguous: matches
+                .ambiguous()
+                .map(|(name, matches)| {
+                    let addresses = matches.iter().map(|offset| self.base() + offset);
+                    (name.to_owned(), addresses.collect())
+                })
+                .collect(),
+        };
+
+        if report.missing.is_empty() && report.ambiguous.is_empty() {
+            Ok(())
+        } else {
+            Err(report)
+        }
+    }
+}
+
+impl ImageSource for Module {
+    fn image(&self) -> &[u8] {
+        Module::image(self)
+    }
+
+    fn base(&self) -> usize {
+        Module::base(self)
+    }
+}
+
+/// A PE file, mapped to its virtual layout but not loaded.
+#[derive(Debug, Clone)]
+pub struct FileImage {
+    image: Vec<u8>,
+    base: usize,
+}
+
+impl FileImage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ImageError> {
+        Ok(Self::from_file(&std::fs::read(path)?)?)
+    }
+
+    /// Map the contents of a PE file.
+    pub fn from_file(file: &[u8]) -> Result<Self, PeError> {
+        Ok(FileImage {
+            image: pe::map(file)?,
+            base: pe::image_base(file)? as usize,
+        })
+    }
+
+    /// The executable named by [GAME_EXECUTABLE_VAR], `None` if it isn't set.
+    pub fn from_env() -> Option<Result<Self, ImageError>> {
+        let path = std::env::var_os(GAME_EXECUTABLE_VAR)?;
+        Some(Self::open(path))
+    }
+}
+
+impl ImageSource for FileImage {
+    fn image(&self) -> &[u8] {
+        &self.image
+    }
+
+    fn base(&self) -> usize {
+        self.base
+    }
+}
+
+/// The signatures [ImageSource::check] didn't find exactly once.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SignatureReport {
+    pub missing: Vec<String>,
+    /// `(name, addresses)` of the signatures found more than once.
+    pub ambiguous: Vec<(String, Vec<usize>)>,
+}
+
+impl Display for SignatureReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for name in &self.missing {
+            writeln!(f, "{name}: not found")?;
+        }
+        for (name, addresses) in &self.ambiguous {
+            write!(f, "{name}: found {} times, at", addresses.len())?;
+            for address in addresses {
+                write!(f, " {address:#X}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::pe::test_image;
+
+    fn file_image() -> FileImage {
+        FileImage {
+            image: test_image(&[(".text", &[0x48, 0x8B, 0x05, 0xCC, 0xE8, 0xCC, 0xE8])]),
+            base: 0x1_4000_0000,
+        }
+    }
+
+    #[test]
+    fn find() {
+        let image = file_image();
+        assert_eq!(image.find("48 8B 05").unwrap(), 0x1_4000_0100);
+        assert_eq!(image.find("CC E8").unwrap(), 0x1_4000_0103);
+        assert!(matches!(
+            image.find("48 8B 06"),
+            Err(PatternSearchError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn check() {
+        let image = file_image();
+
+        let mut signatures = SignatureSet::new();
+        signatures.add("unique", "48 8B").unwrap();
+        assert_eq!(image.check(&signatures), Ok(()));
+
+        signatures.add("twice", "CC E8").unwrap();
+        signatures.add("missing", "90 90").unwrap();
+        let report = image.check(&signatures).unwrap_err();
+        assert_eq!(
+            report,
+            SignatureReport {
+                missing: vec![String::from("missing")],
+                ambiguous: vec![(String::from("twice"), vec![0x1_4000_0103, 0x1_4000_0105])],
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "missing: not found\ntwice: found 2 times, at 0x140000103 0x140000105\n"
+        );
+    }
+
+    #[test]
+    fn invalid_file() {
+        assert!(matches!(
+            FileImage::from_file(b"MZ"),
+            Err(PeError::Truncated)
+        ));
+    }
+}
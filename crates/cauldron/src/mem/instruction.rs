@@ -0,0 +1,263 @@
+//! Resolving addresses from instructions, instead of counting instruction lengths by hand.
+//!
+//! Everything here works on a byte buffer and the address it was read from. The
+//! [Va](crate::mem::address::Va) helpers read the buffer from their module's image, the
+//! [Offset](crate::mem::offset::Offset) ones from the game's memory.
+
+use crate::mem::address::AddressError;
+use crate::mem::patch::{Memory, PatchError};
+use iced_x86::{Decoder, DecoderOptions, Instruction, OpKind, Register};
+use thiserror::Error;
+
+/// The longest an x86-64 instruction can be.
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum ResolveError {
+    #[error("invalid instruction at {address:#X}")]
+    InvalidInstruction { address: usize },
+    #[error("{instruction} at {address:#X} has no rip-relative operand")]
+    NotRipRelative { address: usize, instruction: String },
+    #[error("{instruction} at {address:#X} isn't a relative call or jump")]
+    NotBranch { address: usize, instruction: String },
+    #[error("{instruction} at {address:#X} has no memory operand")]
+    NoMemoryOperand { address: usize, instruction: String },
+    #[error("null pointer at {address:#X}, level {level} of the chain")]
+    NullPointer { address: usize, level: usize },
+    #[error(transparent)]
+    Memory(#[from] PatchError),
+    #[error(transparent)]
+    Address(#[from] AddressError),
+}
+
+/// Decode the instruction at the start of `bytes`, which were read from `address`.
+pub fn decode(bytes: &[u8], address: usize) -> Result<Instruction, ResolveError> {
+    let mut decoder = Decoder::with_ip(64, bytes, address as u64, DecoderOptions::NONE);
+    let instruction = decoder.decode();
+    if instruction.is_invalid() {
+        return Err(ResolveError::InvalidInstruction { address });
+    }
+    Ok(instruction)
+}
+
+fn describe(instruction: &Instruction) -> String {
+    format!("{:?}", instruction.mnemonic()).to_lowercase()
+}
+
+/// The address a rip-relative operand refers to, e.g. `[rip+0x1234]` in `lea rax, [rip+0x1234]`.
+pub fn rip_relative(bytes: &[u8], address: usize) -> Result<usize, ResolveError> {
+    let instruction = decode(bytes, address)?;
+    if instruction.is_ip_rel_memory_operand() {
+        Ok(instruction.ip_rel_memory_address() as usize)
+    } else {
+        Err(ResolveError::NotRipRelative {
+            address,
+            instruction: describe(&instruction),
+        })
+    }
+}
+
+/// The target of a relative `call`, `jmp` or `jcc`.
+pub fn branch_target(bytes: &[u8], address: usize) -> Result<usize, ResolveError> {
+    let instruction = decode(bytes, address)?;
+    match instruction.op0_kind() {
+        OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => {
+            Ok(instruction.near_branch_target() as usize)
+        }
+        _ => Err(ResolveError::NotBranch {
+            address,
+            instruction: describe(&instruction),
+        }),
+    }
+}
+
+/// The target of a relative branch, or the address of a rip-relative operand.
+pub fn resolve(bytes: &[u8], address: usize) -> Result<usize, ResolveError> {
+    branch_target(bytes, address).or_else(|_| rip_relative(bytes, address))
+}
+
+/// The displacement of a memory operand that isn't rip-relative, e.g. `0x1A8` in
+/// `mov rax, [rcx+0x1A8]`, usually a field offset.
+pub fn displacement(bytes: &[u8], address: usize) -> Result<i64, ResolveError> {
+    let instruction = decode(bytes, address)?;
+    let has_memory_operand =
+        (0..instruction.op_count()).any(|i| instruction.op_kind(i) == OpKind::Memory);
+    if !has_memory_operand || instruction.memory_base() == Register::RIP {
+        return Err(ResolveError::NoMemoryOperand {
+            address,
+            instruction: describe(&instruction),
+        });
+    }
+
+    Ok(instruction.memory_displacement64() as i64)
+}
+
+/// Follow a pointer chain like `module+0x1234 → +0x10 → +0x8`.
+///
+/// Reads the pointer at `start` and adds the first offset, then reads the pointer there and adds
+/// the next offset, and so on. The final address isn't dereferenced.
+pub fn pointer_chain<M: Memory + ?Sized>(
+    memory: &M,
+    start: usize,
+    offsets: &[usize],
+) -> Result<usize, ResolveError> {
+    let mut address = start;
+    for (level, offset) in offsets.iter().enumerate() {
+        let mut pointer = [0u8; size_of::<usize>()];
+        memory.read(address, &mut pointer)?;
+        let pointer = usize::from_le_bytes(pointer);
+        if pointer == 0 {
+            return Err(ResolveError::NullPointer { address, level });
+        }
+        address = pointer.wrapping_add(*offset);
+    }
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: usize = 0x1_4000_1000;
+
+    #[test]
+    fn lengths() {
+        for (bytes, length) in [
+            (&[0x48, 0x8B, 0x0D, 0x10, 0x00, 0x00, 0x00][..], 7), // mov rcx, [rip+0x10]
+            (&[0xE8, 0x00, 0x01, 0x00, 0x00], 5),                 // call rel32
+            (&[0xEB, 0xFE], 2),                                   // jmp rel8
+            (&[0x48, 0x89, 0x5C, 0x24, 0x08], 5),                 // mov [rsp+8], rbx
+            (&[0xC3], 1),                                         // ret
+        ] {
+            assert_eq!(
+                decode(bytes, ADDRESS).unwrap().len(),
+                length,
+                "{bytes:02X?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rip_relative_operands() {
+        // lea rax, [rip+0x10]
+        let lea = [0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00];
+        assert_eq!(rip_relative(&lea, ADDRESS), Ok(ADDRESS + 7 + 0x10));
+
+        // movsxd rax, dword [rip-0x20], the displacement is negative
+        let movsxd = [0x48, 0x63, 0x05, 0xE0, 0xFF, 0xFF, 0xFF];
+        assert_eq!(rip_relative(&movsxd, ADDRESS), Ok(ADDRESS + 7 - 0x20));
+
+        // cmp byte [rip+0x100], 0, the displacement isn't the last thing in the instruction
+        let cmp = [0x80, 0x3D, 0x00, 0x01, 0x00, 0x00, 0x00];
+        assert_eq!(rip_relative(&cmp, ADDRESS), Ok(ADDRESS + 7 + 0x100));
+
+        assert_eq!(
+            rip_relative(&[0x48, 0x89, 0x5C, 0x24, 0x08], ADDRESS),
+            Err(ResolveError::NotRipRelative {
+                address: ADDRESS,
+                instruction: String::from("mov"),
+            })
+        );
+    }
+
+    #[test]
+    fn branches() {
+        // call rel32 +0x100
+        assert_eq!(
+            branch_target(&[0xE8, 0x00, 0x01, 0x00, 0x00], ADDRESS),
+            Ok(ADDRESS + 5 + 0x100)
+        );
+        // jmp rel32 -0x10
+        assert_eq!(
+            branch_target(&[0xE9, 0xF0, 0xFF, 0xFF, 0xFF], ADDRESS),
+            Ok(ADDRESS + 5 - 0x10)
+        );
+        // jmp rel8 to itself
+        assert_eq!(branch_target(&[0xEB, 0xFE], ADDRESS), Ok(ADDRESS));
+        // jz rel32
+        assert_eq!(
+            branch_target(&[0x0F, 0x84, 0x20, 0x00, 0x00, 0x00], ADDRESS),
+            Ok(ADDRESS + 6 + 0x20)
+        );
+        // call [rip+0x10] is indirect
+        assert!(matches!(
+            branch_target(&[0xFF, 0x15, 0x10, 0x00, 0x00, 0x00], ADDRESS),
+            Err(ResolveError::NotBranch { .. })
+        ));
+    }
+
+    #[test]
+    fn resolve_either() {
+        assert_eq!(
+            resolve(&[0xE8, 0x00, 0x01, 0x00, 0x00], ADDRESS),
+            Ok(ADDRESS + 5 + 0x100)
+        );
+        assert_eq!(
+            resolve(&[0xFF, 0x15, 0x10, 0x00, 0x00, 0x00], ADDRESS),
+            Ok(ADDRESS + 6 + 0x10)
+        );
+        assert!(resolve(&[0xC3], ADDRESS).is_err());
+    }
+
+    #[test]
+    fn displacements() {
+        // mov rax, [rcx+0x1A8]
+        assert_eq!(
+            displacement(&[0x48, 0x8B, 0x81, 0xA8, 0x01, 0x00, 0x00], ADDRESS),
+            Ok(0x1A8)
+        );
+        // lea rdx, [rsp+0x20]
+        assert_eq!(
+            displacement(&[0x48, 0x8D, 0x54, 0x24, 0x20], ADDRESS),
+            Ok(0x20)
+        );
+        // mov eax, [rbx-8]
+        assert_eq!(displacement(&[0x8B, 0x43, 0xF8], ADDRESS), Ok(-8));
+        assert!(matches!(
+            displacement(&[0x48, 0x8B, 0x0D, 0x10, 0x00, 0x00, 0x00], ADDRESS),
+            Err(ResolveError::NoMemoryOperand { .. })
+        ));
+        assert!(matches!(
+            displacement(&[0x48, 0x89, 0xC8], ADDRESS),
+            Err(ResolveError::NoMemoryOperand { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            decode(&[0x0F, 0xFF], ADDRESS),
+            Err(ResolveError::InvalidInstruction { address: ADDRESS })
+        );
+        assert_eq!(
+            decode(&[0x48, 0x8B], ADDRESS),
+            Err(ResolveError::InvalidInstruction { address: ADDRESS })
+        );
+    }
+
+    #[test]
+    fn chains() {
+        // the buffer is its own address space, pointers are indexes into it
+        let mut memory = vec![0u8; 0x100];
+        memory[0x10..0x18].copy_from_slice(&0x40usize.to_le_bytes());
+        memory[0x50..0x58].copy_from_slice(&0x80usize.to_le_bytes());
+
+        assert_eq!(pointer_chain(memory.as_slice(), 0x10, &[]), Ok(0x10));
+        assert_eq!(pointer_chain(memory.as_slice(), 0x10, &[0x10]), Ok(0x50));
+        assert_eq!(
+            pointer_chain(memory.as_slice(), 0x10, &[0x10, 0x8]),
+            Ok(0x88)
+        );
+        assert_eq!(
+            pointer_chain(memory.as_slice(), 0x10, &[0x10, 0x8, 0x0]),
+            Err(ResolveError::NullPointer {
+                address: 0x88,
+                level: 2
+            })
+        );
+        assert!(matches!(
+            pointer_chain(memory.as_slice(), 0xFC, &[0]),
+            Err(ResolveError::Memory(PatchError::OutOfBounds { .. }))
+        ));
+    }
+}
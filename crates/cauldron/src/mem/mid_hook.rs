@@ -0,0 +1,392 @@
+//! Hooks in the middle of a function, which run a callback with the registers at that point.
+//!
+//! A hook replaces whole instructions at an instruction boundary with a jump to a code cave. The
+//! cave saves every general purpose register, the flags and `xmm0`-`xmm15` as a [Context], calls
+//! the callback with it, loads the registers back with the callback's changes, runs the replaced
+//! instructions relocated into the cave, and jumps back.
+//!
+//! The jump is written either straight to memory, where the loader's patch ledger can't see it,
+//! or [through the ledger](MidHook::install_through), which refuses jumps over other mods' patches.
+
+use crate::mem::asm::{self, AsmError, CaveHook, JMP_LENGTH, code_asm::*};
+use crate::mem::cave::{CaveError, CodeCave};
+use crate::mem::instruction::MAX_INSTRUCTION_LENGTH;
+use crate::mem::patch::{Memory, PatchError, ProcessMemory};
+use crate::{CauldronApi, PatchHandle};
+use iced_x86::IcedError;
+use std::ffi::c_void;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use thiserror::Error;
+
+/// How much code a hook reads at its target, enough for any whole instructions covering a jump.
+const READ_LENGTH: usize = JMP_LENGTH - 1 + MAX_INSTRUCTION_LENGTH;
+
+/// The size of a hook's cave, the saving and loading of the registers takes most of it.
+const CAVE_SIZE: usize = 0x1000;
+
+const GENERAL_PURPOSE: [AsmRegister64; 15] = [
+    rax, rcx, rdx, rbx, rbp, rsi, rdi, r8, r9, r10, r11, r12, r13, r14, r15,
+];
+
+const XMM: [AsmRegisterXmm; 16] = [
+    xmm0, xmm1, xmm2, xmm3, xmm4, xmm5, xmm6, xmm7, xmm8, xmm9, xmm10, xmm11, xmm12, xmm13, xmm14,
+    xmm15,
+];
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum MidHookError {
+    #[error(transparent)]
+    Asm(#[from] AsmError),
+    #[error(transparent)]
+    Cave(#[from] CaveError),
+    #[error(transparent)]
+    Patch(#[from] PatchError),
+    #[error("the loader refused to patch {0:#X}, see its log")]
+    Refused(usize),
+}
+
+/// An `xmm` register.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Xmm(pub [u64; 2]);
+
+impl Xmm {
+    /// The low `f32`, the whole value of a scalar float.
+    pub fn f32(&self) -> f32 {
+        f32::from_bits(self.0[0] as u32)
+    }
+
+    /// The low `f64`, the whole value of a scalar double.
+    pub fn f64(&self) -> f64 {
+        f64::from_bits(self.0[0])
+    }
+
+    /// Replace the low `f32`, keeping the rest.
+    pub fn set_f32(&mut self, value: f32) {
+        self.0[0] = self.0[0] & !0xFFFF_FFFF | value.to_bits() as u64;
+    }
+
+    /// Replace the low `f64`, keeping the rest.
+    pub fn set_f64(&mut self, value: f64) {
+        self.0[0] = value.to_bits();
+    }
+}
+
+/// The registers where a hook was hit, as the cave saves them on the stack.
+///
+/// Changes are loaded back before the replaced instructions run, except for `rsp`, which is only
+/// there to find the stack with.
+#[repr(C)]
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Context {
+    pub xmm: [Xmm; 16],
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rax: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+}
+
+type Callback = Box<dyn Fn(&mut Context) + Send + Sync>;
+
+/// What the cave calls, with `callback` pointing to the hook's [Callback].
+extern "win64" fn dispatch(context: *mut Context, callback: *const Callback) {
+    let result = catch_unwind(AssertUnwindSafe(|| unsafe { (*callback)(&mut *context) }));
+    if result.is_err() {
+        log::error!("A mid-function hook panicked, its changes to the registers are kept.");
+    }
+}
+
+/// Save the registers as a [Context], call [dispatch] with it and `callback`, load them back.
+fn call_with_context(a: &mut CodeAssembler, callback: usize) -> Result<(), IcedError> {
+    a.push(rsp)?;
+    a.pushfq()?;
+    for register in GENERAL_PURPOSE {
+        a.push(register)?;
+    }
+    a.sub(rsp, (XMM.len() * 16) as i32)?;
+    for (i, register) in XMM.into_iter().enumerate() {
+        a.movdqu(xmmword_ptr(rsp + i * 16), register)?;
+    }
+
+    // rbx keeps the context across the call, aligned with shadow space for the win64 abi
+    a.mov(rcx, rsp)?;
+    a.mov(rdx, callback as u64)?;
+    a.mov(rbx, rsp)?;
+    a.and(rsp, -16)?;
+    a.sub(rsp, 0x20)?;
+    a.mov(rax, dispatch as *const () as u64)?;
+    a.call(rax)?;
+    a.mov(rsp, rbx)?;
+
+    for (i, register) in XMM.into_iter().enumerate() {
+        a.movdqu(register, xmmword_ptr(rsp + i * 16))?;
+    }
+    a.add(rsp, (XMM.len() * 16) as i32)?;
+    for register in GENERAL_PURPOSE.into_iter().rev() {
+        a.pop(register)?;
+    }
+    a.popfq()?;
+    // skip the saved rsp without touching the flags
+    a.lea(rsp, ptr(rsp + 8))?;
+    Ok(())
+}
+
+/// Build a hook at `target` calling `callback`, a pointer to a [Callback], from a cave at `cave`.
+/// `code` is read from `target`.
+fn build(target: usize, code: &[u8], cave: usize, callback: usize) -> Result<CaveHook, AsmError> {
+    let mut hook = asm::assembler();
+    call_with_context(&mut hook, callback)?;
+    CaveHook::new(target, code, cave, &mut hook)
+}
+
+/// How a hook's jump was written.
+enum Jump {
+    /// Straight to memory, only removed if it's still there.
+    Direct,
+    /// Through the loader's patch ledger.
+    Ledger(PatchHandle<'static>),
+}
+
+/// A mid-function hook, removed when dropped.
+///
+/// Its cave and callback are never freed, as another thread may still be running them after the
+/// hook is removed.
+#[must_use = "the hook is removed as soon as it's dropped"]
+pub struct MidHook {
+    hook: CaveHook,
+    /// `None` once the hook's been removed or leaked.
+    jump: Option<Jump>,
+}
+
+impl MidHook {
+    /// Hook the instruction at `target`, calling `callback` with the registers every time it's
+    /// about to run.
+    ///
+    /// The jump is written straight to memory, so the loader's patch ledger doesn't know about it,
+    /// see [install_through](MidHook::install_through).
+    ///
+    /// # Safety
+    /// `target` must be the start of an instruction, and the instructions covered by the jump
+    /// mustn't be branched into or be running on another thread while the hook is installed.
+    pub unsafe fn install(
+        target: usize,
+        callback: impl Fn(&mut Context) + Send + Sync + 'static,
+    ) -> Result<Self, MidHookError> {
+        unsafe {
+            Self::install_with(target, Box::new(callback), |hook| {
+                ProcessMemory::new().write(target, &hook.patch)?;
+                Ok(Jump::Direct)
+            })
+        }
+    }
+
+    /// [install](MidHook::install) with the jump written through `api`'s patch ledger, which
+    /// refuses it if another mod has patched any of the replaced bytes.
+    ///
+    /// # Safety
+    /// See [install](MidHook::install).
+    pub unsafe fn install_through(
+        api: &'static CauldronApi,
+        target: usize,
+        callback: impl Fn(&mut Context) + Send + Sync + 'static,
+    ) -> Result<Self, MidHookError> {
+        unsafe {
+            Self::install_with(target, Box::new(callback), |hook| {
+                api.patch(target as *mut c_void, &hook.original, &hook.patch)
+                    .map(Jump::Ledger)
+                    .ok_or(MidHookError::Refused(target))
+            })
+        }
+    }
+
+    /// Build the hook and its cave, then write its jump with `write`.
+    unsafe fn install_with(
+        target: usize,
+        callback: Callback,
+        write: impl FnOnce(&CaveHook) -> Result<Jump, MidHookError>,
+    ) -> Result<Self, MidHookError> {
+        let callback: Box<Callback> = Box::new(callback);
+        let mut code = [0; READ_LENGTH];
+        unsafe { ProcessMemory::new() }.read(target, &mut code)?;
+
+        let mut cave = CodeCave::allocate_near_address(target, CAVE_SIZE)?;
+        let hook = build(
+            target,
+            &code,
+            cave.address(),
+            &*callback as *const Callback as usize,
+        )?;
+        cave.write(0, &hook.code)?;
+        let jump = write(&hook)?;
+
+        cave.leak();
+        Box::leak(callback);
+        Ok(MidHook {
+            hook,
+            jump: Some(jump),
+        })
+    }
+
+    /// Where the hook jumps from.
+    pub fn target(&self) -> usize {
+        self.hook.target
+    }
+
+    /// Put the replaced instructions back now, fails if they've been patched again since.
+    pub fn remove(mut self) -> Result<(), MidHookError> {
+        self.finish()
+    }
+
+    /// Keep the hook installed for good.
+    pub fn leak(mut self) {
+        self.jump = None;
+    }
+
+    fn finish(&mut self) -> Result<(), MidHookError> {
+        match self.jump.take() {
+            None => Ok(()),
+            Some(Jump::Direct) => {
+                // as patchable as when the hook was installed
+                let mut memory = unsafe { ProcessMemory::new() };
+                let mut found = vec![0; self.hook.patch.len()];
+                memory.read(self.hook.target, &mut found)?;
+                if found != self.hook.patch {
+                    return Err(PatchError::Mismatch {
+                        address: self.hook.target,
+                        expected: self.hook.patch.clone(),
+                        found,
+                    }
+                    .into());
+                }
+                Ok(memory.write(self.hook.target, &self.hook.original)?)
+            }
+            Some(Jump::Ledger(patch)) => match patch.restore() {
+                true => Ok(()),
+                false => Err(MidHookError::Refused(self.hook.target)),
+            },
+        }
+    }
+}
+
+impl Drop for MidHook {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to remove the hook at {:#X}: {e}", self.hook.target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::instruction;
+    use iced_x86::{Decoder, DecoderOptions, Mnemonic};
+
+    const TARGET: usize = 0x1_4000_1000;
+    const CAVE: usize = 0x1_3FFF_0000;
+
+    #[test]
+    fn context_layout() {
+        assert_eq!(size_of::<Context>(), 16 * 16 + 17 * 8);
+        assert_eq!(std::mem::offset_of!(Context, r15), 16 * 16);
+        assert_eq!(
+            std::mem::offset_of!(Context, rax),
+            16 * 16 + (GENERAL_PURPOSE.len() - 1) * 8
+        );
+        assert_eq!(std::mem::offset_of!(Context, rsp), size_of::<Context>() - 8);
+    }
+
+    #[test]
+    fn xmm_scalars() {
+        let mut xmm = Xmm([u64::MAX, 7]);
+        xmm.set_f32(1.5);
+        assert_eq!(xmm.f32(), 1.5);
+        assert_eq!(xmm.0, [0xFFFF_FFFF_0000_0000 | 1.5f32.to_bits() as u64, 7]);
+        xmm.set_f64(-2.25);
+        assert_eq!(xmm.f64(), -2.25);
+    }
+
+    #[test]
+    fn relocated_after_the_callback() {
+        // mov rax, [rip+0x100]; add rax, rcx; ret
+        let code = [
+            0x48, 0x8B, 0x05, 0x00, 0x01, 0x00, 0x00, 0x48, 0x01, 0xC8, 0xC3,
+        ];
+        let hook = build(TARGET, &code, CAVE, 0x1234).unwrap();
+        assert_eq!(hook.original, &code[..7]);
+        assert_eq!(instruction::branch_target(&hook.patch, TARGET), Ok(CAVE));
+
+        let decoded: Vec<_> = Decoder::with_ip(64, &hook.code, CAVE as u64, DecoderOptions::NONE)
+            .into_iter()
+            .collect();
+        let calls = decoded
+            .iter()
+            .filter(|i| i.mnemonic() == Mnemonic::Call)
+            .count();
+        assert_eq!(calls, 1);
+
+        let [.., popfq, lea, mov, jmp] = decoded.as_slice() else {
+            panic!("too few instructions");
+        };
+        assert_eq!(popfq.mnemonic(), Mnemonic::Popfq);
+        assert_eq!(lea.mnemonic(), Mnemonic::Lea);
+        assert!(mov.is_ip_rel_memory_operand());
+        assert_eq!(mov.ip_rel_memory_address(), (TARGET + 7 + 0x100) as u64);
+        assert_eq!(jmp.near_branch_target(), (TARGET + 7) as u64);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn runs_the_callback() {
+        const CONSTANT: usize = 0x100;
+
+        // mov rax, [rip+(CONSTANT-7)]; add rax, rcx; ret
+        let mut code = vec![0x48, 0x8B, 0x05];
+        code.extend((CONSTANT as u32 - 7).to_le_bytes());
+        code.extend([0x48, 0x01, 0xC8, 0xC3]);
+        let mut function =
+            CodeCave::allocate_near_address(dispatch as *const () as usize, 0x1000).unwrap();
+        function.write(0, &code).unwrap();
+        function.write(CONSTANT, &5u64.to_le_bytes()).unwrap();
+        let call: extern "win64" fn(u64) -> u64 =
+            unsafe { std::mem::transmute(function.address()) };
+        assert_eq!(call(3), 8);
+
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let hook = unsafe {
+            MidHook::install(function.address(), {
+                let seen = seen.clone();
+                move |context: &mut Context| {
+                    *seen.lock().unwrap() = Some(context.clone());
+                    context.rcx *= 2;
+                    // loaded by the relocated instruction anyway
+                    context.rax = 0;
+                    context.xmm[0].set_f64(1.0);
+                }
+            })
+        }
+        .unwrap();
+        assert_eq!(hook.target(), function.address());
+
+        assert_eq!(call(3), 11);
+        let seen = seen.lock().unwrap().clone().unwrap();
+        assert_eq!(seen.rcx, 3);
+        assert_ne!(seen.rsp, 0);
+
+        hook.remove().unwrap();
+        assert_eq!(call(3), 8);
+    }
+}
@@ -1,23 +1,28 @@
+pub mod address;
+pub mod asm;
+pub mod cave;
+pub mod image;
+pub mod instruction;
+pub mod mid_hook;
+pub mod module;
 // #[deprecated]
 pub mod offset;
+pub mod patch;
+pub mod pattern;
+pub mod pe;
+mod platform;
+pub mod scan;
+pub mod swap;
 
+use crate::mem::patch::{Memory, ProcessMemory};
 use std::ffi::c_void;
-use windows::Win32::System::Diagnostics::Debug::FlushInstructionCache;
-use windows::Win32::System::Memory::{
-    PAGE_EXECUTE_READWRITE, PAGE_PROTECTION_FLAGS, VirtualProtect,
-};
-use windows::Win32::System::Threading::GetCurrentProcess;
 
+/// Write `data` to `ptr` unchecked, prefer [CauldronApi::patch](crate::CauldronApi::patch).
 pub fn patch(ptr: *mut c_void, data: &[u8]) {
     if !ptr.is_null() {
-        unsafe {
-            let mut flags = PAGE_PROTECTION_FLAGS::default();
-            VirtualProtect(ptr, data.len(), PAGE_EXECUTE_READWRITE, &mut flags).unwrap();
-            for (index, byte) in data.iter().enumerate() {
-                std::ptr::write_bytes(ptr.add(index * size_of::<u8>()), *byte, 1);
-            }
-            VirtualProtect(ptr, data.len(), flags, &mut flags).unwrap();
-            FlushInstructionCache(GetCurrentProcess(), Some(ptr as *const _), data.len()).unwrap();
-        }
+        // unchecked and safe to call, as it's always been
+        unsafe { ProcessMemory::new() }
+            .write(ptr as usize, data)
+            .unwrap();
     }
 }
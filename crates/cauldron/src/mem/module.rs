@@ -0,0 +1,206 @@
+//! Modules loaded in the process, e.g. the game's executable or `fullgame.dll`.
+
+use crate::mem::address::{AddressError, Rva, Va};
+use crate::mem::pe::{self, Export, Import, PeError, Section};
+use crate::mem::platform;
+use std::collections::BTreeSet;
+use std::sync::{Mutex, OnceLock};
+
+static GAME: OnceLock<Module> = OnceLock::new();
+
+/// Names of every module looked up so far, kept so [Module] can stay `Copy`.
+static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+fn intern(name: &str) -> &'static str {
+    let mut names = NAMES.lock().unwrap();
+    match names.get(name) {
+        Some(name) => name,
+        None => {
+            let name = String::from(name).leak();
+            names.insert(name);
+            name
+        }
+    }
+}
+
+/// An image mapped in the process, a PE image on Windows and an ELF object on Linux.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Module {
+    name: &'static str,
+    base: usize,
+    size: u32,
+}
+
+impl Module {
+    /// # Safety
+    /// `size` bytes from `base` must be a mapped PE image for as long as the module is used to
+    /// read it, e.g. with [Module::image].
+    pub const unsafe fn new(name: &'static str, base: usize, size: u32) -> Self {
+        Module { name, base, size }
+    }
+
+    /// The game's executable, found once and kept.
+    pub fn game() -> Result<Module, AddressError> {
+        if let Some(game) = GAME.get() {
+            return Ok(*game);
+        }
+
+        let name = std::env::current_exe()
+            .ok()
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| String::from("game"));
+        let (base, size) =
+            platform::loaded(None).ok_or_else(|| AddressError::ModuleNotFound(name.clone()))?;
+
+        Ok(*GAME.get_or_init(|| Module {
+            name: intern(&name),
+            base,
+            size,
+        }))
+    }
+
+    /// A module that's currently loaded, by file name, e.g. `fullgame.dll`.
+    pub fn find(name: &str) -> Result<Module, AddressError> {
+        let (base, size) = platform::loaded(Some(name))
+            .ok_or_else(|| AddressError::ModuleNotFound(name.to_owned()))?;
+        Ok(Module {
+            name: intern(name),
+            base,
+            size,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        address
+            .checked_sub(self.base)
+            .is_some_and(|offset| offset < self.size as usize)
+    }
+
+    /// `address` as a [Va] in this module, if it points into it.
+    pub fn va(&self, address: usize) -> Result<Va, AddressError> {
+        Va::new(*self, address)
+    }
+
+    /// `offset` as an [Rva] in this module, if it's inside of it.
+    pub fn rva(&self, offset: u32) -> Result<Rva, AddressError> {
+        Rva::new(*self, offset)
+    }
+
+    /// The whole mapped image.
+    pub fn image(&self) -> &'static [u8] {
+        unsafe { std::slice::from_raw_parts(self.base as *const u8, self.size as usize) }
+    }
+
+    pub fn sections(&self) -> Result<Vec<Section>, PeError> {
+        pe::sections(self.image())
+    }
+
+    pub fn exports(&self) -> Result<Vec<Export>, PeError> {
+        pe::exports(self.image())
+    }
+
+    /// Where the export named `name` is, `None` if there's no such export or it's forwarded to
+    /// another module.
+    pub fn export(&self, name: &str) -> Option<Va> {
+        let export = self
+            .exports()
+            .ok()?
+            .into_iter()
+            .find(|export| export.name.as_deref() == Some(name))?;
+        if export.forwarder.is_some() {
+            return None;
+        }
+        self.rva(export.address).ok().map(Rva::to_va)
+    }
+
+    pub fn imports(&self) -> Result<Vec<Import>, PeError> {
+        pe::imports(self.image())
+    }
+
+    /// The import address table entry `name` from `module` is called through, patch it to redirect
+    /// every call this module makes to it. Module names are compared case-insensitively.
+    pub fn import(&self, module: &str, name: &str) -> Option<Va> {
+        let import = self.imports().ok()?.into_iter().find(|import| {
+            import.module.eq_ignore_ascii_case(module) && import.name.as_deref() == Some(name)
+        })?;
+        self.rva(import.slot).ok().map(Rva::to_va)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::pe::{TestSection, test_exports, test_image_with, test_imports};
+
+    fn module(image: &'static [u8]) -> Module {
+        unsafe { Module::new("test.dll", image.as_ptr() as usize, image.len() as u32) }
+    }
+
+    #[test]
+    fn exports_and_imports() {
+        let image = test_image_with(&[
+            TestSection {
+                name: ".text",
+                directory: None,
+                data: Box::new(|_| vec![0xC3; 0x20]),
+            },
+            TestSection {
+                name: ".edata",
+                directory: Some(0),
+                data: Box::new(|address| {
+                    test_exports(address, &[("Run", Ok(0x210)), ("Free", Err("base.Free"))])
+                }),
+            },
+            TestSection {
+                name: ".idata",
+                directory: Some(1),
+                data: Box::new(|address| test_imports(address, &[("KERNEL32.dll", &["Sleep"])])),
+            },
+        ]);
+        let module = module(image.leak());
+
+        assert_eq!(module.export("Run").unwrap().to_rva().get(), 0x210);
+        assert_eq!(module.export("Free"), None);
+        assert_eq!(module.export("Missing"), None);
+
+        let slot = module.import("kernel32.dll", "Sleep").unwrap();
+        assert_eq!(slot.module(), module);
+        assert_eq!(
+            slot.get() - module.base(),
+            module.imports().unwrap()[0].slot as usize
+        );
+        assert_eq!(module.import("kernel32.dll", "Missing"), None);
+
+        let sections = module.sections().unwrap();
+        assert_eq!(sections[0].name, ".text");
+        assert_eq!(sections.len(), 3);
+    }
+
+    #[test]
+    fn interned_names() {
+        assert!(std::ptr::eq(intern("fullgame.dll"), intern("fullgame.dll")));
+    }
+
+    #[test]
+    fn game_is_the_running_executable() {
+        let game = Module::game().unwrap();
+        assert!(game.contains(game_is_the_running_executable as fn() as usize));
+        assert_eq!(Module::game(), Ok(game));
+        assert!(matches!(
+            Module::find("missing.dll"),
+            Err(AddressError::ModuleNotFound(_))
+        ));
+    }
+}
@@ -1,8 +1,21 @@
+use crate::mem::address::{AddressError, Rva, Va};
+use crate::mem::instruction::{self, MAX_INSTRUCTION_LENGTH, ResolveError};
+use crate::mem::module::Module;
+use crate::mem::patch::{Memory, ProcessMemory};
+pub use crate::mem::pattern::parse_pattern;
+use crate::mem::pattern::{ParseError, Signature, to_ida_pattern};
+use crate::mem::pe::PeError;
+use crate::mem::scan::{self, Pattern, SignatureSet};
+use std::collections::{BTreeMap, HashMap};
 use std::ops::{Add, Sub};
 use std::ptr::read_unaligned;
-use windows::Win32::System::Diagnostics::Debug::IMAGE_NT_HEADERS64;
-use windows::Win32::System::LibraryLoader::GetModuleHandleW;
-use windows::Win32::System::SystemServices::IMAGE_DOS_HEADER;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// First match of every pattern scanned for, keyed by the base of the module it was scanned for in
+/// and the pattern, `None` if it wasn't found.
+static SIGNATURE_CACHE: Mutex<BTreeMap<(usize, String), Option<usize>>> =
+    Mutex::new(BTreeMap::new());
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Offset(usize);
@@ -12,111 +25,251 @@ impl Offset {
         Offset(address)
     }
 
-    pub fn from_signature(pattern: &str) -> Result<Self, PatternSearchError> {
-        let (module_start, module_end) = get_module()?;
-        let search = find_pattern(module_start as *mut _, module_end - module_start, pattern)?;
-        Ok(Self::new(search as _))
+    /// Find the first match of `pattern` in the game's module, either an IDA-style string or a
+    /// [sig!](crate::sig).
+    ///
+    /// Results are cached, patterns already scanned for, either here or by
+    /// [Offset::from_signatures], aren't scanned for again.
+    pub fn from_signature<S: Signature + ?Sized>(pattern: &S) -> Result<Self, PatternSearchError> {
+        Self::from_module_signature(&game()?, pattern)
+    }
+
+    /// [Offset::from_signature] in any loaded module.
+    pub fn from_module_signature<S: Signature + ?Sized>(
+        module: &Module,
+        pattern: &S,
+    ) -> Result<Self, PatternSearchError> {
+        let bytes = pattern.pattern_bytes()?;
+        let key = (module.base(), to_ida_pattern(&bytes));
+        if let Some(cached) = SIGNATURE_CACHE.lock().unwrap().get(&key) {
+            return cached.map(Self::new).ok_or(PatternSearchError::NotFound);
+        }
+
+        let pattern = Pattern::from_bytes(bytes.into_owned())?;
+        let found = pattern
+            .find(module.image())
+            .map(|offset| module.base() + offset);
+        SIGNATURE_CACHE.lock().unwrap().insert(key, found);
+
+        found.map(Self::new).ok_or(PatternSearchError::NotFound)
+    }
+
+    /// Find every signature in `signatures` in a single pass over the game's module, returns the
+    /// first match of each by name. Signatures that weren't found are left out.
+    ///
+    /// The results are kept, so later [Offset::from_signature] calls for the same patterns don't
+    /// rescan the module.
+    pub fn from_signatures(
+        signatures: &SignatureSet,
+    ) -> Result<HashMap<String, Self>, PatternSearchError> {
+        Self::from_module_signatures(&game()?, signatures)
+    }
+
+    /// [Offset::from_signatures] in any loaded module.
+    pub fn from_module_signatures(
+        module: &Module,
+        signatures: &SignatureSet,
+    ) -> Result<HashMap<String, Self>, PatternSearchError> {
+        let matches = signatures.scan(module.image());
+
+        let mut cache = SIGNATURE_CACHE.lock().unwrap();
+        let mut offsets = HashMap::new();
+        for (name, pattern) in signatures.iter() {
+            let first = matches.first(name).map(|offset| module.base() + offset);
+            cache.insert((module.base(), pattern.to_owned()), first);
+            if let Some(address) = first {
+                offsets.insert(name.to_owned(), Self::new(address));
+            }
+        }
+
+        Ok(offsets)
+    }
+
+    /// Find the first match of `pattern` in the named sections of the game's module.
+    pub fn from_signature_in<S: Signature + ?Sized>(
+        sections: &[&str],
+        pattern: &S,
+    ) -> Result<Self, PatternSearchError> {
+        Self::from_module_signature_in(&game()?, sections, pattern)
+    }
+
+    /// [Offset::from_signature_in] in any loaded module.
+    pub fn from_module_signature_in<S: Signature + ?Sized>(
+        module: &Module,
+        sections: &[&str],
+        pattern: &S,
+    ) -> Result<Self, PatternSearchError> {
+        Self::all_from_module_signature_in(module, sections, pattern)?
+            .into_iter()
+            .next()
+            .ok_or(PatternSearchError::NotFound)
+    }
+
+    /// Find every match of `pattern` in the named sections of the game's module, or the whole
+    /// module if `sections` is empty.
+    pub fn all_from_signature_in<S: Signature + ?Sized>(
+        sections: &[&str],
+        pattern: &S,
+    ) -> Result<Vec<Self>, PatternSearchError> {
+        Self::all_from_module_signature_in(&game()?, sections, pattern)
+    }
+
+    /// [Offset::all_from_signature_in] in any loaded module.
+    pub fn all_from_module_signature_in<S: Signature + ?Sized>(
+        module: &Module,
+        sections: &[&str],
+        pattern: &S,
+    ) -> Result<Vec<Self>, PatternSearchError> {
+        let pattern = Pattern::from_signature(pattern)?;
+        Ok(scan::find_in_sections(module.image(), sections, &pattern)?
+            .into_iter()
+            .map(|offset| Self::new(module.base() + offset))
+            .collect())
     }
 
     pub fn as_adjusted(&self, offset: usize) -> Offset {
-        let result = Offset(self.0.add(offset));
-        result
+        Offset(self.0.add(offset))
     }
 
     pub fn as_nadjusted(&self, offset: usize) -> Offset {
         Offset(self.0.sub(offset))
     }
 
+    /// Prefer [Offset::as_rip_relative] or [Offset::as_branch_target], which decode the
+    /// instruction instead of trusting `instruction_length`.
     pub fn as_relative(&self, instruction_length: usize) -> Offset {
         let rel_adjust = std::ptr::with_exposed_provenance_mut::<u32>(
             self.0.add(instruction_length.sub(size_of::<u32>())),
         );
         let rel_adjust = unsafe { read_unaligned(rel_adjust) } as usize;
-        let result = Offset(self.0.add(rel_adjust.add(instruction_length)));
-        result
+        Offset(self.0.add(rel_adjust.add(instruction_length)))
+    }
+
+    /// Decode the instruction here and follow its rip-relative operand, e.g. the global `lea rax,
+    /// [rip+0x1234]` or `mov rcx, [rip+0x1234]` refers to.
+    ///
+    /// # Safety
+    /// The instruction here has to be readable, [MAX_INSTRUCTION_LENGTH] bytes of it.
+    pub unsafe fn as_rip_relative(&self) -> Result<Offset, ResolveError> {
+        instruction::rip_relative(&unsafe { self.instruction_bytes() }?, self.0).map(Offset)
+    }
+
+    /// Decode the instruction here and follow its relative `call`, `jmp` or `jcc`.
+    ///
+    /// # Safety
+    /// The instruction here has to be readable, [MAX_INSTRUCTION_LENGTH] bytes of it.
+    pub unsafe fn as_branch_target(&self) -> Result<Offset, ResolveError> {
+        instruction::branch_target(&unsafe { self.instruction_bytes() }?, self.0).map(Offset)
+    }
+
+    /// Decode the instruction here and follow whichever of a relative branch or a rip-relative
+    /// operand it has.
+    ///
+    /// # Safety
+    /// The instruction here has to be readable, [MAX_INSTRUCTION_LENGTH] bytes of it.
+    pub unsafe fn as_resolved(&self) -> Result<Offset, ResolveError> {
+        instruction::resolve(&unsafe { self.instruction_bytes() }?, self.0).map(Offset)
+    }
+
+    /// Decode the instruction here and return the displacement of its memory operand, e.g.
+    /// `0x1A8` in `mov rax, [rcx+0x1A8]`.
+    ///
+    /// # Safety
+    /// The instruction here has to be readable, [MAX_INSTRUCTION_LENGTH] bytes of it.
+    pub unsafe fn displacement(&self) -> Result<i64, ResolveError> {
+        instruction::displacement(&unsafe { self.instruction_bytes() }?, self.0)
+    }
+
+    /// Follow a pointer chain starting here, see [instruction::pointer_chain].
+    ///
+    /// # Safety
+    /// Every pointer along the chain has to be readable.
+    pub unsafe fn as_pointer_chain(&self, offsets: &[usize]) -> Result<Offset, ResolveError> {
+        instruction::pointer_chain(&unsafe { ProcessMemory::new() }, self.0, offsets).map(Offset)
+    }
+
+    unsafe fn instruction_bytes(&self) -> Result<[u8; MAX_INSTRUCTION_LENGTH], ResolveError> {
+        let mut bytes = [0u8; MAX_INSTRUCTION_LENGTH];
+        unsafe { ProcessMemory::new() }.read(self.0, &mut bytes)?;
+        Ok(bytes)
     }
 
     pub fn as_ptr<T>(&self) -> *mut T {
         self.0 as *mut T
     }
 
+    /// This address in the game's module.
+    pub fn as_va(&self) -> Result<Va, AddressError> {
+        self.as_va_in(&Module::game()?)
+    }
+
+    /// This address in `module`.
+    pub fn as_va_in(&self, module: &Module) -> Result<Va, AddressError> {
+        module.va(self.0)
+    }
+
+    /// This address relative to the start of the game's module.
+    pub fn as_rva(&self) -> Result<Rva, AddressError> {
+        self.as_va().map(Va::to_rva)
+    }
+
+    #[deprecated = "use Offset::as_rva, which checks the address is in the game's module"]
     pub fn as_offset(&self) -> *mut u8 {
         self.0.sub(get_module().unwrap().0) as *mut _
     }
 }
 
-#[derive(Debug, Clone)]
+impl From<Va> for Offset {
+    fn from(va: Va) -> Self {
+        Offset(va.get())
+    }
+}
+
+#[derive(Debug, Clone, Error)]
 pub enum PatternSearchError {
-    ParseInt(std::num::ParseIntError),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("address is out of range")]
     OutOfRange,
+    #[error("pattern not found")]
     NotFound,
+    #[error("pattern is empty")]
+    Empty,
+    #[error("invalid image: {0}")]
+    Image(PeError),
+    #[error("no section named {0}")]
+    SectionNotFound(String),
+    #[error("duplicate signature {0}")]
+    DuplicateSignature(String),
+    #[error("{0}")]
+    Module(AddressError),
 }
 
-/// parses an ida-style byte sequence pattern
-pub fn parse_pattern(mask: &str) -> Result<Vec<(u8, bool)>, PatternSearchError> {
-    let mask = mask.replace("?", "??");
-    let mask = mask.replace(" ", "");
-
-    (0..mask.len())
-        .step_by(2)
-        .map(|i| {
-            let radix = &mask[i..i + 2];
-            if radix == "??" {
-                Ok((0x00, true))
-            } else {
-                Ok((
-                    u8::from_str_radix(radix, 16).unwrap(), /*? todo */
-                    false,
-                ))
-            }
-        })
-        .collect()
+fn game() -> Result<Module, PatternSearchError> {
+    Module::game().map_err(PatternSearchError::Module)
 }
 
-pub fn find_pattern(
+/// Find the first match of `mask` in `max_size` bytes from `start_address`.
+///
+/// # Safety
+/// `start_address` must be valid for reads of `max_size` bytes.
+pub unsafe fn find_pattern(
     start_address: *mut u8,
     max_size: usize,
     mask: &str,
 ) -> Result<*mut u8, PatternSearchError> {
-    let pattern = parse_pattern(mask)?;
-    let data_end = start_address as usize + max_size + 1;
-
-    let result = unsafe { std::slice::from_raw_parts(start_address, max_size + 1) }
-        .windows(pattern.len())
-        .position(|pos| {
-            pos.iter()
-                .enumerate()
-                .all(|(i, b)| pattern[i].1 || pattern[i].0.eq(b))
-        });
-
-    let Some(result) = result else {
+    let pattern = Pattern::new(mask)?;
+    let data = unsafe { std::slice::from_raw_parts(start_address, max_size) };
+
+    let Some(result) = pattern.find(data) else {
         return Err(PatternSearchError::NotFound);
     };
 
-    if result > data_end {
-        return Err(PatternSearchError::OutOfRange);
-    }
-
     Ok((start_address as usize + result) as *mut u8)
 }
 
+/// Start and end of the game's module, prefer [Module::game].
 pub fn get_module() -> Result<(usize, usize), PatternSearchError> {
-    let base = unsafe { GetModuleHandleW(None).unwrap() };
-    if base.0.is_null() {
-        return Err(PatternSearchError::OutOfRange);
-    }
-
-    let base = base.0 as usize;
-    let dos_header = unsafe { &*(base as *const IMAGE_DOS_HEADER) };
-    let nt_headers_ptr =
-        (base as isize).wrapping_add(dos_header.e_lfanew as isize) as *const IMAGE_NT_HEADERS64;
-    let nt_headers = unsafe {
-        if nt_headers_ptr.is_null() {
-            return Err(PatternSearchError::OutOfRange);
-        } else {
-            &*nt_headers_ptr
-        }
-    };
-    let end = base + nt_headers.OptionalHeader.SizeOfImage as usize;
-    Ok((base, end))
+    let game = game()?;
+    Ok((game.base(), game.base() + game.size() as usize))
 }
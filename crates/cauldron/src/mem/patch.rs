@@ -0,0 +1,409 @@
+//! Verified, reversible memory patches.
+//!
+//! A [PatchLedger] records every patch it applies along with the bytes it replaced, so patches
+//! can be undone, and refuses patches that overlap another mod's. The loader owns the
+//! process-wide ledger, mods go through [CauldronApi::patch](crate::CauldronApi::patch).
+
+use crate::mem::platform;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum PatchError {
+    #[error("patch at {address:#X} is empty")]
+    Empty { address: usize },
+    #[error("patch at {address:#X} expects {expected} bytes but writes {data}")]
+    LengthMismatch {
+        address: usize,
+        expected: usize,
+        data: usize,
+    },
+    #[error("expected {} at {address:#X} but found {}", hex(.expected), hex(.found))]
+    Mismatch {
+        address: usize,
+        expected: Vec<u8>,
+        found: Vec<u8>,
+    },
+    #[error("{address:#X}..{end:#X} overlaps a patch by {owner} at {other:#X}..{other_end:#X}")]
+    Overlap {
+        address: usize,
+        end: usize,
+        owner: String,
+        other: usize,
+        other_end: usize,
+    },
+    #[error("{address:#X}..{end:#X} is out of bounds")]
+    OutOfBounds { address: usize, end: usize },
+    #[error("failed to access {address:#X}: {message}")]
+    Access { address: usize, message: String },
+    #[error("no patch with id {0}")]
+    UnknownPatch(u64),
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Somewhere patches can be written to.
+pub trait Memory {
+    fn read(&self, address: usize, buf: &mut [u8]) -> Result<(), PatchError>;
+    fn write(&mut self, address: usize, data: &[u8]) -> Result<(), PatchError>;
+}
+
+/// A plain buffer, addressed by index.
+impl Memory for [u8] {
+    fn read(&self, address: usize, buf: &mut [u8]) -> Result<(), PatchError> {
+        let range = slice_range(self.len(), address, buf.len())?;
+        buf.copy_from_slice(&self[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, address: usize, data: &[u8]) -> Result<(), PatchError> {
+        let range = slice_range(self.len(), address, data.len())?;
+        self[range].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+fn slice_range(len: usize, address: usize, size: usize) -> Result<Range<usize>, PatchError> {
+    match address.checked_add(size) {
+        Some(end) if end <= len => Ok(address..end),
+        _ => Err(PatchError::OutOfBounds {
+            address,
+            end: address.wrapping_add(size),
+        }),
+    }
+}
+
+/// The current process's memory, made writable for the duration of each write.
+///
+/// Reads and writes go wherever they're told, so only unsafe code can get one.
+pub struct ProcessMemory {
+    _private: (),
+}
+
+impl ProcessMemory {
+    /// # Safety
+    /// Every range read or written through it must be mapped, and writes mustn't change memory
+    /// Rust holds references to, or code another thread may be running.
+    pub const unsafe fn new() -> Self {
+        ProcessMemory { _private: () }
+    }
+}
+
+impl Memory for ProcessMemory {
+    fn read(&self, address: usize, buf: &mut [u8]) -> Result<(), PatchError> {
+        unsafe { std::ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len()) };
+        Ok(())
+    }
+
+    fn write(&mut self, address: usize, data: &[u8]) -> Result<(), PatchError> {
+        platform::write(address, data)
+    }
+}
+
+struct AppliedPatch {
+    owner: String,
+    address: usize,
+    original: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl AppliedPatch {
+    fn range(&self) -> Range<usize> {
+        self.address..self.address + self.data.len()
+    }
+}
+
+/// Every patch applied through it, keyed by id in the order they were applied.
+#[derive(Default)]
+pub struct PatchLedger {
+    next_id: u64,
+    patches: BTreeMap<u64, AppliedPatch>,
+}
+
+impl PatchLedger {
+    /// Replace `expected` at `address` with `data`, returns the patch's id.
+    ///
+    /// Fails without writing anything if the bytes at `address` aren't `expected`, or the range
+    /// overlaps a patch by another owner. Overlapping your own patches is fine, as long as they're
+    /// restored in reverse order.
+    pub fn apply<M: Memory + ?Sized>(
+        &mut self,
+        memory: &mut M,
+        owner: &str,
+        address: usize,
+        expected: &[u8],
+        data: &[u8],
+    ) -> Result<u64, PatchError> {
+        if data.is_empty() {
+            return Err(PatchError::Empty { address });
+        }
+        if expected.len() != data.len() {
+            return Err(PatchError::LengthMismatch {
+                address,
+                expected: expected.len(),
+                data: data.len(),
+            });
+        }
+
+        let range = slice_range(usize::MAX, address, data.len())?;
+        if let Some(other) = self
+            .patches
+            .values()
+            .find(|p| p.owner != owner && p.address < range.end && range.start < p.range().end)
+        {
+            return Err(PatchError::Overlap {
+                address,
+                end: range.end,
+                owner: other.owner.clone(),
+                other: other.address,
+                other_end: other.range().end,
+            });
+        }
+
+        verify(memory, address, expected)?;
+        memory.write(address, data)?;
+
+        // ids start at 1, the api uses 0 for failure
+        self.next_id += 1;
+        let id = self.next_id;
+        self.patches.insert(
+            id,
+            AppliedPatch {
+                owner: owner.to_owned(),
+                address,
+                original: expected.to_vec(),
+                data: data.to_vec(),
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Write back the bytes patch `id` replaced.
+    ///
+    /// Fails if the patched bytes have since been changed, e.g. by a later overlapping patch.
+    pub fn restore<M: Memory + ?Sized>(
+        &mut self,
+        memory: &mut M,
+        id: u64,
+    ) -> Result<(), PatchError> {
+        let patch = self.patches.get(&id).ok_or(PatchError::UnknownPatch(id))?;
+        verify(memory, patch.address, &patch.data)?;
+        memory.write(patch.address, &patch.original)?;
+
+        self.patches.remove(&id);
+        Ok(())
+    }
+
+    /// The owner of patch `id`.
+    pub fn owner(&self, id: u64) -> Option<&str> {
+        self.patches.get(&id).map(|p| p.owner.as_str())
+    }
+
+    /// Restore every patch `owner` applied, newest first, returns the number of restored patches.
+    pub fn restore_owned_by<M: Memory + ?Sized>(&mut self, memory: &mut M, owner: &str) -> usize {
+        let ids: Vec<u64> = self
+            .patches
+            .iter()
+            .rev()
+            .filter(|(_, p)| p.owner == owner)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut restored = 0;
+        for id in ids {
+            match self.restore(memory, id) {
+                Ok(()) => restored += 1,
+                Err(e) => log::error!("Failed to restore {owner}'s patch {id}: {e}"),
+            }
+        }
+        restored
+    }
+}
+
+fn verify<M: Memory + ?Sized>(
+    memory: &M,
+    address: usize,
+    expected: &[u8],
+) -> Result<(), PatchError> {
+    let mut found = vec![0; expected.len()];
+    memory.read(address, &mut found)?;
+    if found != expected {
+        return Err(PatchError::Mismatch {
+            address,
+            expected: expected.to_vec(),
+            found,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer() -> Vec<u8> {
+        (0..32).collect()
+    }
+
+    #[test]
+    fn apply_and_restore() {
+        let mut memory = buffer();
+        let mut ledger = PatchLedger::default();
+
+        let id = ledger
+            .apply(
+                memory.as_mut_slice(),
+                "a",
+                4,
+                &[4, 5, 6],
+                &[0x90, 0x90, 0x90],
+            )
+            .unwrap();
+        assert_eq!(&memory[3..8], &[3, 0x90, 0x90, 0x90, 7]);
+        assert_eq!(ledger.owner(id), Some("a"));
+
+        ledger.restore(memory.as_mut_slice(), id).unwrap();
+        assert_eq!(memory, buffer());
+        assert_eq!(
+            ledger.restore(memory.as_mut_slice(), id),
+            Err(PatchError::UnknownPatch(id))
+        );
+    }
+
+    #[test]
+    fn mismatch_writes_nothing() {
+        let mut memory = buffer();
+        let mut ledger = PatchLedger::default();
+
+        assert_eq!(
+            ledger.apply(memory.as_mut_slice(), "a", 4, &[4, 0xFF], &[0, 0]),
+            Err(PatchError::Mismatch {
+                address: 4,
+                expected: vec![4, 0xFF],
+                found: vec![4, 5],
+            })
+        );
+        assert_eq!(memory, buffer());
+    }
+
+    #[test]
+    fn invalid_patches() {
+        let mut memory = buffer();
+        let mut ledger = PatchLedger::default();
+
+        assert_eq!(
+            ledger.apply(memory.as_mut_slice(), "a", 4, &[], &[]),
+            Err(PatchError::Empty { address: 4 })
+        );
+        assert!(matches!(
+            ledger.apply(memory.as_mut_slice(), "a", 4, &[4], &[0, 0]),
+            Err(PatchError::LengthMismatch { .. })
+        ));
+        assert_eq!(
+            ledger.apply(memory.as_mut_slice(), "a", 30, &[30, 31, 32], &[0, 0, 0]),
+            Err(PatchError::OutOfBounds {
+                address: 30,
+                end: 33
+            })
+        );
+    }
+
+    #[test]
+    fn overlap_between_owners() {
+        let mut memory = buffer();
+        let mut ledger = PatchLedger::default();
+
+        ledger
+            .apply(memory.as_mut_slice(), "a", 4, &[4, 5, 6, 7], &[0; 4])
+            .unwrap();
+
+        let overlap = ledger.apply(memory.as_mut_slice(), "b", 7, &[0, 8], &[1, 1]);
+        assert_eq!(
+            overlap,
+            Err(PatchError::Overlap {
+                address: 7,
+                end: 9,
+                owner: "a".into(),
+                other: 4,
+                other_end: 8,
+            })
+        );
+        assert_eq!(
+            overlap.unwrap_err().to_string(),
+            "0x7..0x9 overlaps a patch by a at 0x4..0x8"
+        );
+
+        // adjacent is fine
+        ledger
+            .apply(memory.as_mut_slice(), "b", 8, &[8, 9], &[1, 1])
+            .unwrap();
+        ledger
+            .apply(memory.as_mut_slice(), "b", 2, &[2, 3], &[1, 1])
+            .unwrap();
+    }
+
+    #[test]
+    fn own_overlap_restores_in_reverse() {
+        let mut memory = buffer();
+        let mut ledger = PatchLedger::default();
+
+        let first = ledger
+            .apply(memory.as_mut_slice(), "a", 4, &[4, 5, 6], &[0xAA; 3])
+            .unwrap();
+        let second = ledger
+            .apply(memory.as_mut_slice(), "a", 5, &[0xAA, 0xAA, 7], &[0xBB; 3])
+            .unwrap();
+
+        // the second patch changed the first's bytes
+        assert!(matches!(
+            ledger.restore(memory.as_mut_slice(), first),
+            Err(PatchError::Mismatch { address: 4, .. })
+        ));
+
+        ledger.restore(memory.as_mut_slice(), second).unwrap();
+        ledger.restore(memory.as_mut_slice(), first).unwrap();
+        assert_eq!(memory, buffer());
+    }
+
+    #[test]
+    fn restore_owned_by() {
+        let mut memory = buffer();
+        let mut ledger = PatchLedger::default();
+
+        ledger
+            .apply(memory.as_mut_slice(), "a", 0, &[0, 1], &[0xAA; 2])
+            .unwrap();
+        ledger
+            .apply(memory.as_mut_slice(), "a", 1, &[0xAA, 2], &[0xBB; 2])
+            .unwrap();
+        let other = ledger
+            .apply(memory.as_mut_slice(), "b", 10, &[10], &[0xCC])
+            .unwrap();
+
+        assert_eq!(ledger.restore_owned_by(memory.as_mut_slice(), "a"), 2);
+        assert_eq!(&memory[..3], &[0, 1, 2]);
+        assert_eq!(memory[10], 0xCC);
+        assert_eq!(ledger.owner(other), Some("b"));
+    }
+
+    #[test]
+    fn mismatch_display() {
+        let error = PatchError::Mismatch {
+            address: 0x1400,
+            expected: vec![0x48, 0x8B],
+            found: vec![0xE9, 0x00],
+        };
+        assert_eq!(
+            error.to_string(),
+            "expected 48 8B at 0x1400 but found E9 00"
+        );
+    }
+}
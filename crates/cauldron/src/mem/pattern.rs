@@ -0,0 +1,121 @@
+//! Byte pattern parsing, see [cauldron_pattern] for the formats.
+//!
+//! [sig!](crate::sig) parses either format at compile time instead, with the same parser.
+
+use crate::mem::offset::PatternSearchError;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+pub use cauldron_pattern::{ParseError, PatternByte};
+
+/// Something that can be scanned for, either an IDA-style pattern or the output of
+/// [sig!](crate::sig).
+pub trait Signature {
+    fn pattern_bytes(&self) -> Result<Cow<'_, [PatternByte]>, PatternSearchError>;
+}
+
+impl Signature for str {
+    fn pattern_bytes(&self) -> Result<Cow<'_, [PatternByte]>, PatternSearchError> {
+        parse_pattern(self).map(Cow::Owned)
+    }
+}
+
+impl Signature for String {
+    fn pattern_bytes(&self) -> Result<Cow<'_, [PatternByte]>, PatternSearchError> {
+        self.as_str().pattern_bytes()
+    }
+}
+
+impl Signature for [PatternByte] {
+    fn pattern_bytes(&self) -> Result<Cow<'_, [PatternByte]>, PatternSearchError> {
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+impl<const N: usize> Signature for [PatternByte; N] {
+    fn pattern_bytes(&self) -> Result<Cow<'_, [PatternByte]>, PatternSearchError> {
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+/// Write `bytes` as an IDA-style pattern, the same bytes always give the same string.
+pub fn to_ida_pattern(bytes: &[PatternByte]) -> String {
+    let mut pattern = String::with_capacity(bytes.len() * 3);
+    for (i, byte) in bytes.iter().enumerate() {
+        if i > 0 {
+            pattern.push(' ');
+        }
+        for shift in [4, 0] {
+            if (byte.mask >> shift) & 0xF == 0 {
+                pattern.push('?');
+            } else {
+                write!(pattern, "{:X}", (byte.value >> shift) & 0xF).unwrap();
+            }
+        }
+    }
+    pattern
+}
+
+/// Parse an IDA-style pattern.
+pub fn parse_pattern(pattern: &str) -> Result<Vec<PatternByte>, PatternSearchError> {
+    Ok(cauldron_pattern::parse_pattern(pattern)?)
+}
+
+/// Parse a code-style pattern, `bytes` written as `\x48\x8B` escapes and `mask` made of `x`
+/// (match) and `?` (any byte).
+pub fn parse_code_pattern(bytes: &str, mask: &str) -> Result<Vec<PatternByte>, PatternSearchError> {
+    Ok(cauldron_pattern::parse_code_pattern(bytes, mask)?)
+}
+
+/// Combine raw bytes with a code-style mask, e.g. `b"\x48\x8B\x00"` and `xx?`.
+pub fn code_pattern(bytes: &[u8], mask: &str) -> Result<Vec<PatternByte>, PatternSearchError> {
+    Ok(cauldron_pattern::code_pattern(bytes, mask)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse_pattern("48 8G"),
+            Err(PatternSearchError::Parse(ParseError::InvalidCharacter {
+                position: 4,
+                character: 'G'
+            }))
+        ));
+        assert_eq!(
+            parse_code_pattern(r"\x48\x8B", "x")
+                .unwrap_err()
+                .to_string(),
+            "mask is 1 long but the pattern has 2 bytes"
+        );
+    }
+
+    #[test]
+    fn ida_round_trip() {
+        let pattern = "48 8B ?? 4? ?B";
+        assert_eq!(to_ida_pattern(&parse_pattern(pattern).unwrap()), pattern);
+        assert_eq!(
+            to_ida_pattern(&parse_pattern("488b ? 4?").unwrap()),
+            "48 8B ?? 4?"
+        );
+    }
+
+    #[test]
+    fn sig_macro() {
+        const SIGNATURE: &[PatternByte] = crate::sig!("48 8B ? 4?");
+        assert_eq!(SIGNATURE, parse_pattern("48 8B ? 4?").unwrap());
+        assert_eq!(crate::sig!("488B??05"), parse_pattern("488B??05").unwrap());
+        assert_eq!(crate::sig!(" ?B\t48 "), parse_pattern(" ?B\t48 ").unwrap());
+        assert_eq!(
+            crate::sig!(b"\x48\x8B\x00", "xx?"),
+            code_pattern(b"\x48\x8B\x00", "xx?").unwrap()
+        );
+        assert_eq!(
+            crate::sig!(r"\x48\x8B\x00", "xX?"),
+            parse_code_pattern(r"\x48\x8B\x00", "xX?").unwrap()
+        );
+    }
+}
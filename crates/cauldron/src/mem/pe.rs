@@ -0,0 +1,682 @@
+//! Just enough PE parsing to find the sections, exports and imports of a mapped image.
+
+use std::ops::Range;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum PeError {
+    #[error("missing MZ signature")]
+    NotDos,
+    #[error("missing PE signature")]
+    NotPe,
+    #[error("not a PE32+ image")]
+    NotPe32Plus,
+    #[error("headers run past the end of the image")]
+    Truncated,
+    #[error("imports from {0} have no lookup table")]
+    NoLookupTable(String),
+}
+
+/// A section of a mapped image.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+}
+
+impl Section {
+    /// Where the section is relative to the image base, clamped to `image_len`.
+    pub fn range(&self, image_len: usize) -> Range<usize> {
+        let start = (self.virtual_address as usize).min(image_len);
+        let end = (start + self.virtual_size as usize).min(image_len);
+        start..end
+    }
+}
+
+fn read_u16(image: &[u8], offset: usize) -> Result<u16, PeError> {
+    image
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(PeError::Truncated)
+}
+
+fn read_u32(image: &[u8], offset: usize) -> Result<u32, PeError> {
+    image
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(PeError::Truncated)
+}
+
+fn read_u64(image: &[u8], offset: usize) -> Result<u64, PeError> {
+    image
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(PeError::Truncated)
+}
+
+fn read_str(image: &[u8], offset: usize) -> Result<String, PeError> {
+    let bytes = image.get(offset..).ok_or(PeError::Truncated)?;
+    let len = bytes
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(PeError::Truncated)?;
+    Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+const PE32_PLUS: u16 = 0x20B;
+const EXPORT_DIRECTORY: usize = 0;
+const IMPORT_DIRECTORY: usize = 1;
+
+struct Headers {
+    optional_header: usize,
+    optional_header_size: usize,
+    section_table: usize,
+    section_count: usize,
+}
+
+fn headers(image: &[u8]) -> Result<Headers, PeError> {
+    if image.get(0..2) != Some(b"MZ") {
+        return Err(PeError::NotDos);
+    }
+
+    let nt_headers = read_u32(image, 0x3C)? as usize;
+    if image.get(nt_headers..nt_headers + 4) != Some(b"PE\0\0") {
+        return Err(PeError::NotPe);
+    }
+
+    let file_header = nt_headers + 4;
+    let optional_header_size = read_u16(image, file_header + 16)? as usize;
+    Ok(Headers {
+        optional_header: file_header + 20,
+        optional_header_size,
+        section_table: file_header + 20 + optional_header_size,
+        section_count: read_u16(image, file_header + 2)? as usize,
+    })
+}
+
+/// The optional header, if the image has one, which has to be PE32+.
+fn optional_header(image: &[u8]) -> Result<Option<usize>, PeError> {
+    let headers = headers(image)?;
+    if headers.optional_header_size == 0 {
+        return Ok(None);
+    }
+    if read_u16(image, headers.optional_header)? != PE32_PLUS {
+        return Err(PeError::NotPe32Plus);
+    }
+    Ok(Some(headers.optional_header))
+}
+
+/// Where the data directory at `index` is, `None` if the image doesn't have it.
+fn directory(image: &[u8], index: usize) -> Result<Option<Range<usize>>, PeError> {
+    let Some(optional_header) = optional_header(image)? else {
+        return Ok(None);
+    };
+    if index >= read_u32(image, optional_header + 108)? as usize {
+        return Ok(None);
+    }
+
+    let entry = optional_header + 112 + index * 8;
+    let address = read_u32(image, entry)? as usize;
+    let size = read_u32(image, entry + 4)? as usize;
+    Ok((address != 0).then_some(address..address + size))
+}
+
+/// The section table of an image mapped the way the Windows loader maps it.
+pub fn sections(image: &[u8]) -> Result<Vec<Section>, PeError> {
+    let headers = headers(image)?;
+    (0..headers.section_count)
+        .map(|i| {
+            let header = headers.section_table + i * 40;
+            let name = image.get(header..header + 8).ok_or(PeError::Truncated)?;
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(8)];
+
+            Ok(Section {
+                name: String::from_utf8_lossy(name).into_owned(),
+                virtual_size: read_u32(image, header + 8)?,
+                virtual_address: read_u32(image, header + 12)?,
+            })
+        })
+        .collect()
+}
+
+/// How much memory the image takes up once mapped, only the headers have to be in `image`.
+pub fn image_size(image: &[u8]) -> Result<u32, PeError> {
+    match optional_header(image)? {
+        Some(optional_header) => read_u32(image, optional_header + 56),
+        None => Err(PeError::Truncated),
+    }
+}
+
+/// The file header and section table, which tell builds of an image apart. Unlike the optional
+/// header, they're mapped as they are in the file, so they're the same on every launch.
+pub fn build_headers(image: &[u8]) -> Result<Vec<u8>, PeError> {
+    let headers = headers(image)?;
+    let file_header = image
+        .get(headers.optional_header - 20..headers.optional_header)
+        .ok_or(PeError::Truncated)?;
+    let section_table = image
+        .get(headers.section_table..headers.section_table + headers.section_count * 40)
+        .ok_or(PeError::Truncated)?;
+    Ok([file_header, section_table].concat())
+}
+
+/// The address the image would rather be loaded at.
+pub fn image_base(image: &[u8]) -> Result<u64, PeError> {
+    match optional_header(image)? {
+        Some(optional_header) => read_u64(image, optional_header + 24),
+        None => Err(PeError::Truncated),
+    }
+}
+
+/// Lay a PE file out the way the Windows loader maps it, without relocating it or resolving its
+/// imports.
+pub fn map(file: &[u8]) -> Result<Vec<u8>, PeError> {
+    let headers = headers(file)?;
+    let optional_header = optional_header(file)?.ok_or(PeError::Truncated)?;
+    let size = read_u32(file, optional_header + 56)? as usize;
+    let headers_size = read_u32(file, optional_header + 60)? as usize;
+
+    let mut image = vec![0; size];
+    let headers_size = headers_size.min(file.len()).min(size);
+    image[..headers_size].copy_from_slice(&file[..headers_size]);
+
+    for i in 0..headers.section_count {
+        let header = headers.section_table + i * 40;
+        let virtual_size = read_u32(file, header + 8)? as usize;
+        let address = read_u32(file, header + 12)? as usize;
+        let raw_size = read_u32(file, header + 16)? as usize;
+        let raw_address = read_u32(file, header + 20)? as usize;
+
+        // the rest of the section is zeroes, e.g. uninitialized data
+        let len = if virtual_size == 0 {
+            raw_size
+        } else {
+            raw_size.min(virtual_size)
+        };
+        let data = file
+            .get(raw_address..raw_address + len)
+            .ok_or(PeError::Truncated)?;
+        image
+            .get_mut(address..address + len)
+            .ok_or(PeError::Truncated)?
+            .copy_from_slice(data);
+    }
+
+    Ok(image)
+}
+
+/// A function or variable exported by an image.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Export {
+    /// `None` for exports that are only exported by ordinal.
+    pub name: Option<String>,
+    pub ordinal: u32,
+    /// Where the export is relative to the image base, meaningless if it's forwarded.
+    pub address: u32,
+    /// The `module.function` the export is forwarded to, if it is.
+    pub forwarder: Option<String>,
+}
+
+/// The export table of a mapped image.
+pub fn exports(image: &[u8]) -> Result<Vec<Export>, PeError> {
+    let Some(directory) = directory(image, EXPORT_DIRECTORY)? else {
+        return Ok(Vec::new());
+    };
+
+    let base = read_u32(image, directory.start + 0x10)?;
+    let function_count = read_u32(image, directory.start + 0x14)? as usize;
+    let name_count = read_u32(image, directory.start + 0x18)? as usize;
+    let functions = read_u32(image, directory.start + 0x1C)? as usize;
+    let names = read_u32(image, directory.start + 0x20)? as usize;
+    let name_ordinals = read_u32(image, directory.start + 0x24)? as usize;
+
+    let mut exports = (0..function_count)
+        .map(|i| {
+            let address = read_u32(image, functions + i * 4)?;
+            let forwarder = if directory.contains(&(address as usize)) {
+                Some(read_str(image, address as usize)?)
+            } else {
+                None
+            };
+
+            Ok(Export {
+                name: None,
+                ordinal: base + i as u32,
+                address,
+                forwarder,
+            })
+        })
+        .collect::<Result<Vec<_>, PeError>>()?;
+
+    for i in 0..name_count {
+        let index = read_u16(image, name_ordinals + i * 2)? as usize;
+        let name = read_str(image, read_u32(image, names + i * 4)? as usize)?;
+        exports.get_mut(index).ok_or(PeError::Truncated)?.name = Some(name);
+    }
+
+    // unused ordinals are left as zeroes
+    exports.retain(|export| export.address != 0);
+    Ok(exports)
+}
+
+/// A function or variable an image imports from another module.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Import {
+    pub module: String,
+    /// `None` for functions that are imported by ordinal.
+    pub name: Option<String>,
+    /// The ordinal it's imported by, or the hint for where its name is in the export table.
+    pub ordinal: u16,
+    /// Where its import address table entry is relative to the image base.
+    pub slot: u32,
+}
+
+/// The import table of a mapped image.
+///
+/// Names are read from the import lookup tables, modules imported without one fail with
+/// [PeError::NoLookupTable], as their import address tables are overwritten once they're mapped.
+pub fn imports(image: &[u8]) -> Result<Vec<Import>, PeError> {
+    let Some(directory) = directory(image, IMPORT_DIRECTORY)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut imports = Vec::new();
+    for descriptor in (directory.start..).step_by(20) {
+        let lookup_table = read_u32(image, descriptor)? as usize;
+        let name = read_u32(image, descriptor + 12)? as usize;
+        let address_table = read_u32(image, descriptor + 16)?;
+        if name == 0 && address_table == 0 {
+            break;
+        }
+
+        let module = read_str(image, name)?;
+        if lookup_table == 0 {
+            return Err(PeError::NoLookupTable(module));
+        }
+        for i in 0.. {
+            let entry = read_u64(image, lookup_table + i * 8)?;
+            if entry == 0 {
+                break;
+            }
+
+            let (name, ordinal) = if entry & (1 << 63) != 0 {
+                (None, entry as u16)
+            } else {
+                let hint_name = entry as u32 as usize;
+                (
+                    Some(read_str(image, hint_name + 2)?),
+                    read_u16(image, hint_name)?,
+                )
+            };
+
+            imports.push(Import {
+                module: module.clone(),
+                name,
+                ordinal,
+                slot: address_table + i as u32 * 8,
+            });
+        }
+    }
+
+    Ok(imports)
+}
+
+/// Build a mapped image with the given sections, for tests elsewhere in the crate.
+#[cfg(test)]
+pub(crate) fn test_image(sections: &[(&str, &[u8])]) -> Vec<u8> {
+    let sections: Vec<TestSection> = sections
+        .iter()
+        .map(|&(name, data)| TestSection {
+            name,
+            directory: None,
+            data: Box::new(move |_| data.to_vec()),
+        })
+        .collect();
+    test_image_with(&sections)
+}
+
+/// A section of an image built by [test_image_with], its data is built from its address.
+#[cfg(test)]
+pub(crate) struct TestSection<'a> {
+    pub name: &'a str,
+    /// The data directory the whole section is, if any.
+    pub directory: Option<usize>,
+    pub data: Box<dyn Fn(u32) -> Vec<u8> + 'a>,
+}
+
+/// Build a mapped image with the given sections, with a PE32+ optional header if any of them are
+/// data directories.
+#[cfg(test)]
+pub(crate) fn test_image_with(sections: &[TestSection]) -> Vec<u8> {
+    const NT_HEADERS: usize = 0x40;
+    const OPTIONAL_HEADER: usize = NT_HEADERS + 4 + 20;
+    const ALIGNMENT: usize = 0x100;
+
+    let optional_header_size = if sections.iter().any(|s| s.directory.is_some()) {
+        112 + 16 * 8
+    } else {
+        0
+    };
+    let section_table = OPTIONAL_HEADER + optional_header_size;
+    let headers_end = section_table + sections.len() * 40;
+    let mut image = vec![0; headers_end.next_multiple_of(ALIGNMENT)];
+    image[0..2].copy_from_slice(b"MZ");
+    image[0x3C..0x40].copy_from_slice(&(NT_HEADERS as u32).to_le_bytes());
+    image[NT_HEADERS..NT_HEADERS + 4].copy_from_slice(b"PE\0\0");
+    image[NT_HEADERS + 6..NT_HEADERS + 8].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+    image[NT_HEADERS + 20..NT_HEADERS + 22]
+        .copy_from_slice(&(optional_header_size as u16).to_le_bytes());
+    if optional_header_size != 0 {
+        image[OPTIONAL_HEADER..OPTIONAL_HEADER + 2].copy_from_slice(&PE32_PLUS.to_le_bytes());
+        image[OPTIONAL_HEADER + 108..OPTIONAL_HEADER + 112].copy_from_slice(&16u32.to_le_bytes());
+    }
+
+    for (i, section) in sections.iter().enumerate() {
+        let header = section_table + i * 40;
+        let address = image.len();
+        let data = (section.data)(address as u32);
+        image[header..header + section.name.len()].copy_from_slice(section.name.as_bytes());
+        image[header + 8..header + 12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        image[header + 12..header + 16].copy_from_slice(&(address as u32).to_le_bytes());
+        if let Some(index) = section.directory {
+            let entry = OPTIONAL_HEADER + 112 + index * 8;
+            image[entry..entry + 4].copy_from_slice(&(address as u32).to_le_bytes());
+            image[entry + 4..entry + 8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+
+        image.extend_from_slice(&data);
+        image.resize(image.len().next_multiple_of(ALIGNMENT), 0);
+    }
+
+    if optional_header_size != 0 {
+        let size = image.len() as u32;
+        image[OPTIONAL_HEADER + 56..OPTIONAL_HEADER + 60].copy_from_slice(&size.to_le_bytes());
+    }
+    image
+}
+
+/// An export directory at `address`, exporting each name at its address, or forwarding it if its
+/// target is a string.
+#[cfg(test)]
+pub(crate) fn test_exports(address: u32, exports: &[(&str, Result<u32, &str>)]) -> Vec<u8> {
+    let functions = 0x28;
+    let names = functions + exports.len() * 4;
+    let name_ordinals = names + exports.len() * 4;
+
+    fn put(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    let mut data = vec![0; name_ordinals + exports.len() * 2];
+    put(&mut data, 0x10, 1);
+    put(&mut data, 0x14, exports.len() as u32);
+    put(&mut data, 0x18, exports.len() as u32);
+    put(&mut data, 0x1C, address + functions as u32);
+    put(&mut data, 0x20, address + names as u32);
+    put(&mut data, 0x24, address + name_ordinals as u32);
+
+    for (i, (name, target)) in exports.iter().enumerate() {
+        let name_address = address + data.len() as u32;
+        put(&mut data, names + i * 4, name_address);
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+
+        let target = match target {
+            Ok(target) => *target,
+            Err(forwarder) => {
+                let forwarder_address = address + data.len() as u32;
+                data.extend_from_slice(forwarder.as_bytes());
+                data.push(0);
+                forwarder_address
+            }
+        };
+        put(&mut data, functions + i * 4, target);
+        data[name_ordinals + i * 2..name_ordinals + i * 2 + 2]
+            .copy_from_slice(&(i as u16).to_le_bytes());
+    }
+
+    data
+}
+
+/// An import directory at `address`, importing the given names from each module, or ordinals for
+/// names starting with `#`.
+#[cfg(test)]
+pub(crate) fn test_imports(address: u32, imports: &[(&str, &[&str])]) -> Vec<u8> {
+    let mut data = vec![0; (imports.len() + 1) * 20];
+    for (i, (module, names)) in imports.iter().enumerate() {
+        let lookup_table = data.len();
+        let address_table = lookup_table + (names.len() + 1) * 8;
+        data.resize(address_table + (names.len() + 1) * 8, 0);
+
+        for (j, name) in names.iter().enumerate() {
+            let entry = match name.strip_prefix('#') {
+                Some(ordinal) => (1 << 63) | ordinal.parse::<u64>().unwrap(),
+                None => {
+                    let hint_name = address as u64 + data.len() as u64;
+                    data.extend_from_slice(&(j as u16).to_le_bytes());
+                    data.extend_from_slice(name.as_bytes());
+                    data.push(0);
+                    hint_name
+                }
+            };
+            for table in [lookup_table, address_table] {
+                data[table + j * 8..table + j * 8 + 8].copy_from_slice(&entry.to_le_bytes());
+            }
+        }
+
+        let module_name = address + data.len() as u32;
+        data.extend_from_slice(module.as_bytes());
+        data.push(0);
+
+        let descriptor = i * 20;
+        data[descriptor..descriptor + 4]
+            .copy_from_slice(&(address + lookup_table as u32).to_le_bytes());
+        data[descriptor + 12..descriptor + 16].copy_from_slice(&module_name.to_le_bytes());
+        data[descriptor + 16..descriptor + 20]
+            .copy_from_slice(&(address + address_table as u32).to_le_bytes());
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn section_table() {
+        let image = test_image(&[(".text", &[0xCC; 0x180]), (".rdata", b"hello")]);
+        assert_eq!(
+            sections(&image),
+            Ok(vec![
+                Section {
+                    name: ".text".into(),
+                    virtual_address: 0x100,
+                    virtual_size: 0x180,
+                },
+                Section {
+                    name: ".rdata".into(),
+                    virtual_address: 0x300,
+                    virtual_size: 5,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn build_identity() {
+        let image = image_with_directories();
+        let build = build_headers(&image).unwrap();
+        assert_eq!(build.len(), 20 + sections(&image).unwrap().len() * 40);
+
+        // relocated and hooked
+        let mut mapped = image.clone();
+        let optional_header = headers(&image).unwrap().optional_header;
+        mapped[optional_header + 24..optional_header + 32].fill(0xAB);
+        let text = sections(&image).unwrap()[0].virtual_address as usize;
+        mapped[text] = 0xE9;
+        assert_eq!(build_headers(&mapped).unwrap(), build);
+
+        let other = test_image(&[(".text", &[0xCC; 0x180])]);
+        assert_ne!(build_headers(&other).unwrap(), build);
+    }
+
+    #[test]
+    fn invalid_images() {
+        assert_eq!(sections(b""), Err(PeError::NotDos));
+        assert_eq!(sections(b"MZ"), Err(PeError::Truncated));
+
+        let mut image = test_image(&[(".text", &[0; 4])]);
+        image[0x40] = b'X';
+        assert_eq!(sections(&image), Err(PeError::NotPe));
+    }
+
+    fn image_with_directories() -> Vec<u8> {
+        test_image_with(&[
+            TestSection {
+                name: ".text",
+                directory: None,
+                data: Box::new(|_| vec![0xC3; 0x20]),
+            },
+            TestSection {
+                name: ".edata",
+                directory: Some(EXPORT_DIRECTORY),
+                data: Box::new(|address| {
+                    test_exports(
+                        address,
+                        &[
+                            ("Init", Ok(0x200)),
+                            ("Shutdown", Ok(0x210)),
+                            ("Alloc", Err("other.Alloc")),
+                        ],
+                    )
+                }),
+            },
+            TestSection {
+                name: ".idata",
+                directory: Some(IMPORT_DIRECTORY),
+                data: Box::new(|address| {
+                    test_imports(
+                        address,
+                        &[
+                            ("KERNEL32.dll", &["GetProcAddress", "#42"]),
+                            ("fullgame.dll", &["Run"]),
+                        ],
+                    )
+                }),
+            },
+        ])
+    }
+
+    #[test]
+    fn export_table() {
+        let image = image_with_directories();
+        let exports = exports(&image).unwrap();
+        assert_eq!(
+            exports[..2],
+            [
+                Export {
+                    name: Some("Init".into()),
+                    ordinal: 1,
+                    address: 0x200,
+                    forwarder: None,
+                },
+                Export {
+                    name: Some("Shutdown".into()),
+                    ordinal: 2,
+                    address: 0x210,
+                    forwarder: None,
+                },
+            ]
+        );
+        assert_eq!(exports[2].name.as_deref(), Some("Alloc"));
+        assert_eq!(exports[2].ordinal, 3);
+        assert_eq!(exports[2].forwarder.as_deref(), Some("other.Alloc"));
+        assert_eq!(exports.len(), 3);
+    }
+
+    #[test]
+    fn import_table() {
+        let image = image_with_directories();
+        let imports = imports(&image).unwrap();
+        let names: Vec<_> = imports
+            .iter()
+            .map(|i| (i.module.as_str(), i.name.as_deref(), i.ordinal))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("KERNEL32.dll", Some("GetProcAddress"), 0),
+                ("KERNEL32.dll", None, 42),
+                ("fullgame.dll", Some("Run"), 0),
+            ]
+        );
+
+        // each import's slot is the next entry of its module's address table
+        assert_eq!(imports[1].slot, imports[0].slot + 8);
+        let slot = imports[2].slot as usize;
+        let hint_name = u64::from_le_bytes(image[slot..slot + 8].try_into().unwrap());
+        assert_eq!(read_str(&image, hint_name as usize + 2).unwrap(), "Run");
+    }
+
+    #[test]
+    fn import_without_lookup_table() {
+        let mut image = image_with_directories();
+        let descriptor = directory(&image, IMPORT_DIRECTORY).unwrap().unwrap().start;
+        image[descriptor..descriptor + 4].fill(0);
+        assert_eq!(
+            imports(&image),
+            Err(PeError::NoLookupTable(String::from("KERNEL32.dll")))
+        );
+    }
+
+    #[test]
+    fn without_directories() {
+        let image = test_image(&[(".text", &[0; 4])]);
+        assert_eq!(exports(&image), Ok(vec![]));
+        assert_eq!(imports(&image), Ok(vec![]));
+        assert_eq!(image_size(&image), Err(PeError::Truncated));
+    }
+
+    #[test]
+    fn size() {
+        let image = image_with_directories();
+        assert_eq!(image_size(&image), Ok(image.len() as u32));
+
+        let mut image = image;
+        image[0x58] = 0x0B;
+        image[0x59] = 0x01;
+        assert_eq!(image_size(&image), Err(PeError::NotPe32Plus));
+    }
+
+    #[test]
+    fn map_file() {
+        let image = image_with_directories();
+        let sections = sections(&image).unwrap();
+        let headers_size = sections[0].virtual_address as usize;
+
+        // pack the sections one after another, the way they're stored on disk
+        let mut file = image[..headers_size].to_vec();
+        file[0x58 + 60..0x58 + 64].copy_from_slice(&(headers_size as u32).to_le_bytes());
+        for (i, section) in sections.iter().enumerate() {
+            let header = 0x148 + i * 40;
+            let raw_size = (section.virtual_size as usize).next_multiple_of(0x10);
+            file[header + 16..header + 20].copy_from_slice(&(raw_size as u32).to_le_bytes());
+            let raw_address = file.len() as u32;
+            file[header + 20..header + 24].copy_from_slice(&raw_address.to_le_bytes());
+
+            let start = section.virtual_address as usize;
+            file.extend_from_slice(&image[start..start + raw_size]);
+        }
+
+        let mapped = map(&file).unwrap();
+        assert_eq!(mapped.len(), image.len());
+        assert_eq!(mapped[headers_size..], image[headers_size..]);
+        assert_eq!(exports(&mapped), exports(&image));
+        assert_eq!(imports(&mapped), imports(&image));
+
+        file.truncate(file.len() - 0x10);
+        assert_eq!(map(&file), Err(PeError::Truncated));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::mem::patch::PatchError;
+use std::ffi::{CStr, c_int, c_void};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Held while pages are made writable, so one write can't protect pages another is writing to.
+static WRITING: Mutex<()> = Mutex::new(());
+
+const RWX: c_int = libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC;
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+fn last_error() -> String {
+    std::io::Error::last_os_error().to_string()
+}
+
+/// A mapping from `/proc/self/maps`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Mapping {
+    range: Range<usize>,
+    protection: c_int,
+}
+
+/// Parse a line of `/proc/self/maps`, e.g. `7f00-7f10 r-xp 00000000 08:01 42 /usr/lib/libc.so.6`.
+fn parse_mapping(line: &str) -> Option<Mapping> {
+    let mut fields = line.split_whitespace();
+    let (start, end) = fields.next()?.split_once('-')?;
+    let permissions = fields.next()?.as_bytes();
+    let flag = |i: usize, c: u8, flag: c_int| {
+        if permissions.get(i) == Some(&c) {
+            flag
+        } else {
+            0
+        }
+    };
+    Some(Mapping {
+        range: usize::from_str_radix(start, 16).ok()?..usize::from_str_radix(end, 16).ok()?,
+        protection: flag(0, b'r', libc::PROT_READ)
+            | flag(1, b'w', libc::PROT_WRITE)
+            | flag(2, b'x', libc::PROT_EXEC),
+    })
+}
+
+/// The mappings covering `pages`, `None` if any of it isn't mapped.
+fn mappings(pages: &Range<usize>) -> std::io::Result<Option<Vec<Mapping>>> {
+    let maps = std::fs::read_to_string("/proc/self/maps")?;
+    let mut covering = Vec::new();
+    let mut next = pages.start;
+    for mapping in maps.lines().filter_map(parse_mapping) {
+        if mapping.range.end <= next || mapping.range.start >= pages.end {
+            continue;
+        }
+        if mapping.range.start > next {
+            return Ok(None);
+        }
+        next = mapping.range.end;
+        covering.push(mapping);
+    }
+    Ok((next >= pages.end).then_some(covering))
+}
+
+/// Write `data` to `address`, made writable for the duration of the write.
+pub(crate) fn write(address: usize, data: &[u8]) -> Result<(), PatchError> {
+    let access = |message: String| PatchError::Access { address, message };
+    if data.is_empty() {
+        return Ok(());
+    }
+    let end = address
+        .checked_add(data.len())
+        .ok_or_else(|| access(String::from("the write wraps around")))?;
+    let page = page_size();
+    let pages = address / page * page..end.div_ceil(page) * page;
+
+    let _writing = WRITING.lock().unwrap_or_else(|e| e.into_inner());
+    let mappings = mappings(&pages)
+        .map_err(|e| access(e.to_string()))?
+        .ok_or_else(|| access(String::from("not mapped")))?;
+
+    let protect = |range: &Range<usize>, protection: c_int| {
+        let result = unsafe { libc::mprotect(range.start as *mut c_void, range.len(), protection) };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(access(last_error()))
+        }
+    };
+    protect(&pages, RWX)?;
+    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), address as *mut u8, data.len()) };
+    for mapping in mappings {
+        let range = mapping.range.start.max(pages.start)..mapping.range.end.min(pages.end);
+        protect(&range, mapping.protection)?;
+    }
+
+    // x86 keeps instruction caches coherent by itself
+    Ok(())
+}
+
+/// Allocate `size` bytes of executable memory at exactly `address`.
+pub(crate) fn allocate(address: usize, size: usize) -> Option<usize> {
+    let allocated = unsafe {
+        libc::mmap(
+            address as *mut c_void,
+            size,
+            RWX,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+            -1,
+            0,
+        )
+    };
+    if allocated == libc::MAP_FAILED {
+        return None;
+    }
+    // kernels older than 4.17 take the address as a hint
+    if allocated as usize != address {
+        unsafe { libc::munmap(allocated, size) };
+        return None;
+    }
+    Some(address)
+}
+
+/// Free memory from [allocate].
+pub(crate) fn free(address: usize, size: usize) -> Result<(), String> {
+    match unsafe { libc::munmap(address as *mut c_void, size) } {
+        0 => Ok(()),
+        _ => Err(last_error()),
+    }
+}
+
+struct Search<'a> {
+    name: Option<&'a str>,
+    found: Option<(usize, u32)>,
+}
+
+unsafe extern "C" fn visit(
+    info: *mut libc::dl_phdr_info,
+    _size: libc::size_t,
+    search: *mut c_void,
+) -> c_int {
+    let (info, search) = unsafe { (&*info, &mut *(search as *mut Search)) };
+
+    // the executable always comes first
+    if let Some(name) = search.name {
+        if info.dlpi_name.is_null() {
+            return 0;
+        }
+        let path = unsafe { CStr::from_ptr(info.dlpi_name) }.to_string_lossy();
+        if Path::new(path.as_ref())
+            .file_name()
+            .and_then(|f| f.to_str())
+            != Some(name)
+        {
+            return 0;
+        }
+    }
+
+    let headers = unsafe { std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize) };
+    let segments = headers
+        .iter()
+        .filter(|header| header.p_type == libc::PT_LOAD);
+    let start = segments.clone().map(|header| header.p_vaddr).min();
+    let end = segments.map(|header| header.p_vaddr + header.p_memsz).max();
+    if let (Some(start), Some(end)) = (start, end) {
+        let base = info.dlpi_addr as usize + start as usize;
+        search.found = u32::try_from(end - start).ok().map(|size| (base, size));
+    }
+    1
+}
+
+/// Base and size of a loaded object, or the executable if `name` is `None`. The size spans every
+/// loaded segment, gaps between them included.
+pub(crate) fn loaded(name: Option<&str>) -> Option<(usize, u32)> {
+    let mut search = Search { name, found: None };
+    unsafe { libc::dl_iterate_phdr(Some(visit), &mut search as *mut Search as *mut c_void) };
+    search.found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline(never)]
+    extern "C" fn answer() -> u32 {
+        std::hint::black_box(42)
+    }
+
+    #[test]
+    fn parse_maps() {
+        let line = "7f00a000-7f00c000 r-xp 00000000 08:01 42     /usr/lib/libc.so.6";
+        assert_eq!(
+            parse_mapping(line),
+            Some(Mapping {
+                range: 0x7F00_A000..0x7F00_C000,
+                protection: libc::PROT_READ | libc::PROT_EXEC,
+            })
+        );
+        assert_eq!(
+            parse_mapping("1000-2000 ---p 00000000 00:00 0")
+                .unwrap()
+                .protection,
+            0
+        );
+        assert_eq!(parse_mapping("garbage"), None);
+    }
+
+    #[test]
+    fn write_own_code() {
+        let function = answer as extern "C" fn() -> u32 as usize;
+        let mut original = [0; 6];
+        unsafe { std::ptr::copy_nonoverlapping(function as *const u8, original.as_mut_ptr(), 6) };
+        let protection = |address: usize| {
+            let page = address / page_size() * page_size();
+            mappings(&(page..page + 1)).unwrap().unwrap()[0].protection
+        };
+        let before = protection(function);
+
+        // mov eax, 7; ret
+        write(function, &[0xB8, 7, 0, 0, 0, 0xC3]).unwrap();
+        assert_eq!(std::hint::black_box(answer as extern "C" fn() -> u32)(), 7);
+        assert_eq!(protection(function), before);
+
+        write(function, &original).unwrap();
+        assert_eq!(std::hint::black_box(answer as extern "C" fn() -> u32)(), 42);
+
+        assert!(matches!(write(0, &[0]), Err(PatchError::Access { .. })));
+    }
+
+    #[test]
+    fn allocate_and_free() {
+        let address = 0x7E00_0000_0000;
+        assert_eq!(allocate(address, 0x1000), Some(address));
+        assert_eq!(allocate(address, 0x1000), None);
+        write(address, &[0xC3]).unwrap();
+        free(address, 0x1000).unwrap();
+    }
+
+    #[test]
+    fn loaded_objects() {
+        let (base, size) = loaded(None).unwrap();
+        let function = answer as extern "C" fn() -> u32 as usize;
+        assert!((base..base + size as usize).contains(&function));
+        assert_eq!(unsafe { *(base as *const [u8; 4]) }, *b"\x7FELF");
+
+        assert!(loaded(Some("libc.so.6")).is_some());
+        assert_eq!(loaded(Some("missing.so")), None);
+    }
+}
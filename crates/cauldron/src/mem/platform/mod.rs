@@ -0,0 +1,17 @@
+//! What [mem](crate::mem) needs from the OS, always about the current process.
+//!
+//! Windows is what the games run on, Linux is there so patching, scanning and hooking can be
+//! tested with `cargo test` against the test binary's own memory.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::*;
+#[cfg(windows)]
+pub(crate) use windows::*;
+
+#[cfg(not(any(windows, target_os = "linux")))]
+compile_error!("cauldron::mem only supports Windows and Linux");
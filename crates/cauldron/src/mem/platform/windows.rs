@@ -0,0 +1,78 @@
+use crate::mem::patch::PatchError;
+use crate::mem::pe;
+use std::ffi::c_void;
+use windows::Win32::System::Diagnostics::Debug::FlushInstructionCache;
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Memory::{
+    MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PAGE_EXECUTE_READWRITE, PAGE_PROTECTION_FLAGS,
+    VirtualAlloc, VirtualFree, VirtualProtect,
+};
+use windows::Win32::System::Threading::GetCurrentProcess;
+use windows::core::PCWSTR;
+
+/// Write `data` to `address`, made writable for the duration of the write.
+pub(crate) fn write(address: usize, data: &[u8]) -> Result<(), PatchError> {
+    let ptr = address as *mut c_void;
+    let access = |e: windows::core::Error| PatchError::Access {
+        address,
+        message: e.to_string(),
+    };
+
+    unsafe {
+        let mut flags = PAGE_PROTECTION_FLAGS::default();
+        VirtualProtect(ptr, data.len(), PAGE_EXECUTE_READWRITE, &mut flags).map_err(access)?;
+        std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+        VirtualProtect(ptr, data.len(), flags, &mut flags).map_err(access)?;
+        FlushInstructionCache(GetCurrentProcess(), Some(ptr as *const _), data.len())
+            .map_err(access)?;
+    }
+
+    Ok(())
+}
+
+/// Allocate `size` bytes of executable memory at exactly `address`.
+pub(crate) fn allocate(address: usize, size: usize) -> Option<usize> {
+    let requested = address as *const c_void;
+    let allocated = unsafe {
+        VirtualAlloc(
+            Some(requested),
+            size,
+            MEM_RESERVE | MEM_COMMIT,
+            PAGE_EXECUTE_READWRITE,
+        )
+    };
+    if allocated.is_null() {
+        return None;
+    }
+    if allocated as usize != address {
+        let _ = unsafe { VirtualFree(allocated, 0, MEM_RELEASE) };
+        return None;
+    }
+    Some(address)
+}
+
+/// Free memory from [allocate].
+pub(crate) fn free(address: usize, _size: usize) -> Result<(), String> {
+    unsafe { VirtualFree(address as *mut c_void, 0, MEM_RELEASE) }.map_err(|e| e.to_string())
+}
+
+/// Base and size of a loaded module, or the executable if `name` is `None`.
+pub(crate) fn loaded(name: Option<&str>) -> Option<(usize, u32)> {
+    let handle = match name {
+        Some(name) => {
+            let name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+            unsafe { GetModuleHandleW(PCWSTR(name.as_ptr())) }
+        }
+        None => unsafe { GetModuleHandleW(None) },
+    }
+    .ok()?;
+    if handle.0.is_null() {
+        return None;
+    }
+
+    // the headers always fit in the first page
+    let base = handle.0 as usize;
+    let headers = unsafe { std::slice::from_raw_parts(base as *const u8, 0x1000) };
+    let size = pe::image_size(headers).ok()?;
+    Some((base, size))
+}
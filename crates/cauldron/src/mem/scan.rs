@@ -0,0 +1,586 @@
+//! Wildcard-aware pattern scanning.
+//!
+//! Patterns are matched with Boyer-Moore-Horspool, where a wildcard limits how far the scan can
+//! skip ahead, as any byte could match it. Batches of patterns are found in a single pass with a
+//! [SignatureSet] instead.
+
+use crate::mem::offset::PatternSearchError;
+use crate::mem::pattern::{self, PatternByte, Signature};
+use crate::mem::pe;
+use std::ops::Range;
+
+/// A parsed pattern, ready to be scanned for.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    bytes: Vec<PatternByte>,
+    /// How far to skip ahead, keyed by the byte under the end of the pattern.
+    skip: [usize; 256],
+}
+
+impl Pattern {
+    /// Parse an IDA-style pattern, e.g. `48 8B ? ?? 4? 89`.
+    pub fn new(pattern: &str) -> Result<Self, PatternSearchError> {
+        Self::from_bytes(pattern::parse_pattern(pattern)?)
+    }
+
+    pub fn from_signature<S: Signature + ?Sized>(
+        signature: &S,
+    ) -> Result<Self, PatternSearchError> {
+        Self::from_bytes(signature.pattern_bytes()?.into_owned())
+    }
+
+    /// Parse a code-style pattern, e.g. `\x48\x8B\x00` with the mask `xx?`.
+    pub fn from_code(bytes: &str, mask: &str) -> Result<Self, PatternSearchError> {
+        Self::from_bytes(pattern::parse_code_pattern(bytes, mask)?)
+    }
+
+    pub fn from_bytes(bytes: Vec<PatternByte>) -> Result<Self, PatternSearchError> {
+        if bytes.is_empty() {
+            return Err(PatternSearchError::Empty);
+        }
+
+        let last = bytes.len() - 1;
+        let mut skip = [bytes.len(); 256];
+        for (i, byte) in bytes[..last].iter().enumerate() {
+            for (b, skip) in skip.iter_mut().enumerate() {
+                if byte.matches(b as u8) {
+                    *skip = last - i;
+                }
+            }
+        }
+
+        Ok(Pattern { bytes, skip })
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The pattern's bytes, where anything that isn't an exact match is `None`.
+    pub fn bytes(&self) -> impl Iterator<Item = Option<u8>> + '_ {
+        self.bytes.iter().map(|b| b.is_exact().then_some(b.value))
+    }
+
+    /// Whether `window` starts with this pattern.
+    pub fn matches(&self, window: &[u8]) -> bool {
+        window.len() >= self.len() && self.bytes.iter().zip(window).all(|(b, w)| b.matches(*w))
+    }
+
+    /// The offset of the first match in `haystack`.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        self.find_all(haystack).next()
+    }
+
+    /// The offsets of every match in `haystack`, including overlapping ones.
+    pub fn find_all<'a>(&'a self, haystack: &'a [u8]) -> FindAll<'a> {
+        FindAll {
+            pattern: self,
+            haystack,
+            position: 0,
+        }
+    }
+}
+
+/// Iterator over the matches of a [Pattern], see [Pattern::find_all].
+pub struct FindAll<'a> {
+    pattern: &'a Pattern,
+    haystack: &'a [u8],
+    position: usize,
+}
+
+impl Iterator for FindAll<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let last = self.pattern.len() - 1;
+        while self.position + last < self.haystack.len() {
+            let position = self.position;
+            let end = self.haystack[position + last];
+            if self.pattern.matches(&self.haystack[position..]) {
+                self.position += 1;
+                return Some(position);
+            }
+            self.position += self.pattern.skip[end as usize];
+        }
+        None
+    }
+}
+
+/// Scan the named sections of a mapped `image`, returns the offset of every match from the image
+/// base. Every section is scanned if `sections` is empty.
+pub fn find_in_sections(
+    image: &[u8],
+    sections: &[&str],
+    pattern: &Pattern,
+) -> Result<Vec<usize>, PatternSearchError> {
+    let image_sections = pe::sections(image).map_err(PatternSearchError::Image)?;
+    for name in sections {
+        if !image_sections.iter().any(|s| s.name == *name) {
+            return Err(PatternSearchError::SectionNotFound(name.to_string()));
+        }
+    }
+
+    let mut matches: Vec<usize> = image_sections
+        .iter()
+        .filter(|s| sections.is_empty() || sections.contains(&s.name.as_str()))
+        .flat_map(|s| {
+            let range = s.range(image.len());
+            pattern
+                .find_all(&image[range.clone()])
+                .map(move |offset| range.start + offset)
+        })
+        .collect();
+    matches.sort_unstable();
+    Ok(matches)
+}
+
+/// Scanning less than this per thread isn't worth spawning it.
+const MIN_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Bytes that show up all over x86-64 code, which make poor anchors.
+const COMMON_BYTES: [u8; 16] = [
+    0x00, 0x01, 0x0F, 0x24, 0x41, 0x44, 0x48, 0x4C, 0x74, 0x83, 0x85, 0x89, 0x8B, 0x8D, 0xCC, 0xFF,
+];
+
+/// Where a pattern is looked up during a batch scan.
+#[derive(Debug, Clone, Copy)]
+enum Anchor {
+    /// Two consecutive fixed bytes at an offset into the pattern.
+    Pair(u16, usize),
+    /// A single fixed byte at an offset into the pattern.
+    Byte(u8, usize),
+    /// Nothing but wildcards, matches everywhere it fits.
+    None,
+}
+
+impl Anchor {
+    fn choose(pattern: &Pattern) -> Self {
+        let bytes: Vec<Option<u8>> = pattern.bytes().collect();
+        let score = |b: u8| COMMON_BYTES.contains(&b) as u8;
+
+        let pair = bytes
+            .windows(2)
+            .enumerate()
+            .filter_map(|(i, w)| Some((i, w[0]?, w[1]?)))
+            .min_by_key(|&(_, a, b)| score(a) + score(b));
+        if let Some((offset, a, b)) = pair {
+            return Anchor::Pair(u16::from_be_bytes([a, b]), offset);
+        }
+
+        bytes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| Some((i, (*b)?)))
+            .min_by_key(|&(_, b)| score(b))
+            .map_or(Anchor::None, |(offset, b)| Anchor::Byte(b, offset))
+    }
+}
+
+/// `(pattern, anchor offset)` lists, indexed by anchor value.
+#[derive(Debug, Clone)]
+struct AnchorTable {
+    starts: Vec<u32>,
+    entries: Vec<(usize, usize)>,
+}
+
+impl AnchorTable {
+    fn new(size: usize, mut anchors: Vec<(usize, usize, usize)>) -> Self {
+        anchors.sort_unstable_by_key(|&(key, _, _)| key);
+
+        let mut starts = vec![0u32; size + 1];
+        for &(key, _, _) in &anchors {
+            starts[key + 1] += 1;
+        }
+        for i in 0..size {
+            starts[i + 1] += starts[i];
+        }
+
+        AnchorTable {
+            starts,
+            entries: anchors
+                .into_iter()
+                .map(|(_, pattern, offset)| (pattern, offset))
+                .collect(),
+        }
+    }
+
+    fn get(&self, key: usize) -> &[(usize, usize)] {
+        &self.entries[self.starts[key] as usize..self.starts[key + 1] as usize]
+    }
+}
+
+/// A set of named patterns, found together in one pass with [SignatureSet::scan].
+///
+/// Each pattern is looked up by its rarest pair of fixed bytes, so the cost of a scan depends on
+/// the size of the image rather than the number of patterns.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureSet {
+    names: Vec<String>,
+    sources: Vec<String>,
+    patterns: Vec<Pattern>,
+}
+
+impl SignatureSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `pattern` under `name`, which has to be unique within the set.
+    pub fn add<S: Signature + ?Sized>(
+        &mut self,
+        name: &str,
+        pattern: &S,
+    ) -> Result<(), PatternSearchError> {
+        if self.names.iter().any(|n| n == name) {
+            return Err(PatternSearchError::DuplicateSignature(name.to_owned()));
+        }
+
+        let bytes = pattern.pattern_bytes()?;
+        self.sources.push(pattern::to_ida_pattern(&bytes));
+        self.patterns.push(Pattern::from_bytes(bytes.into_owned())?);
+        self.names.push(name.to_owned());
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// `(name, pattern)` of every signature in the set, patterns written IDA-style.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.names
+            .iter()
+            .map(String::as_str)
+            .zip(self.sources.iter().map(String::as_str))
+    }
+
+    /// Find every match of every pattern in `haystack`, split across as many threads as are
+    /// available.
+    pub fn scan(&self, haystack: &[u8]) -> SignatureMatches {
+        let mut pairs = Vec::new();
+        let mut bytes = Vec::new();
+        let mut unanchored = Vec::new();
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            match Anchor::choose(pattern) {
+                Anchor::Pair(key, offset) => pairs.push((key as usize, index, offset)),
+                Anchor::Byte(key, offset) => bytes.push((key as usize, index, offset)),
+                Anchor::None => unanchored.push(index),
+            }
+        }
+        let pairs = AnchorTable::new(0x10000, pairs);
+        let bytes = AnchorTable::new(0x100, bytes);
+
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(haystack.len() / MIN_CHUNK_SIZE)
+            .max(1);
+        let chunk_size = haystack.len().div_ceil(threads).max(1);
+
+        // every match is found through its anchor's position, so chunks split on anchor positions
+        // don't need to overlap
+        let chunks: Vec<Vec<Vec<usize>>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..haystack.len())
+                .step_by(chunk_size)
+                .map(|start| {
+                    let positions = start..(start + chunk_size).min(haystack.len());
+                    let (pairs, bytes, unanchored) = (&pairs, &bytes, &unanchored);
+                    scope.spawn(move || {
+                        self.scan_chunk(haystack, positions, pairs, bytes, unanchored)
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        let mut matches = vec![Vec::new(); self.patterns.len()];
+        for chunk in chunks {
+            for (all, found) in matches.iter_mut().zip(chunk) {
+                all.extend(found);
+            }
+        }
+
+        SignatureMatches {
+            names: self.names.clone(),
+            matches,
+        }
+    }
+
+    fn scan_chunk(
+        &self,
+        haystack: &[u8],
+        positions: Range<usize>,
+        pairs: &AnchorTable,
+        bytes: &AnchorTable,
+        unanchored: &[usize],
+    ) -> Vec<Vec<usize>> {
+        let mut matches = vec![Vec::new(); self.patterns.len()];
+        let mut check = |index: usize, position: usize, offset: usize| {
+            if let Some(start) = position.checked_sub(offset)
+                && self.patterns[index].matches(&haystack[start..])
+            {
+                matches[index].push(start);
+            }
+        };
+
+        for position in positions {
+            let byte = haystack[position];
+            for &(index, offset) in bytes.get(byte as usize) {
+                check(index, position, offset);
+            }
+            if let Some(&next) = haystack.get(position + 1) {
+                for &(index, offset) in pairs.get(u16::from_be_bytes([byte, next]) as usize) {
+                    check(index, position, offset);
+                }
+            }
+            for &index in unanchored {
+                check(index, position, 0);
+            }
+        }
+
+        matches
+    }
+}
+
+/// The matches of every pattern in a [SignatureSet], in ascending order.
+#[derive(Debug, Clone)]
+pub struct SignatureMatches {
+    names: Vec<String>,
+    matches: Vec<Vec<usize>>,
+}
+
+impl SignatureMatches {
+    /// Every match of the pattern called `name`.
+    pub fn get(&self, name: &str) -> Option<&[usize]> {
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .map(|i| self.matches[i].as_slice())
+    }
+
+    /// The first match of the pattern called `name`.
+    pub fn first(&self, name: &str) -> Option<usize> {
+        self.get(name)?.first().copied()
+    }
+
+    /// `(name, matches)` of every pattern in the set.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[usize])> {
+        self.names
+            .iter()
+            .map(String::as_str)
+            .zip(self.matches.iter().map(Vec::as_slice))
+    }
+
+    /// Names of the patterns that weren't found.
+    pub fn missing(&self) -> impl Iterator<Item = &str> {
+        self.iter()
+            .filter(|(_, matches)| matches.is_empty())
+            .map(|(name, _)| name)
+    }
+
+    /// `(name, matches)` of the patterns that were found more than once.
+    pub fn ambiguous(&self) -> impl Iterator<Item = (&str, &[usize])> {
+        self.iter().filter(|(_, matches)| matches.len() > 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(haystack: &[u8], pattern: &Pattern) -> Vec<usize> {
+        (0..haystack.len())
+            .filter(|&i| pattern.matches(&haystack[i..]))
+            .collect()
+    }
+
+    // deterministic noise, with a small alphabet so partial matches are common
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545F4914F6CDD1Du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state % 4) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find() {
+        let haystack = [0x48, 0x8B, 0x05, 0x10, 0x20, 0x30, 0x40, 0x48, 0x89];
+        assert_eq!(Pattern::new("8B ? 10").unwrap().find(&haystack), Some(1));
+        assert_eq!(Pattern::new("48 89").unwrap().find(&haystack), Some(7));
+        assert_eq!(Pattern::new("48 8A").unwrap().find(&haystack), None);
+        assert_eq!(Pattern::new("89 ?").unwrap().find(&haystack), None);
+        assert_eq!(Pattern::new("? 48").unwrap().find(&haystack), Some(6));
+    }
+
+    #[test]
+    fn find_all_overlapping() {
+        let haystack = [1, 1, 1, 2, 1, 1];
+        assert_eq!(
+            Pattern::new("01 01")
+                .unwrap()
+                .find_all(&haystack)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 4]
+        );
+        assert_eq!(
+            Pattern::new("01 ? 01")
+                .unwrap()
+                .find_all(&haystack)
+                .collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+    }
+
+    #[test]
+    fn matches_naive_scan() {
+        let haystack = noise(4096);
+        for pattern in [
+            "00 01 02",
+            "03 ? 03 03",
+            "? ? 01",
+            "02 ? ?",
+            "01 02 03 00 01 02",
+            "? 00 ? 00 ? 00",
+            "?",
+            "0? ?1 0?",
+            "?3 ? 0?",
+        ] {
+            let pattern = Pattern::new(pattern).unwrap();
+            assert_eq!(
+                pattern.find_all(&haystack).collect::<Vec<_>>(),
+                naive(&haystack, &pattern),
+                "{pattern:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn nibbles() {
+        let haystack = [0x48, 0x8B, 0x4C, 0x8B, 0x49, 0x8D];
+        assert_eq!(
+            Pattern::new("4? 8B")
+                .unwrap()
+                .find_all(&haystack)
+                .collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(Pattern::new("4? ?D").unwrap().find(&haystack), Some(4));
+    }
+
+    #[test]
+    fn code() {
+        let haystack = [0x90, 0x48, 0x8B, 0x05, 0xE8];
+        assert_eq!(
+            Pattern::from_code(r"\x48\x8B\x00\xE8", "xx?x")
+                .unwrap()
+                .find(&haystack),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert!(matches!(Pattern::new(""), Err(PatternSearchError::Empty)));
+        assert_eq!(Pattern::new("01 02").unwrap().find(&[1]), None);
+    }
+
+    #[test]
+    fn signature_set() {
+        let haystack = noise(4096);
+        let patterns = [
+            ("pair", "01 02 03"),
+            ("common", "00 01 ? 03"),
+            ("byte", "? 02 ? 02"),
+            ("wildcards", "? ?"),
+            ("missing", "05 06"),
+        ];
+
+        let mut set = SignatureSet::new();
+        for (name, pattern) in patterns {
+            set.add(name, pattern).unwrap();
+        }
+        let matches = set.scan(&haystack);
+
+        for (name, pattern) in patterns {
+            let pattern = Pattern::new(pattern).unwrap();
+            assert_eq!(
+                matches.get(name).unwrap(),
+                naive(&haystack, &pattern),
+                "{name}"
+            );
+        }
+        assert_eq!(matches.missing().collect::<Vec<_>>(), vec!["missing"]);
+        assert!(
+            matches
+                .ambiguous()
+                .all(|(name, matches)| name != "missing" && matches.len() > 1)
+        );
+        assert!(matches.ambiguous().any(|(name, _)| name == "wildcards"));
+        assert_eq!(matches.get("nope"), None);
+    }
+
+    #[test]
+    fn signature_set_chunks() {
+        // big enough to be split across threads, with matches straddling every chunk boundary
+        let mut haystack = vec![0x90; MIN_CHUNK_SIZE * 4];
+        let mut expected = Vec::new();
+        for i in 1..8 {
+            let start = i * MIN_CHUNK_SIZE / 2 - 2;
+            haystack[start..start + 4].copy_from_slice(&[0xE8, 0x11, 0x22, 0x33]);
+            expected.push(start);
+        }
+
+        let mut set = SignatureSet::new();
+        set.add("call", "E8 ? 22 33").unwrap();
+        assert_eq!(set.scan(&haystack).get("call").unwrap(), expected);
+    }
+
+    #[test]
+    fn duplicate_signature() {
+        let mut set = SignatureSet::new();
+        set.add("a", "01").unwrap();
+        assert!(matches!(
+            set.add("a", "02"),
+            Err(PatternSearchError::DuplicateSignature(name)) if name == "a"
+        ));
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn sections() {
+        let image = pe::test_image(&[
+            (".text", &[0x90, 0xE8, 0x00, 0xCC, 0xE8, 0x00]),
+            (".rdata", &[0xE8, 0x00]),
+        ]);
+        let pattern = Pattern::new("E8 00").unwrap();
+
+        assert_eq!(
+            find_in_sections(&image, &[".text"], &pattern).unwrap(),
+            vec![0x101, 0x104]
+        );
+        assert_eq!(
+            find_in_sections(&image, &[".rdata"], &pattern).unwrap(),
+            vec![0x200]
+        );
+        assert_eq!(
+            find_in_sections(&image, &[], &pattern).unwrap(),
+            vec![0x101, 0x104, 0x200]
+        );
+        assert!(matches!(
+            find_in_sections(&image, &[".data"], &pattern),
+            Err(PatternSearchError::SectionNotFound(name)) if name == ".data"
+        ));
+    }
+}
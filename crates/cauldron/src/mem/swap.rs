@@ -0,0 +1,448 @@
+//! Hooks made by swapping a pointer, vtable entries and import address table entries.
+//!
+//! Every hook returns a guard holding the original pointer, which puts it back when dropped.
+//! Guards only restore pointers that haven't been swapped again since, so hooks stacked on the
+//! same pointer have to be dropped in reverse order.
+
+use crate::mem::module::Module;
+use crate::mem::patch::{Memory, PatchError, ProcessMemory};
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use thiserror::Error;
+
+const POINTER_SIZE: usize = size_of::<usize>();
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum SwapError {
+    #[error("{module} doesn't import {name} from {dll}")]
+    ImportNotFound {
+        module: &'static str,
+        dll: String,
+        name: String,
+    },
+    #[error(transparent)]
+    Patch(#[from] PatchError),
+}
+
+fn read_pointer<M: Memory + ?Sized>(memory: &M, address: usize) -> Result<usize, PatchError> {
+    let mut bytes = [0; POINTER_SIZE];
+    memory.read(address, &mut bytes)?;
+    Ok(usize::from_le_bytes(bytes))
+}
+
+fn write_pointer<M: Memory + ?Sized>(
+    memory: &mut M,
+    address: usize,
+    pointer: usize,
+) -> Result<(), PatchError> {
+    memory.write(address, &pointer.to_le_bytes())
+}
+
+/// Write `original` back to `slot`, if it still holds `replacement`.
+fn put_back<M: Memory + ?Sized>(
+    memory: &mut M,
+    slot: usize,
+    replacement: usize,
+    original: usize,
+) -> Result<(), PatchError> {
+    let found = read_pointer(memory, slot)?;
+    if found != replacement {
+        return Err(PatchError::Mismatch {
+            address: slot,
+            expected: replacement.to_le_bytes().to_vec(),
+            found: found.to_le_bytes().to_vec(),
+        });
+    }
+    write_pointer(memory, slot, original)
+}
+
+/// A pointer replaced in memory, which is put back when the guard is dropped.
+#[must_use = "the original pointer is put back as soon as the guard is dropped"]
+pub struct PointerSwap<M: Memory = ProcessMemory> {
+    memory: M,
+    slot: usize,
+    original: usize,
+    replacement: usize,
+    finished: bool,
+}
+
+impl<M: Memory> PointerSwap<M> {
+    /// Replace the pointer at `slot` with `replacement`.
+    pub fn new(mut memory: M, slot: usize, replacement: *const c_void) -> Result<Self, PatchError> {
+        let original = read_pointer(&memory, slot)?;
+        write_pointer(&mut memory, slot, replacement as usize)?;
+        Ok(PointerSwap {
+            memory,
+            slot,
+            original,
+            replacement: replacement as usize,
+            finished: false,
+        })
+    }
+
+    /// Where the swapped pointer is.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// The pointer that was replaced, what a detour calls to run the original.
+    pub fn original(&self) -> *const c_void {
+        self.original as *const c_void
+    }
+
+    /// Put the original pointer back now, fails if it's been swapped again since.
+    pub fn restore(mut self) -> Result<(), PatchError> {
+        self.finish()
+    }
+
+    /// Keep the replacement for good, returns the original pointer.
+    pub fn leak(mut self) -> *const c_void {
+        self.finished = true;
+        self.original()
+    }
+
+    fn finish(&mut self) -> Result<(), PatchError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        put_back(&mut self.memory, self.slot, self.replacement, self.original)
+    }
+}
+
+impl<M: Memory> Drop for PointerSwap<M> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Failed to restore the pointer at {:#X}: {e}", self.slot);
+        }
+    }
+}
+
+/// Replace entry `index` of `vtable`, for every object of the class it belongs to.
+///
+/// # Safety
+/// `vtable` must have more than `index` entries, and `detour` must have the same signature as the
+/// entry it replaces.
+pub unsafe fn swap_vtable_entry(
+    vtable: *const *const c_void,
+    index: usize,
+    detour: *const c_void,
+) -> Result<PointerSwap, PatchError> {
+    let slot = vtable as usize + index * POINTER_SIZE;
+    PointerSwap::new(unsafe { ProcessMemory::new() }, slot, detour)
+}
+
+/// Redirect every call `module` makes to `name` from `dll`, through its import address table.
+/// Dll names are compared case-insensitively.
+///
+/// # Safety
+/// `detour` must have the same signature as the imported function.
+pub unsafe fn swap_import(
+    module: &Module,
+    dll: &str,
+    name: &str,
+    detour: *const c_void,
+) -> Result<PointerSwap, SwapError> {
+    let slot = module
+        .import(dll, name)
+        .ok_or_else(|| SwapError::ImportNotFound {
+            module: module.name(),
+            dll: dll.to_owned(),
+            name: name.to_owned(),
+        })?;
+    // the import address table is part of the module
+    Ok(PointerSwap::new(
+        unsafe { ProcessMemory::new() },
+        slot.get(),
+        detour,
+    )?)
+}
+
+/// A copy of an object's vtable, which only that object uses, so its entries can be swapped
+/// without affecting any other object of the same class.
+///
+/// The object is pointed back at its original vtable when the guard is dropped.
+#[must_use = "the object's original vtable is put back as soon as the guard is dropped"]
+pub struct InstanceVtable<M: Memory = ProcessMemory> {
+    memory: M,
+    object: usize,
+    original: usize,
+    /// The entry before the vtable, then the vtable's entries.
+    entries: Box<[AtomicUsize]>,
+    originals: Box<[usize]>,
+    finished: bool,
+}
+
+impl InstanceVtable {
+    /// Give `object` its own copy of the first `len` entries of its vtable.
+    ///
+    /// # Safety
+    /// `object` must start with a vtable pointer, to a vtable with at least `len` entries. Only
+    /// entries below `len` may be called on the object while it uses the copy, and the guard must
+    /// be dropped before the object is.
+    pub unsafe fn new(object: *mut c_void, len: usize) -> Result<Self, PatchError> {
+        unsafe { Self::with_memory(ProcessMemory::new(), object as usize, len) }
+    }
+}
+
+impl<M: Memory> InstanceVtable<M> {
+    /// [InstanceVtable::new], reading and writing the object through `memory`.
+    pub fn with_memory(mut memory: M, object: usize, len: usize) -> Result<Self, PatchError> {
+        let original = read_pointer(&memory, object)?;
+
+        // with msvc the entry before the vtable points at the class's rtti, keep it for casts
+        let start = original.wrapping_sub(POINTER_SIZE);
+        let originals = (0..=len)
+            .map(|i| read_pointer(&memory, start + i * POINTER_SIZE))
+            .collect::<Result<Box<[usize]>, _>>()?;
+        let entries: Box<[AtomicUsize]> = originals.iter().copied().map(AtomicUsize::new).collect();
+
+        write_pointer(&mut memory, object, entries[1..].as_ptr() as usize)?;
+        Ok(InstanceVtable {
+            memory,
+            object,
+            original,
+            entries,
+            originals,
+            finished: false,
+        })
+    }
+
+    /// The number of entries copied.
+    pub fn len(&self) -> usize {
+        self.originals.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The object's original vtable.
+    pub fn original_vtable(&self) -> *const *const c_void {
+        self.original as *const *const c_void
+    }
+
+    /// Entry `index` of the original vtable, `None` if it wasn't copied.
+    pub fn original(&self, index: usize) -> Option<*const c_void> {
+        (index < self.len()).then(|| self.originals[index + 1] as *const c_void)
+    }
+
+    /// Replace entry `index` for this object only, returns the entry it replaced, `None` if it
+    /// wasn't copied.
+    ///
+    /// # Safety
+    /// `detour` must have the same signature as the entry it replaces.
+    pub unsafe fn swap(&self, index: usize, detour: *const c_void) -> Option<*const c_void> {
+        let entry = self.entries.get(index.checked_add(1)?)?;
+        Some(entry.swap(detour as usize, Ordering::AcqRel) as *const c_void)
+    }
+
+    /// Point the object back at its original vtable now, fails if its vtable pointer has been
+    /// changed since.
+    pub fn restore(mut self) -> Result<(), PatchError> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<(), PatchError> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        let copy = self.entries[1..].as_ptr() as usize;
+        let result = put_back(&mut self.memory, self.object, copy, self.original);
+        if result.is_err() {
+            // the object might still use the copy
+            std::mem::forget(std::mem::take(&mut self.entries));
+        }
+        result
+    }
+}
+
+impl<M: Memory> Drop for InstanceVtable<M> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!(
+                "Failed to restore the vtable of the object at {:#X}: {e}",
+                self.object
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::pe::{TestSection, test_image_with, test_imports};
+
+    /// Plain reads and writes, for memory that's always writable.
+    struct Heap;
+
+    impl Memory for Heap {
+        fn read(&self, address: usize, buf: &mut [u8]) -> Result<(), PatchError> {
+            unsafe {
+                std::ptr::copy_nonoverlapping(address as *const u8, buf.as_mut_ptr(), buf.len())
+            };
+            Ok(())
+        }
+
+        fn write(&mut self, address: usize, data: &[u8]) -> Result<(), PatchError> {
+            unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), address as *mut u8, data.len()) };
+            Ok(())
+        }
+    }
+
+    type Method = extern "C" fn(*const Object) -> u32;
+
+    #[repr(C)]
+    struct Object {
+        vtable: *const Method,
+    }
+
+    extern "C" fn one(_: *const Object) -> u32 {
+        1
+    }
+
+    extern "C" fn two(_: *const Object) -> u32 {
+        2
+    }
+
+    extern "C" fn detour(_: *const Object) -> u32 {
+        3
+    }
+
+    /// The rtti pointer, then `one` and `two`.
+    fn vtable() -> Box<[usize; 3]> {
+        Box::new([0xAAAA, one as Method as usize, two as Method as usize])
+    }
+
+    fn call(object: &Object, index: usize) -> u32 {
+        unsafe { (*object.vtable.add(index))(object) }
+    }
+
+    #[test]
+    fn pointer_swap() {
+        let mut slot = Box::new(one as Method as usize);
+        let address = &mut *slot as *mut usize as usize;
+
+        let swap = PointerSwap::new(Heap, address, detour as *const c_void).unwrap();
+        assert_eq!(swap.original(), one as *const c_void);
+        assert_eq!(
+            unsafe { *(address as *const usize) },
+            detour as Method as usize
+        );
+        drop(swap);
+        assert_eq!(
+            unsafe { *(address as *const usize) },
+            one as Method as usize
+        );
+
+        let swap = PointerSwap::new(Heap, address, detour as *const c_void).unwrap();
+        assert_eq!(swap.leak(), one as *const c_void);
+        assert_eq!(
+            unsafe { *(address as *const usize) },
+            detour as Method as usize
+        );
+        *slot = 0;
+    }
+
+    #[test]
+    fn stacked_swaps_restore_in_reverse() {
+        let mut slot = Box::new(one as Method as usize);
+        let address = &mut *slot as *mut usize as usize;
+        let read = || unsafe { *(address as *const usize) };
+
+        let first = PointerSwap::new(Heap, address, two as *const c_void).unwrap();
+        let second = PointerSwap::new(Heap, address, detour as *const c_void).unwrap();
+        assert_eq!(second.original(), two as *const c_void);
+
+        // the second swap is still in place
+        assert!(matches!(first.restore(), Err(PatchError::Mismatch { .. })));
+        assert_eq!(read(), detour as Method as usize);
+
+        second.restore().unwrap();
+        assert_eq!(read(), two as Method as usize);
+    }
+
+    #[test]
+    fn instance_vtable() {
+        let vtable = vtable();
+        let mut object = Object {
+            vtable: vtable[1..].as_ptr() as *const Method,
+        };
+        let other = Object {
+            vtable: object.vtable,
+        };
+        // the swap writes to the object, so it's only used through this pointer from here on
+        let object = std::ptr::addr_of_mut!(object);
+        let vtable_of = || unsafe { (*object).vtable };
+        let call_object = |index| call(unsafe { &*object }, index);
+
+        let copy = InstanceVtable::with_memory(Heap, object as usize, 2).unwrap();
+        assert_eq!(copy.len(), 2);
+        assert_eq!(copy.original_vtable(), other.vtable as *const *const c_void);
+        assert_ne!(vtable_of(), other.vtable);
+        assert_eq!(unsafe { *(vtable_of() as *const usize).sub(1) }, 0xAAAA);
+
+        let replaced = unsafe { copy.swap(1, detour as *const c_void) };
+        assert_eq!(replaced, Some(two as *const c_void));
+        assert_eq!(unsafe { copy.swap(2, detour as *const c_void) }, None);
+        assert_eq!(copy.original(1), Some(two as *const c_void));
+        assert_eq!(copy.original(2), None);
+
+        assert_eq!(call_object(0), 1);
+        assert_eq!(call_object(1), 3);
+        assert_eq!(call(&other, 1), 2);
+
+        copy.restore().unwrap();
+        assert_eq!(vtable_of(), other.vtable);
+        assert_eq!(call_object(1), 2);
+    }
+
+    #[test]
+    fn instance_vtable_restored_on_drop() {
+        let vtable = vtable();
+        let mut object = Object {
+            vtable: vtable[1..].as_ptr() as *const Method,
+        };
+        let original = object.vtable;
+        let object = std::ptr::addr_of_mut!(object);
+
+        let copy = InstanceVtable::with_memory(Heap, object as usize, 2).unwrap();
+        unsafe { copy.swap(0, detour as *const c_void) };
+        assert_eq!(call(unsafe { &*object }, 0), 3);
+        drop(copy);
+        assert_eq!(unsafe { (*object).vtable }, original);
+        assert_eq!(call(unsafe { &*object }, 0), 1);
+    }
+
+    #[test]
+    fn import_slot() {
+        let image = test_image_with(&[TestSection {
+            name: ".idata",
+            directory: Some(1),
+            data: Box::new(|address| test_imports(address, &[("KERNEL32.dll", &["Sleep"])])),
+        }])
+        .leak();
+        let module =
+            unsafe { Module::new("test.exe", image.as_ptr() as usize, image.len() as u32) };
+
+        let slot = module.import("KERNEL32.dll", "Sleep").unwrap();
+        let swap = PointerSwap::new(Heap, slot.get(), detour as *const c_void).unwrap();
+        assert_eq!(
+            unsafe { *slot.as_ptr::<usize>() },
+            detour as Method as usize
+        );
+        drop(swap);
+        assert_ne!(
+            unsafe { *slot.as_ptr::<usize>() },
+            detour as Method as usize
+        );
+
+        let error = unsafe { swap_import(&module, "kernel32.dll", "Beep", std::ptr::null()) };
+        assert_eq!(
+            error.err().unwrap().to_string(),
+            "test.exe doesn't import Beep from kernel32.dll"
+        );
+    }
+}
@@ -319,7 +319,7 @@ impl From<CauldronModInfo> for SafeCauldronModInfo {
                 unsafe {
                     std::slice::from_raw_parts(value.depends, value.depends_len as usize)
                         .iter()
-                        .map(|dep| SafeCauldronModDependency::from(dep))
+                        .map(SafeCauldronModDependency::from)
                         .collect()
                 }
             } else {
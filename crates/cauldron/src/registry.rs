@@ -0,0 +1,322 @@
+//! Type descriptors for registry entries, see [CauldronApi::register_typed](crate::CauldronApi::register_typed).
+//!
+//! A descriptor is a signature string like `extern "C" fn(u32) -> *mut c_void`, built the same
+//! way in every mod, so the loader can tell when two mods disagree on what an entry is. Structs
+//! only match by name, size and alignment, see [registry_type!](crate::registry_type).
+//!
+//! Entries can be listed with [CauldronApi::namespaces](crate::CauldronApi::namespaces),
+//! [CauldronApi::names](crate::CauldronApi::names) and [CauldronApi::glob](crate::CauldronApi::glob).
+
+use std::ffi::{c_char, c_void};
+
+/// A type with a descriptor, anything registry entries can point to.
+pub trait RegistryType {
+    fn descriptor() -> String;
+}
+
+/// A pointer-sized [RegistryType] that can be a registry entry, i.e. raw and function pointers.
+///
+/// # Safety
+/// `Self` must be a pointer that's valid to transmute from and to `*const c_void`.
+pub unsafe trait RegistryValue: RegistryType + Copy {
+    fn into_ptr(self) -> *const c_void {
+        const { assert!(size_of::<Self>() == size_of::<*const c_void>()) };
+        unsafe { std::mem::transmute_copy(&self) }
+    }
+
+    /// # Safety
+    /// `ptr` must be a valid `Self`.
+    unsafe fn from_ptr(ptr: *const c_void) -> Self {
+        const { assert!(size_of::<Self>() == size_of::<*const c_void>()) };
+        unsafe { std::mem::transmute_copy(&ptr) }
+    }
+}
+
+/// Implement [RegistryType] for a struct, described by its name and layout, e.g.
+/// `Importer[size=0x28, align=8]`.
+#[macro_export]
+macro_rules! registry_type {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl $crate::registry::RegistryType for $ty {
+                fn descriptor() -> String {
+                    format!(
+                        "{}[size={:#X}, align={}]",
+                        stringify!($ty),
+                        ::core::mem::size_of::<$ty>(),
+                        ::core::mem::align_of::<$ty>()
+                    )
+                }
+            }
+        )+
+    };
+}
+
+macro_rules! primitive {
+    ($($ty:ty),+) => {
+        $(
+            impl RegistryType for $ty {
+                fn descriptor() -> String {
+                    String::from(stringify!($ty))
+                }
+            }
+        )+
+    };
+}
+
+primitive!(
+    (),
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    c_void
+);
+
+impl<T: RegistryType + ?Sized> RegistryType for *const T {
+    fn descriptor() -> String {
+        format!("*const {}", T::descriptor())
+    }
+}
+
+impl<T: RegistryType + ?Sized> RegistryType for *mut T {
+    fn descriptor() -> String {
+        format!("*mut {}", T::descriptor())
+    }
+}
+
+unsafe impl<T: RegistryType> RegistryValue for *const T {}
+unsafe impl<T: RegistryType> RegistryValue for *mut T {}
+
+/// `prefix fn(args) -> ret`, without the return type if it's `()`.
+fn function(prefix: &str, args: &[String], ret: String) -> String {
+    let args = args.join(", ");
+    if ret == "()" {
+        format!("{prefix} fn({args})")
+    } else {
+        format!("{prefix} fn({args}) -> {ret}")
+    }
+}
+
+macro_rules! function_pointer {
+    ($($arg:ident),*) => {
+        function_pointer!(@abi "C", $($arg),*);
+        function_pointer!(@abi "C-unwind", $($arg),*);
+        function_pointer!(@abi "system", $($arg),*);
+    };
+    (@abi $abi:literal, $($arg:ident),*) => {
+        function_pointer!(@impl (extern $abi fn($($arg),*) -> R), concat!("extern \"", $abi, "\""), $($arg),*);
+        function_pointer!(@impl (unsafe extern $abi fn($($arg),*) -> R), concat!("unsafe extern \"", $abi, "\""), $($arg),*);
+    };
+    (@impl ($($fn:tt)*), $prefix:expr, $($arg:ident),*) => {
+        impl<R: RegistryType, $($arg: RegistryType),*> RegistryType for $($fn)* {
+            fn descriptor() -> String {
+                function($prefix, &[$($arg::descriptor()),*], R::descriptor())
+            }
+        }
+
+        unsafe impl<R: RegistryType, $($arg: RegistryType),*> RegistryValue for $($fn)* {}
+    };
+}
+
+function_pointer!();
+function_pointer!(A);
+function_pointer!(A, B);
+function_pointer!(A, B, C);
+function_pointer!(A, B, C, D);
+function_pointer!(A, B, C, D, E);
+function_pointer!(A, B, C, D, E, F);
+function_pointer!(A, B, C, D, E, F, G);
+function_pointer!(A, B, C, D, E, F, G, H);
+
+/// Called by the loader with every listed namespace, and the context given to the listing call.
+pub type NamespaceCallback = extern "C" fn(context: *mut c_void, namespace: *const c_char);
+
+/// Called by the loader with every listed entry, and the context given to the listing call.
+pub type EntryCallback = extern "C" fn(
+    context: *mut c_void,
+    namespace: *const c_char,
+    name: *const c_char,
+    ptr: *const c_void,
+);
+
+/// How what a name is queried as changed, see [CauldronApi::subscribe](crate::CauldronApi::subscribe).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum RegistryChange {
+    /// Nothing was registered under the name before.
+    Registered,
+    /// Replaced by another entry, or the entry it replaced came back.
+    Replaced,
+    /// Nothing's registered under the name anymore.
+    Removed,
+}
+
+/// Called by the loader with every change to a subscribed name, and the context given to the
+/// subscription. `ptr` is what the name is queried as now, null if it was removed.
+pub type ChangeCallback = extern "C" fn(
+    context: *mut c_void,
+    change: RegistryChange,
+    namespace: *const c_char,
+    name: *const c_char,
+    ptr: *const c_void,
+);
+
+/// An entry listed from the registry.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RegistryEntry {
+    pub namespace: String,
+    pub name: String,
+    pub ptr: *const c_void,
+}
+
+impl RegistryEntry {
+    /// `namespace/name`, what [glob] patterns match against.
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.namespace, self.name)
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` matches any run of characters, `/` included, `?`
+/// matches any one character and `\` makes the next character literal.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // where to resume if the text doesn't match after the last `*`
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some('\\') if pattern.get(p + 1) == Some(&text[t]) => {
+                p += 2;
+                t += 1;
+                continue;
+            }
+            Some(c) if *c != '\\' && *c == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        match star {
+            Some((after, start)) => {
+                p = after;
+                t = start + 1;
+                star = Some((after, start + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// A [glob] pattern matching everything starting with `prefix`.
+pub fn prefix_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('*');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Importer {
+        _vtable: *const c_void,
+        _count: u32,
+    }
+
+    crate::registry_type!(Importer);
+
+    #[test]
+    fn descriptors() {
+        assert_eq!(<*mut c_void>::descriptor(), "*mut c_void");
+        assert_eq!(
+            <extern "C" fn(u32) -> *mut c_void>::descriptor(),
+            "extern \"C\" fn(u32) -> *mut c_void"
+        );
+        assert_eq!(
+            <unsafe extern "system" fn(*const u8, usize)>::descriptor(),
+            "unsafe extern \"system\" fn(*const u8, usize)"
+        );
+        assert_eq!(
+            <extern "C-unwind" fn() -> *const Importer>::descriptor(),
+            "extern \"C-unwind\" fn() -> *const Importer[size=0x10, align=8]"
+        );
+        assert_ne!(
+            <extern "C" fn(u32)>::descriptor(),
+            <extern "C" fn(u64)>::descriptor()
+        );
+    }
+
+    #[test]
+    fn pointers_round_trip() {
+        extern "C" fn double(x: u32) -> u32 {
+            x * 2
+        }
+
+        let ptr = (double as extern "C" fn(u32) -> u32).into_ptr();
+        let back = unsafe { <extern "C" fn(u32) -> u32>::from_ptr(ptr) };
+        assert_eq!(back(4), 8);
+
+        let value = 5u64;
+        let ptr = (&value as *const u64).into_ptr();
+        assert_eq!(unsafe { *<*const u64>::from_ptr(ptr) }, 5);
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob(
+            "libdecima/game/functions/*Player*",
+            "libdecima/game/functions/Game/Player::Update"
+        ));
+        assert!(glob("*", ""));
+        assert!(glob("a?c", "abc"));
+        assert!(!glob("a?c", "ac"));
+        assert!(glob("*/*", "a/b/c"));
+        assert!(!glob("a*b", "acbc"));
+        assert!(glob("a*b*c", "aXbYbZc"));
+        assert!(!glob("abc", "abcd"));
+        assert!(glob("a\\*", "a*"));
+        assert!(!glob("a\\*", "ab"));
+    }
+
+    #[test]
+    fn prefixes() {
+        let pattern = prefix_pattern("ns/what?*");
+        assert_eq!(pattern, "ns/what\\?\\**");
+        assert!(glob(&pattern, "ns/what?*else"));
+        assert!(!glob(&pattern, "ns/whatX*else"));
+    }
+}
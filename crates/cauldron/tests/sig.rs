@@ -0,0 +1,6 @@
+//! `sig!` rejects invalid signatures at compile time.
+
+#[test]
+fn invalid_signatures() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
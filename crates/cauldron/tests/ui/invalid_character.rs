@@ -0,0 +1,5 @@
+use cauldron::mem::pattern::PatternByte;
+
+const SIGNATURE: &[PatternByte] = cauldron::sig!("48 8G");
+
+fn main() {}
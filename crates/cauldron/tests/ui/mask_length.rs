@@ -0,0 +1,5 @@
+use cauldron::mem::pattern::PatternByte;
+
+const SIGNATURE: &[PatternByte] = cauldron::sig!(b"\x48\x8B", "x");
+
+fn main() {}
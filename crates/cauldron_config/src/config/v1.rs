@@ -13,6 +13,10 @@ pub struct CauldronConfig {
     /// Logging configuration.
     pub logging: CauldronLoggingConfig,
 
+    /// Mod loading configuration.
+    #[serde(default)]
+    pub loader: CauldronLoaderConfig,
+
     /// Patch configuration.
     pub patches: CauldronPatchConfig,
 
@@ -50,6 +54,15 @@ pub struct CauldronLoggingConfig {
     pub file_level: LogLevel,
 }
 
+#[derive(Serialize, Deserialize, DocumentedFields, Default)]
+pub struct CauldronLoaderConfig {
+    /// Stop the game if any mod fails to resolve, instead of skipping it and its dependents.
+    ///
+    /// Type: Boolean (true, false)
+    /// Default: false
+    pub strict: bool,
+}
+
 #[derive(Serialize, Deserialize, DocumentedFields)]
 pub struct CauldronPatchConfig {
     /// Disables game telemetry from being sent to Guerrilla Games and Sony.
@@ -99,6 +112,7 @@ impl Default for CauldronConfig {
                 file_path: "cauldron/cauldron.log".into(),
                 file_level: LogLevel::Info,
             },
+            loader: CauldronLoaderConfig { strict: false },
             patches: CauldronPatchConfig {
                 disable_telemetry: true,
                 disable_crash_reporter: true,
@@ -62,6 +62,30 @@ impl Game {
         }
         .into()
     }
+
+    /// The game [Game::code] is for.
+    pub fn from_code(code: &str) -> Option<Game> {
+        match code {
+            "hzd" => Some(Game::HorizonZeroDawn),
+            "hzdr" => Some(Game::HorizonZeroDawnRemastered),
+            "hfw" => Some(Game::HorizonForbiddenWest),
+            "ds" => Some(Game::DeathStranding),
+            "dsdc" => Some(Game::DeathStrandingDirectorsCut),
+            _ => None,
+        }
+    }
+
+    /// The game whose executable is called `file_name`.
+    pub fn from_executable(file_name: &str) -> Option<Game> {
+        match file_name {
+            "HorizonZeroDawn.exe" => Some(Game::HorizonZeroDawn),
+            "HorizonZeroDawnRemastered.exe" => Some(Game::HorizonZeroDawnRemastered),
+            "HorizonForbiddenWest.exe" => Some(Game::HorizonForbiddenWest),
+            "ds.exe" => Some(Game::DeathStrandingDirectorsCut),
+            // todo(py): differentiate between ds and dsdc, they both use the same "ds.exe" filename. (¬_¬")
+            _ => None,
+        }
+    }
 }
 
 /// Find all installations of [Game] on the system.
@@ -75,20 +99,14 @@ pub fn find_installations() -> Vec<Installation> {
 
 /// Find the current [Game] and [Version].
 pub fn detect_active() -> Option<(Game, Version)> {
-    let game: Option<Game> = match current_exe()
-        .unwrap()
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-    {
-        "HorizonZeroDawn.exe" => Some(Game::HorizonZeroDawn),
-        "HorizonZeroDawnRemastered.exe" => Some(Game::HorizonZeroDawnRemastered),
-        "HorizonForbiddenWest.exe" => Some(Game::HorizonForbiddenWest),
-        "ds.exe" => Some(Game::DeathStrandingDirectorsCut),
-        // todo(py): differentiate between ds and dsdc, they both use the same "ds.exe" filename. (¬_¬")
-        _ => None,
-    };
+    let game = Game::from_executable(
+        current_exe()
+            .unwrap()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap(),
+    );
 
     let Some(game) = game else {
         return None;
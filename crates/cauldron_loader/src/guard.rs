@@ -0,0 +1,131 @@
+//! Fault isolation for calls into mod code.
+
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+/// A fault raised while running mod code.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum ModFault {
+    /// A panic unwound out of the mod, with its message if it had one.
+    Panic(String),
+    /// A structured exception was raised, e.g. an access violation.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Exception { code: u32, address: usize },
+}
+
+impl Display for ModFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModFault::Panic(message) => write!(f, "panicked: {message}"),
+            ModFault::Exception { code, address } => {
+                let name = match code {
+                    0xC0000005 => "access violation",
+                    0xC000001D => "illegal instruction",
+                    0xC0000094 => "integer divide by zero",
+                    0xC00000FD => "stack overflow",
+                    0x80000003 => "breakpoint",
+                    // how panics from another Rust runtime (i.e. another dll) show up
+                    0xE06D7363 => "c++ exception",
+                    _ => "exception",
+                };
+                write!(f, "raised {name} ({code:#010X}) at {address:#X}")
+            }
+        }
+    }
+}
+
+/// Call into mod code, turning panics and structured exceptions into a [ModFault].
+///
+/// Panics can only reach the loader if the mod function is `extern "C-unwind"`,
+/// unwinding out of an `extern "C"` function aborts inside the mod.
+pub(crate) fn guarded<R>(f: impl FnOnce() -> R) -> Result<R, ModFault> {
+    match catch_unwind(AssertUnwindSafe(|| seh_guarded(f))) {
+        Ok(result) => result,
+        Err(payload) => Err(ModFault::Panic(panic_message(payload.as_ref()))),
+    }
+}
+
+// This has to sit closest to the mod code, a panic from a mod's own runtime is a foreign
+// exception to `catch_unwind` and would abort instead of being caught.
+#[cfg(windows)]
+fn seh_guarded<R>(f: impl FnOnce() -> R) -> Result<R, ModFault> {
+    let mut f = Some(f);
+    microseh::try_seh(|| (f.take().unwrap())()).map_err(|e| ModFault::Exception {
+        code: e.raw_code(),
+        address: e.address() as usize,
+    })
+}
+
+#[cfg(not(windows))]
+fn seh_guarded<R>(f: impl FnOnce() -> R) -> Result<R, ModFault> {
+    Ok(f())
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C-unwind" fn panicking_load() -> bool {
+        panic!("failed to find signature");
+    }
+
+    extern "C-unwind" fn formatted_panic() -> bool {
+        let name = "libfoo";
+        panic!("{name} exploded");
+    }
+
+    extern "C-unwind" fn successful_load() -> bool {
+        true
+    }
+
+    #[test]
+    fn ok() {
+        assert_eq!(guarded(|| successful_load()), Ok(true));
+    }
+
+    #[test]
+    fn static_panic() {
+        assert_eq!(
+            guarded(|| panicking_load()),
+            Err(ModFault::Panic("failed to find signature".into()))
+        );
+    }
+
+    #[test]
+    fn formatted_panic_message() {
+        assert_eq!(
+            guarded(|| formatted_panic()),
+            Err(ModFault::Panic("libfoo exploded".into()))
+        );
+    }
+
+    #[test]
+    fn non_string_payload() {
+        let fault = guarded(|| std::panic::panic_any(42u32)).unwrap_err();
+        assert_eq!(fault, ModFault::Panic("Box<dyn Any>".into()));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(ModFault::Panic("oops".into()).to_string(), "panicked: oops");
+        assert_eq!(
+            ModFault::Exception {
+                code: 0xC0000005,
+                address: 0x7FF612340000,
+            }
+            .to_string(),
+            "raised access violation (0xC0000005) at 0x7FF612340000"
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! Loader-owned function hooks, shared between mods.
+//!
+//! Every hooked target has a single detour owned by the loader. Mods add hooks to the target's
+//! chain, which runs from the highest priority hook down to the original function. Each hook
+//! gets a slot holding the next function in the chain, the slot stays valid for as long as the
+//! hook is installed but its contents change whenever the chain is rebuilt.
+//!
+//! Game threads may be calling through a chain while it's rebuilt, so replaced detours are kept
+//! alive, a thread that read the old trampoline from a slot can still call it.
+
+use once_cell::sync::Lazy;
+use retour::RawDetour;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+pub(crate) static HOOKS: Lazy<Mutex<HookManager>> =
+    Lazy::new(|| Mutex::new(HookManager::default()));
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum HookError {
+    AlreadyHooked,
+    NotHooked,
+    Detour(String),
+}
+
+impl std::fmt::Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HookError::AlreadyHooked => f.write_str("the mod has already hooked this target"),
+            HookError::NotHooked => f.write_str("the mod hasn't hooked this target"),
+            HookError::Detour(e) => write!(f, "detour error: {e}"),
+        }
+    }
+}
+
+struct Hook {
+    owner: String,
+    detour: *const c_void,
+    priority: i32,
+    enabled: bool,
+    /// The next function in the chain, handed to the owner when the hook is installed.
+    next: Box<AtomicPtr<c_void>>,
+}
+
+impl Hook {
+    fn link(&self, next: *const c_void) {
+        self.next.store(next as *mut c_void, Ordering::Release);
+    }
+}
+
+#[derive(Default)]
+struct HookChain {
+    /// Sorted by descending priority, then install order.
+    hooks: Vec<Hook>,
+    /// The active detour and the hook it jumps to.
+    detour: Option<(RawDetour, *const c_void)>,
+}
+
+#[derive(Default)]
+pub(crate) struct HookManager {
+    chains: HashMap<usize, HookChain>,
+    /// Disabled detours whose trampolines may still be in use, never freed.
+    retired: Vec<RawDetour>,
+}
+
+unsafe impl Send for HookManager {}
+unsafe impl Sync for HookManager {}
+
+impl HookManager {
+    /// Add a disabled hook to `target`'s chain, returns the hook's next function slot.
+    pub(crate) fn install(
+        &mut self,
+        owner: &str,
+        target: *const c_void,
+        detour: *const c_void,
+        priority: i32,
+    ) -> Result<*const *const c_void, HookError> {
+        let chain = self.chains.entry(target as usize).or_default();
+        if chain.hooks.iter().any(|h| h.owner == owner) {
+            return Err(HookError::AlreadyHooked);
+        }
+
+        if !chain.hooks.is_empty() {
+            let owners = chain
+                .hooks
+                .iter()
+                .map(|h| format!("{} (priority {})", h.owner, h.priority))
+                .collect::<Vec<_>>()
+                .join(", ");
+            log::warn!(
+                "{owner} hooked {target:#X?} with priority {priority}, which is already hooked by {owners}."
+            );
+        }
+
+        // the original function until the hook is enabled
+        let next = Box::new(AtomicPtr::new(target as *mut c_void));
+        let slot = next.as_ptr() as *const *const c_void;
+        let position = chain
+            .hooks
+            .iter()
+            .position(|h| h.priority < priority)
+            .unwrap_or(chain.hooks.len());
+        chain.hooks.insert(
+            position,
+            Hook {
+                owner: owner.to_owned(),
+                detour,
+                priority,
+                enabled: false,
+                next,
+            },
+        );
+
+        Ok(slot)
+    }
+
+    pub(crate) fn set_enabled(
+        &mut self,
+        owner: &str,
+        target: *const c_void,
+        enabled: bool,
+    ) -> Result<(), HookError> {
+        let chain = self
+            .chains
+            .get_mut(&(target as usize))
+            .ok_or(HookError::NotHooked)?;
+        let hook = chain
+            .hooks
+            .iter_mut()
+            .find(|h| h.owner == owner)
+            .ok_or(HookError::NotHooked)?;
+        hook.enabled = enabled;
+
+        unsafe { chain.rebuild(target, &mut self.retired) }
+    }
+
+    pub(crate) fn remove(&mut self, owner: &str, target: *const c_void) -> Result<(), HookError> {
+        let chain = self
+            .chains
+            .get_mut(&(target as usize))
+            .ok_or(HookError::NotHooked)?;
+        let position = chain
+            .hooks
+            .iter()
+            .position(|h| h.owner == owner)
+            .ok_or(HookError::NotHooked)?;
+        chain.hooks.remove(position);
+
+        let result = unsafe { chain.rebuild(target, &mut self.retired) };
+        if chain.hooks.is_empty() {
+            self.chains.remove(&(target as usize));
+        }
+        result
+    }
+
+    /// Remove every hook `owner` installed, returns the number of removed hooks.
+    pub(crate) fn remove_owned_by(&mut self, owner: &str) -> usize {
+        let targets: Vec<usize> = self
+            .chains
+            .iter()
+            .filter(|(_, chain)| chain.hooks.iter().any(|h| h.owner == owner))
+            .map(|(target, _)| *target)
+            .collect();
+
+        let mut removed = 0;
+        for target in targets {
+            match self.remove(owner, target as *const c_void) {
+                Ok(()) => removed += 1,
+                Err(e) => log::error!("Failed to remove {owner}'s hook on {target:#X}: {e}"),
+            }
+        }
+        removed
+    }
+}
+
+impl HookChain {
+    /// Point the detour at the first enabled hook and link every hook to the one after it.
+    ///
+    /// A replaced detour is disabled before its replacement is created, as both patch the same
+    /// bytes, but only moved to `retired` once no slot points at its trampoline anymore.
+    unsafe fn rebuild(
+        &mut self,
+        target: *const c_void,
+        retired: &mut Vec<RawDetour>,
+    ) -> Result<(), HookError> {
+        let enabled: Vec<usize> = (0..self.hooks.len())
+            .filter(|&i| self.hooks[i].enabled)
+            .collect();
+
+        let Some(&first) = enabled.first() else {
+            if let Some((detour, head)) = self.detour.take() {
+                if let Err(e) = unsafe { detour.disable() } {
+                    self.detour = Some((detour, head));
+                    return Err(HookError::Detour(e.to_string()));
+                }
+                retired.push(detour);
+            }
+            for hook in &self.hooks {
+                hook.link(target);
+            }
+            return Ok(());
+        };
+
+        let head = self.hooks[first].detour;
+        let mut replaced = None;
+        if self.detour.as_ref().map(|(_, h)| *h) != Some(head) {
+            // retour can't retarget a detour, so it's recreated whenever the head changes
+            if let Some((detour, old_head)) = self.detour.take() {
+                if let Err(e) = unsafe { detour.disable() } {
+                    self.detour = Some((detour, old_head));
+                    return Err(HookError::Detour(e.to_string()));
+                }
+                replaced = Some(detour);
+            }
+            let detour = unsafe { RawDetour::new(target as *const (), head as *const ()) }
+                .and_then(|detour| unsafe { detour.enable() }.map(|()| detour));
+            match detour {
+                Ok(detour) => self.detour = Some((detour, head)),
+                Err(e) => {
+                    // the target is unhooked now, but the old trampoline still runs the original
+                    for hook in &self.hooks {
+                        hook.link(target);
+                    }
+                    retired.extend(replaced);
+                    return Err(HookError::Detour(e.to_string()));
+                }
+            }
+        }
+
+        let trampoline = self.detour.as_ref().unwrap().0.trampoline() as *const () as *const c_void;
+        for (i, &index) in enabled.iter().enumerate() {
+            self.hooks[index].link(match enabled.get(i + 1) {
+                Some(&next) => self.hooks[next].detour,
+                None => trampoline,
+            });
+        }
+        for hook in self.hooks.iter().filter(|h| !h.enabled) {
+            hook.link(trampoline);
+        }
+        retired.extend(replaced);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hint::black_box;
+
+    type Fn = extern "C" fn(u64) -> u64;
+
+    #[inline(never)]
+    extern "C" fn original(x: u64) -> u64 {
+        // big enough for a detour's jump
+        let mut result = black_box(x);
+        for _ in 0..black_box(3) {
+            result = black_box(result.wrapping_mul(3).wrapping_add(1));
+        }
+        result
+    }
+
+    macro_rules! hook_fn {
+        ($name:ident, $slot:ident, $tag:expr) => {
+            static $slot: AtomicPtr<*const c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+            extern "C" fn $name(x: u64) -> u64 {
+                let slot = $slot.load(Ordering::SeqCst) as *mut *mut c_void;
+                let next = unsafe { AtomicPtr::from_ptr(slot) }.load(Ordering::Acquire);
+                let next: Fn = unsafe { std::mem::transmute(next) };
+                next(x * 10 + $tag)
+            }
+        };
+    }
+
+    hook_fn!(hook_a, SLOT_A, 1);
+    hook_fn!(hook_b, SLOT_B, 2);
+    hook_fn!(hook_c, SLOT_C, 3);
+
+    fn call(target: Fn, x: u64) -> u64 {
+        black_box(target)(x)
+    }
+
+    fn install(
+        manager: &mut HookManager,
+        owner: &str,
+        target: Fn,
+        detour: Fn,
+        priority: i32,
+        slot: &AtomicPtr<*const c_void>,
+    ) {
+        let next = manager
+            .install(
+                owner,
+                target as *const c_void,
+                detour as *const c_void,
+                priority,
+            )
+            .unwrap();
+        slot.store(next as *mut _, Ordering::SeqCst);
+    }
+
+    // one test, as every step patches the same function
+    #[test]
+    fn chain() {
+        let target = original as Fn;
+        let target_ptr = target as *const c_void;
+        // what `original` computes, it can't be called directly once it's hooked
+        let expected = |x: u64| (0..3).fold(x, |r, _| r * 3 + 1);
+        let mut manager = HookManager::default();
+
+        install(&mut manager, "a", target, hook_a, 0, &SLOT_A);
+        install(&mut manager, "b", target, hook_b, 10, &SLOT_B);
+        install(&mut manager, "c", target, hook_c, 0, &SLOT_C);
+        assert_eq!(
+            manager.install("a", target_ptr, hook_a as *const c_void, 5),
+            Err(HookError::AlreadyHooked)
+        );
+
+        // installed hooks stay disabled
+        assert_eq!(call(target, 5), expected(5));
+
+        manager.set_enabled("a", target_ptr, true).unwrap();
+        assert_eq!(call(target, 5), expected(51));
+
+        // b has a higher priority, c was installed after a
+        manager.set_enabled("c", target_ptr, true).unwrap();
+        manager.set_enabled("b", target_ptr, true).unwrap();
+        assert_eq!(call(target, 5), expected(5213));
+
+        manager.set_enabled("a", target_ptr, false).unwrap();
+        assert_eq!(call(target, 5), expected(523));
+
+        manager.remove("b", target_ptr).unwrap();
+        assert_eq!(call(target, 5), expected(53));
+        assert_eq!(
+            manager.set_enabled("b", target_ptr, true),
+            Err(HookError::NotHooked)
+        );
+
+        assert_eq!(manager.remove_owned_by("c"), 1);
+        assert_eq!(call(target, 5), expected(5));
+
+        manager.set_enabled("a", target_ptr, true).unwrap();
+        assert_eq!(call(target, 5), expected(51));
+
+        manager.remove("a", target_ptr).unwrap();
+        assert_eq!(call(target, 5), expected(5));
+        assert!(manager.chains.is_empty());
+    }
+}
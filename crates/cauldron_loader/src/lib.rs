@@ -1,21 +1,26 @@
+mod guard;
+mod hooks;
+mod patches;
+mod registry;
 pub mod util;
 
 use crate::util::message_box;
-use cauldron::mem::offset::Offset;
+use cauldron::mem::patch::ProcessMemory;
 use cauldron::mod_info::SafeCauldronModInfo;
 use cauldron::prelude::{CauldronApi, CauldronModInfo};
+use cauldron::registry::{ChangeCallback, EntryCallback, NamespaceCallback, RegistryEntry};
+use cauldron::{CAULDRON_API_VERSION, CauldronApiV0};
 use cauldron_config::{LogLevel, VersionedConfig};
 use libloading::{Library, Symbol};
 use once_cell::sync::Lazy;
 use retour::static_detour;
-use semver::{Version, VersionReq};
 use simplelog::{
     ColorChoice, CombinedLogger, ConfigBuilder, LevelFilter, TermLogger, TerminalMode, WriteLogger,
 };
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::ffi::{CStr, c_char, c_void};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString, c_char, c_void};
 use std::fs::File;
+use std::path::Path;
 use std::sync::Mutex;
 
 #[unsafe(no_mangle)]
@@ -54,11 +59,12 @@ fn CoreLibrary_Initialize_impl(
 }
 
 // hooks the function that loads and initializes fullgame.dll
+//
+// runs under the loader lock, before there's a logger, so the game isn't detected and the offset
+// cache isn't touched until loader_initialize
 fn loader_prepare() {
     unsafe {
-        let Ok(offset) =
-            Offset::from_signature("48 8B C4 4C 89 40 ? 55 53 57 41 54 48 8D A8 58 FE FF FF")
-        else {
+        let Ok(offset) = cauldron_signatures::find_uncached("CoreLibrary_Initialize") else {
             // todo(py): alert user of failure
             return;
         };
@@ -77,13 +83,19 @@ fn loader_prepare() {
 static LOADER_STATE: Lazy<Mutex<LoaderState>> = Lazy::new(|| Mutex::new(LoaderState::default()));
 
 struct LoaderState {
-    registered_funcs: HashMap<String, HashMap<String, *const c_void>>,
+    registry: registry::Registry,
+    /// Name of the mod whose `CauldronMod_Load` is currently running.
+    loading_mod: Option<String>,
+    /// The mod each api table was given to, keyed by the table's address.
+    api_owners: HashMap<usize, String>,
 }
 
 impl Default for LoaderState {
     fn default() -> Self {
         LoaderState {
-            registered_funcs: HashMap::new(),
+            registry: registry::Registry::default(),
+            loading_mod: None,
+            api_owners: HashMap::new(),
         }
     }
 }
@@ -91,54 +103,363 @@ impl Default for LoaderState {
 unsafe impl Send for LoaderState {}
 unsafe impl Sync for LoaderState {}
 
+/// Run `f` on the registry, then send the notifications it queued with the loader state unlocked,
+/// so subscribers can use the registry from their callbacks.
+///
+/// Callbacks are mod code, so they're guarded and a subscription whose callback faults is removed.
+fn with_registry<T>(f: impl FnOnce(&mut registry::Registry) -> T) -> T {
+    let (result, notifications) = {
+        let mut state = LOADER_STATE.lock().unwrap();
+        let result = f(&mut state.registry);
+        (result, state.registry.take_notifications())
+    };
+
+    let mut faulted = Vec::new();
+    for notification in notifications {
+        if faulted.contains(&notification.subscription) {
+            continue;
+        }
+
+        let c_namespace = CString::new(notification.entry.namespace).unwrap();
+        let c_name = CString::new(notification.entry.name).unwrap();
+        let sent = guard::guarded(|| {
+            (notification.callback)(
+                notification.context,
+                notification.change,
+                c_namespace.as_ptr(),
+                c_name.as_ptr(),
+                notification.entry.ptr,
+            )
+        });
+        if let Err(fault) = sent {
+            let (owner, id) = (notification.owner, notification.subscription);
+            log::error!("{owner}'s registry subscription {id} {fault}, it's been removed.");
+            // it may have unsubscribed before faulting
+            let _ = LOADER_STATE
+                .lock()
+                .unwrap()
+                .registry
+                .unsubscribe(&owner, id);
+            faulted.push(id);
+        }
+    }
+    result
+}
+
+/// A string as given by a mod.
+fn mod_string(string: *const c_char) -> String {
+    unsafe { CStr::from_ptr(string) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `namespace` and `name` as given by a mod.
+fn registry_key(namespace: *const c_char, name: *const c_char) -> (String, String) {
+    (mod_string(namespace), mod_string(name))
+}
+
 pub extern "C" fn loader_query_ptr_impl(
     namespace: *const c_char,
     name: *const c_char,
 ) -> *const c_void {
-    let c_namespace = unsafe { CStr::from_ptr(namespace) };
-    let c_name = unsafe { CStr::from_ptr(name) };
-    let r_namespace = c_namespace.to_str().unwrap().to_owned();
-    let r_name = c_name.to_str().unwrap().to_owned();
+    let (namespace, name) = registry_key(namespace, name);
+    loader_query(&namespace, &name)
+}
 
-    if let Some(funcs) = LOADER_STATE
+fn loader_query(namespace: &str, name: &str) -> *const c_void {
+    LOADER_STATE
         .lock()
         .unwrap()
-        .registered_funcs
-        .get_mut(&r_namespace)
-    {
-        if let Some(func) = funcs.get(&r_name) {
-            return *func;
-        }
+        .registry
+        .query(namespace, name)
+        .unwrap_or(std::ptr::null())
+}
+
+/// A descriptor as given by a mod, `None` if it's null.
+fn registry_descriptor(descriptor: *const c_char) -> Option<String> {
+    if descriptor.is_null() {
+        return None;
     }
+    Some(
+        unsafe { CStr::from_ptr(descriptor) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
 
-    std::ptr::null()
+fn loader_register(
+    owner: Option<String>,
+    namespace: *const c_char,
+    name: *const c_char,
+    function: *const c_void,
+    descriptor: Option<String>,
+) -> bool {
+    let (namespace, name) = registry_key(namespace, name);
+
+    let result = with_registry(|registry| {
+        registry.register(
+            owner.as_deref(),
+            &namespace,
+            &name,
+            function,
+            descriptor.as_deref(),
+        )
+    });
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            let owner = owner.as_deref().unwrap_or("An unknown mod");
+            log::error!("{owner} failed to register {namespace}/{name}: {e}");
+            false
+        }
+    }
 }
 
+/// Registrations through the unversioned table belong to the mod that's loading, if any.
 pub extern "C" fn loader_register_ptr_impl(
     namespace: *const c_char,
     name: *const c_char,
     function: *const c_void,
 ) -> bool {
-    let c_namespace = unsafe { CStr::from_ptr(namespace) };
-    let c_name = unsafe { CStr::from_ptr(name) };
-    let r_namespace = c_namespace.to_str().unwrap().to_owned();
-    let r_name = c_name.to_str().unwrap().to_owned();
+    let owner = LOADER_STATE.lock().unwrap().loading_mod.clone();
+    loader_register(owner, namespace, name, function, None)
+}
 
-    let mut added = false;
+pub extern "C" fn loader_registry_register_impl(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    name: *const c_char,
+    function: *const c_void,
+) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to register a pointer with an unknown api table.");
+        return false;
+    };
 
-    LOADER_STATE
+    loader_register(Some(owner), namespace, name, function, None)
+}
+
+pub extern "C" fn loader_registry_register_typed_impl(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    name: *const c_char,
+    function: *const c_void,
+    descriptor: *const c_char,
+) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to register a pointer with an unknown api table.");
+        return false;
+    };
+
+    let descriptor = registry_descriptor(descriptor);
+    loader_register(Some(owner), namespace, name, function, descriptor)
+}
+
+pub extern "C" fn loader_registry_query_typed_impl(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    name: *const c_char,
+    descriptor: *const c_char,
+) -> *const c_void {
+    let owner = api_owner(api).unwrap_or_else(|| String::from("An unknown mod"));
+    let (namespace, name) = registry_key(namespace, name);
+    let Some(descriptor) = registry_descriptor(descriptor) else {
+        return loader_query(&namespace, &name);
+    };
+
+    let result = LOADER_STATE
         .lock()
         .unwrap()
-        .registered_funcs
-        .entry(r_namespace)
-        .or_default()
-        .entry(r_name)
-        .or_insert_with(|| {
-            added = true;
-            function
-        });
+        .registry
+        .query_typed(&namespace, &name, &descriptor);
+    match result {
+        Ok(ptr) => ptr,
+        Err(registry::RegistryError::Missing) => std::ptr::null(),
+        Err(e) => {
+            log::error!("{owner} queried {namespace}/{name} with the wrong type: {e}");
+            std::ptr::null()
+        }
+    }
+}
+
+pub extern "C" fn loader_registry_unregister_impl(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    name: *const c_char,
+) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to unregister a pointer with an unknown api table.");
+        return false;
+    };
+    let (namespace, name) = registry_key(namespace, name);
+
+    let result = with_registry(|registry| registry.unregister(&owner, &namespace, &name));
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("{owner} failed to unregister {namespace}/{name}: {e}");
+            false
+        }
+    }
+}
+
+fn loader_replace(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    name: *const c_char,
+    function: *const c_void,
+    priority: i32,
+    descriptor: Option<String>,
+) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to replace a pointer with an unknown api table.");
+        return false;
+    };
+    let (namespace, name) = registry_key(namespace, name);
+
+    let result = with_registry(|registry| {
+        registry.replace(
+            &owner,
+            &namespace,
+            &name,
+            function,
+            priority,
+            descriptor.as_deref(),
+        )
+    });
+    match result {
+        Ok(Some(Some(previous))) if previous != owner => {
+            log::warn!(
+                "{owner} replaced {namespace}/{name} registered by {previous}, with priority {priority}."
+            );
+            true
+        }
+        Ok(Some(None)) => {
+            log::warn!(
+                "{owner} replaced {namespace}/{name} registered by an unknown mod, with priority {priority}."
+            );
+            true
+        }
+        Ok(_) => true,
+        Err(e) => {
+            log::error!("{owner} failed to replace {namespace}/{name}: {e}");
+            false
+        }
+    }
+}
 
-    added
+pub extern "C" fn loader_registry_replace_impl(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    name: *const c_char,
+    function: *const c_void,
+    priority: i32,
+) -> bool {
+    loader_replace(api, namespace, name, function, priority, None)
+}
+
+pub extern "C" fn loader_registry_replace_typed_impl(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    name: *const c_char,
+    function: *const c_void,
+    priority: i32,
+    descriptor: *const c_char,
+) -> bool {
+    let descriptor = registry_descriptor(descriptor);
+    loader_replace(api, namespace, name, function, priority, descriptor)
+}
+
+/// Call a mod's listing callback with each item, outside of the lock so it can use the registry.
+///
+/// The callback is mod code, so it's guarded and the listing stops at the first fault. Returns how
+/// many items it was called with.
+fn list<T>(api: *const CauldronApi, items: &[T], mut call: impl FnMut(&T)) -> usize {
+    for (i, item) in items.iter().enumerate() {
+        if let Err(fault) = guard::guarded(|| call(item)) {
+            let owner = api_owner(api).unwrap_or_else(|| String::from("An unknown mod"));
+            log::error!("{owner}'s registry listing callback {fault}, the listing was stopped.");
+            return i;
+        }
+    }
+    items.len()
+}
+
+pub extern "C" fn loader_registry_namespaces_impl(
+    api: *const CauldronApi,
+    callback: NamespaceCallback,
+    context: *mut c_void,
+) -> usize {
+    let namespaces = LOADER_STATE.lock().unwrap().registry.namespaces();
+    list(api, &namespaces, |namespace| {
+        let c_namespace = CString::new(namespace.as_str()).unwrap();
+        callback(context, c_namespace.as_ptr());
+    })
+}
+
+fn list_entries(
+    api: *const CauldronApi,
+    entries: Vec<RegistryEntry>,
+    callback: EntryCallback,
+    context: *mut c_void,
+) -> usize {
+    list(api, &entries, |entry| {
+        let c_namespace = CString::new(entry.namespace.as_str()).unwrap();
+        let c_name = CString::new(entry.name.as_str()).unwrap();
+        callback(context, c_namespace.as_ptr(), c_name.as_ptr(), entry.ptr);
+    })
+}
+
+pub extern "C" fn loader_registry_names_impl(
+    api: *const CauldronApi,
+    namespace: *const c_char,
+    callback: EntryCallback,
+    context: *mut c_void,
+) -> usize {
+    let namespace = mod_string(namespace);
+    let entries = LOADER_STATE.lock().unwrap().registry.names(&namespace);
+    list_entries(api, entries, callback, context)
+}
+
+pub extern "C" fn loader_registry_glob_impl(
+    api: *const CauldronApi,
+    pattern: *const c_char,
+    callback: EntryCallback,
+    context: *mut c_void,
+) -> usize {
+    let pattern = mod_string(pattern);
+    let entries = LOADER_STATE.lock().unwrap().registry.glob(&pattern);
+    list_entries(api, entries, callback, context)
+}
+
+pub extern "C" fn loader_registry_subscribe_impl(
+    api: *const CauldronApi,
+    pattern: *const c_char,
+    callback: ChangeCallback,
+    context: *mut c_void,
+) -> u64 {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to subscribe to the registry with an unknown api table.");
+        return 0;
+    };
+
+    let pattern = mod_string(pattern);
+    with_registry(|registry| registry.subscribe(&owner, &pattern, callback, context))
+}
+
+pub extern "C" fn loader_registry_unsubscribe_impl(api: *const CauldronApi, id: u64) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to unsubscribe from the registry with an unknown api table.");
+        return false;
+    };
+
+    match with_registry(|registry| registry.unsubscribe(&owner, id)) {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("{owner} failed to unsubscribe {id} from the registry: {e}");
+            false
+        }
+    }
 }
 
 pub extern "C" fn loader_log_impl(
@@ -153,19 +474,208 @@ pub extern "C" fn loader_log_impl(
     log::log!(target: &target_str, log_level, "{}", message_str);
 }
 
-static LOADER_API: CauldronApi = CauldronApi {
+/// The mod `api` was given to.
+fn api_owner(api: *const CauldronApi) -> Option<String> {
+    LOADER_STATE
+        .lock()
+        .unwrap()
+        .api_owners
+        .get(&(api as usize))
+        .cloned()
+}
+
+pub extern "C" fn loader_hook_install_impl(
+    api: *const CauldronApi,
+    target: *const c_void,
+    detour: *const c_void,
+    priority: i32,
+) -> *const *const c_void {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to hook {target:#X?} with an unknown api table.");
+        return std::ptr::null();
+    };
+
+    match hooks::HOOKS
+        .lock()
+        .unwrap()
+        .install(&owner, target, detour, priority)
+    {
+        Ok(slot) => slot,
+        Err(e) => {
+            log::error!("{owner} failed to hook {target:#X?}: {e}");
+            std::ptr::null()
+        }
+    }
+}
+
+fn loader_hook_set_enabled(api: *const CauldronApi, target: *const c_void, enabled: bool) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to toggle the hook on {target:#X?} with an unknown api table.");
+        return false;
+    };
+
+    match hooks::HOOKS
+        .lock()
+        .unwrap()
+        .set_enabled(&owner, target, enabled)
+    {
+        Ok(()) => true,
+        Err(e) => {
+            let action = if enabled { "enable" } else { "disable" };
+            log::error!("{owner} failed to {action} its hook on {target:#X?}: {e}");
+            false
+        }
+    }
+}
+
+pub extern "C" fn loader_hook_enable_impl(api: *const CauldronApi, target: *const c_void) -> bool {
+    loader_hook_set_enabled(api, target, true)
+}
+
+pub extern "C" fn loader_hook_disable_impl(api: *const CauldronApi, target: *const c_void) -> bool {
+    loader_hook_set_enabled(api, target, false)
+}
+
+pub extern "C" fn loader_hook_remove_impl(api: *const CauldronApi, target: *const c_void) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to unhook {target:#X?} with an unknown api table.");
+        return false;
+    };
+
+    match hooks::HOOKS.lock().unwrap().remove(&owner, target) {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("{owner} failed to remove its hook on {target:#X?}: {e}");
+            false
+        }
+    }
+}
+
+static LOADER_API_V0: CauldronApiV0 = CauldronApiV0 {
     query_ptr: loader_query_ptr_impl,
     register_ptr: loader_register_ptr_impl,
     log: loader_log_impl,
 };
 
+/// The api table for `mod_name`, which needs at least `api_version`.
+///
+/// Versioned tables are per mod, so calls that act on behalf of a mod (e.g. hooking) know who
+/// made them.
+fn loader_api_for(mod_name: &str, api_version: u32) -> *const c_void {
+    if api_version == 0 {
+        return &LOADER_API_V0 as *const CauldronApiV0 as *const c_void;
+    }
+
+    let api: &'static CauldronApi = Box::leak(Box::new(CauldronApi {
+        size: size_of::<CauldronApi>() as u32,
+        version: CAULDRON_API_VERSION,
+        query_ptr: loader_query_ptr_impl,
+        register_ptr: loader_register_ptr_impl,
+        log: loader_log_impl,
+        hook_install_ptr: loader_hook_install_impl,
+        hook_enable_ptr: loader_hook_enable_impl,
+        hook_disable_ptr: loader_hook_disable_impl,
+        hook_remove_ptr: loader_hook_remove_impl,
+        patch_apply_ptr: patches::loader_patch_apply_impl,
+        patch_restore_ptr: patches::loader_patch_restore_impl,
+        registry_register_ptr: loader_registry_register_impl,
+        registry_unregister_ptr: loader_registry_unregister_impl,
+        registry_replace_ptr: loader_registry_replace_impl,
+        registry_register_typed_ptr: loader_registry_register_typed_impl,
+        registry_replace_typed_ptr: loader_registry_replace_typed_impl,
+        registry_query_typed_ptr: loader_registry_query_typed_impl,
+        registry_namespaces_ptr: loader_registry_namespaces_impl,
+        registry_names_ptr: loader_registry_names_impl,
+        registry_glob_ptr: loader_registry_glob_impl,
+        registry_subscribe_ptr: loader_registry_subscribe_impl,
+        registry_unsubscribe_ptr: loader_registry_unsubscribe_impl,
+    }));
+    LOADER_STATE
+        .lock()
+        .unwrap()
+        .api_owners
+        .insert(api as *const CauldronApi as usize, mod_name.to_owned());
+
+    api as *const CauldronApi as *const c_void
+}
+
+/// A mod whose info has been read, waiting to be loaded.
+struct LoadingMod {
+    dll_name: String,
+    lib: Library,
+    info: SafeCauldronModInfo,
+    /// Minimum [CauldronApi] version the mod needs, 0 if it predates the versioned table and needs a
+    /// [CauldronApiV0].
+    api_version: u32,
+}
+
+/// Load the mod at `path` and read its [CauldronModInfo].
+///
+/// Both the library's `DllMain` and `CauldronMod_Info` are mod code, so they're guarded and any
+/// fault is returned as a reason to skip the mod.
+fn load_mod_info(path: &Path) -> Result<LoadingMod, String> {
+    let dll_name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+    let lib = match guard::guarded(|| unsafe { Library::new(path) }) {
+        Ok(Ok(lib)) => lib,
+        Ok(Err(e)) => return Err(format!("failed to load library: {e}")),
+        Err(fault) => return Err(format!("{fault} while being loaded.")),
+    };
+
+    let api_version = match unsafe {
+        lib.get::<unsafe extern "C-unwind" fn() -> u32>(b"CauldronMod_ApiVersion\0")
+    } {
+        Ok(version_func) => match guard::guarded(|| unsafe { version_func() }) {
+            Ok(api_version) => api_version,
+            Err(fault) => return Err(format!("{fault} in CauldronMod_ApiVersion.")),
+        },
+        // mods built against a cauldron with the header can read it without declaring a version
+        Err(_) => match unsafe { lib.get::<*const u32>(b"CauldronMod_ApiHeader\0") } {
+            Ok(_) => 1,
+            Err(_) => 0,
+        },
+    };
+    if api_version > CAULDRON_API_VERSION {
+        return Err(format!(
+            "requires Cauldron API v{api_version} but this version of Cauldron only provides up to v{CAULDRON_API_VERSION}."
+        ));
+    }
+
+    let info_func: Symbol<unsafe extern "C-unwind" fn() -> *const CauldronModInfo> =
+        match unsafe { lib.get(b"CauldronMod_Info\0") } {
+            Ok(info_func) => info_func,
+            Err(e) => return Err(format!("missing CauldronMod_Info: {e}")),
+        };
+
+    let info = guard::guarded(|| {
+        let info = unsafe { info_func() };
+        if info.is_null() {
+            None
+        } else {
+            Some(SafeCauldronModInfo::from(unsafe { (*info).clone() }))
+        }
+    });
+
+    match info {
+        Ok(Some(info)) => Ok(LoadingMod {
+            dll_name,
+            lib,
+            info,
+            api_version,
+        }),
+        Ok(None) => Err(String::from("CauldronMod_Info returned null.")),
+        Err(fault) => Err(format!("{fault} in CauldronMod_Info.")),
+    }
+}
+
 unsafe fn loader_initialize() {
     let config = cauldron_config::load_config_or_write_default();
     let config = match config {
         VersionedConfig::V1(cauldron_config) => cauldron_config,
     };
 
-    let game_ver_tuple = cauldron_game_detection::detect_active();
+    // detected once for the loader and the signature database
+    let game_ver_tuple = cauldron_signatures::active().ok().cloned();
     let (game, game_version) = match game_ver_tuple {
         None => {
             log::error!("Unable to detect the running game or version.");
@@ -230,159 +740,282 @@ unsafe fn loader_initialize() {
     log::info!("Starting Cauldron v{}...", env!("CARGO_PKG_VERSION"));
 
     let mods_dir = std::fs::read_dir("cauldron/mods").expect("Failed to read mods dir");
-    let mut loading_mods: Vec<(Library, SafeCauldronModInfo)> = Vec::new();
-    let mut mod_versions: HashMap<String, Version> = HashMap::new();
+    let mut loading_mods: Vec<LoadingMod> = Vec::new();
 
-    // add active game to mod_versions
-    mod_versions.insert(game.code(), game_version.clone());
+    // (name, reason) of every mod that won't be loaded, shown to the user once loading is done
+    let mut skipped_mods: Vec<(String, String)> = Vec::new();
 
     for entry in mods_dir {
         let path = entry.unwrap().path();
         if path.extension().map_or(false, |ext| ext == "dll") {
             log::debug!("Loading mod at {}", path.display());
 
-            let lib = unsafe { Library::new(&path).unwrap() };
-
-            let info_func: Symbol<unsafe extern "C" fn() -> *const CauldronModInfo> =
-                unsafe { lib.get(b"CauldronMod_Info\0").unwrap() };
-
-            let mod_info = unsafe { &*info_func() };
-            let mod_info = SafeCauldronModInfo::from(mod_info.clone());
-            log::debug!("{mod_info:?}");
-            loading_mods.push((lib, mod_info));
+            match load_mod_info(&path) {
+                Ok(loading_mod) => {
+                    log::debug!("{:?} (api v{})", loading_mod.info, loading_mod.api_version);
+                    loading_mods.push(loading_mod);
+                }
+                Err(reason) => {
+                    let dll_name = path.file_name().unwrap().to_string_lossy().into_owned();
+                    log::error!("Skipping {dll_name}: {reason}");
+                    skipped_mods.push((dll_name, reason));
+                }
+            }
         }
     }
 
-    // parse versions
-    for (_, mod_info) in &loading_mods {
-        let version = match Version::parse(&mod_info.version) {
-            Ok(v) => v,
-            Err(e) => {
-                message_box(
-                    "Mod Loading Error",
-                    format!(
-                        "{} has an invalid version string, \"{}\", must be semver compliant.",
-                        &mod_info.name, &mod_info.version
-                    )
-                    .as_str(),
-                    0u32 | 16u32, /* MB_OK | MB_ICONERROR */
-                );
-
-                log::error!(
-                    "Failed to parse {}'s version string as semver version: {e}, exiting.",
-                    &mod_info.name
-                );
-                std::process::exit(0);
-            }
-        };
-
-        mod_versions.insert(mod_info.name.clone(), version);
+    let mod_infos: Vec<SafeCauldronModInfo> = loading_mods.iter().map(|m| m.info.clone()).collect();
+    let resolution = cauldron_resolver::resolve(&game.code(), &game_version, &mod_infos);
+    for error in &resolution.errors {
+        log::error!("{error}");
     }
 
-    // ensure all required dependencies are loaded
-    for (_, mod_info) in &loading_mods {
-        for dep in &mod_info.dependencies {
-            let version = match VersionReq::parse(&match &dep.version {
-                None => String::from("*"),
-                Some(v) => v.clone(),
-            }) {
-                Ok(v) => v,
-                Err(e) => {
-                    message_box("Mod Loading Error", format!("{} has an invalid dependency version constraint for {}: \"{}\", see log for mod details.", &mod_info.name, &dep.name, &match &dep.version {
-                        None => String::from("*"),
-                        Some(v) => v.clone(),
-                    }).as_str(), 0u32 | 16u32 /* MB_OK | MB_ICONERROR */);
-                    log::error!(
-                        "Mod {} has an invalid dependency version constraint for {}: {e}, exiting.",
-                        &mod_info.name,
-                        &dep.name
-                    );
-                    std::process::exit(0);
-                }
-            };
-
-            if !mod_versions.contains_key(&dep.name) && !dep.optional {
-                // dep not found but is required
-                log::error!(
-                    "{} requires {} {version} to be installed.",
-                    &mod_info.name,
-                    &dep.name
-                );
-                message_box(
-                    "Mod Loading Error",
-                    format!(
-                        "{} lists {} as a required dependency but it is not present.",
-                        &mod_info.name, &dep.name,
-                    )
-                    .as_str(),
-                    0u32 | 16u32, /* MB_OK | MB_ICONERROR */
-                );
-                std::process::exit(0);
-            } else if !version.matches(&mod_versions[&dep.name]) && dep.optional {
-                // is optional but versions dont match
-                log::error!(
-                    "{} optionally depends on {} {version} but {} is installed instead, exiting.",
-                    &mod_info.name,
-                    &dep.name,
-                    &mod_versions[&dep.name]
-                );
-                message_box("Mod Loading Error", format!("{} lists {} as an optional dependency but a version that doesn't fit the required constraints is present. (required: {version}, present: {})", &mod_info.name, &dep.name, &mod_versions[&dep.name]).as_str(), 0u32 | 16u32 /* MB_OK | MB_ICONERROR */);
-                std::process::exit(0);
-            }
-        }
+    if !resolution.is_ok() && config.loader.strict {
+        log::error!("Failed to resolve mods in strict mode, exiting.");
+        message_box(
+            "Mod Loading Error",
+            resolution
+                .errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+                .as_str(),
+            16u32, /* MB_OK | MB_ICONERROR */
+        );
+        std::process::exit(0);
     }
 
-    loading_mods.sort_by(|(_, a), (_, b)| {
-        let a_deps = a.dependencies.iter().map(|d| &d.name).collect::<Vec<_>>();
-        let b_deps = b.dependencies.iter().map(|d| &d.name).collect::<Vec<_>>();
+    for skipped in &resolution.skipped {
+        let name = &mod_infos[skipped.index].name;
+        log::warn!("Skipping {name}: {}", skipped.reason);
+        skipped_mods.push((name.clone(), skipped.reason.to_string()));
+    }
 
-        if a_deps.contains(&&b.name) && b_deps.contains(&&a.name) {
-            // circular dependencies
-            log::error!(
-                "circular dependencies: {} and {} both depend on each other.",
-                &a.name,
-                &b.name
-            );
-            message_box(
-                "Mod Loading Error",
-                format!("{} and {} both depend on each other.", &a.name, &b.name).as_str(),
-                0u32 | 16u32, /* MB_OK | MB_ICONERROR */
-            );
-            std::process::exit(0);
-        } else if a_deps.contains(&&b.name) {
-            // a depends on b
-            Ordering::Greater
-        } else if b_deps.contains(&&a.name) {
-            // b depends on a
-            Ordering::Less
-        } else {
-            Ordering::Equal
-        }
-    });
+    let mut loading_mods: Vec<Option<LoadingMod>> = loading_mods.into_iter().map(Some).collect();
+    let loading_mods: Vec<LoadingMod> = resolution
+        .order
+        .iter()
+        .map(|&index| loading_mods[index].take().unwrap())
+        .collect();
 
     // todo(py): table these (see https://github.com/QuiltMC/quilt-loader/blob/0a17274320a646551abb04435d810158988f0fcc/src/main/java/org/quiltmc/loader/impl/QuiltLoaderImpl.java#L819)
     let mut mods_string = format!("\t 0. {} v{}", game.code(), &game_version);
-    for (i, (_, mod_info)) in loading_mods.iter().enumerate() {
+    for (i, loading_mod) in loading_mods.iter().enumerate() {
         mods_string.push_str(&format!(
             "\n\t {}. {} v{}",
             i + 1,
-            &mod_info.name,
-            &mod_info.version
+            loading_mod.info.name,
+            loading_mod.info.version
         ));
     }
 
-    log::info!("Found {} mods:\n{mods_string}", mod_versions.len());
+    log::info!("Found {} mods:\n{mods_string}", loading_mods.len() + 1);
     log::info!("Loading mods...");
 
-    for (lib, _) in &loading_mods {
-        let init_func: Symbol<unsafe extern "C" fn(*const CauldronApi) -> bool> =
-            unsafe { lib.get(b"CauldronMod_Load\0").unwrap() };
+    let mut failed_mods: HashSet<String> = HashSet::new();
+    for LoadingMod {
+        dll_name,
+        lib,
+        info: mod_info,
+        api_version,
+    } in &loading_mods
+    {
+        if let Some(dep) = mod_info
+            .dependencies
+            .iter()
+            .find(|dep| !dep.optional && failed_mods.contains(&dep.name))
+        {
+            log::warn!(
+                "Skipping {}: its dependency {} failed to load.",
+                mod_info.name,
+                dep.name
+            );
+            skipped_mods.push((
+                mod_info.name.clone(),
+                format!("its dependency {} failed to load.", dep.name),
+            ));
+            failed_mods.insert(mod_info.name.clone());
+            continue;
+        }
 
-        let _load_status = unsafe { init_func(&LOADER_API as *const CauldronApi) };
-        // todo(py): handle mod load failure
+        let init_func: Symbol<unsafe extern "C-unwind" fn(*const c_void) -> bool> =
+            match unsafe { lib.get(b"CauldronMod_Load\0") } {
+                Ok(init_func) => init_func,
+                Err(e) => {
+                    log::error!(
+                        "Skipping {}: {dll_name} has no CauldronMod_Load: {e}",
+                        mod_info.name
+                    );
+                    skipped_mods.push((
+                        mod_info.name.clone(),
+                        format!("{dll_name} has no CauldronMod_Load."),
+                    ));
+                    failed_mods.insert(mod_info.name.clone());
+                    continue;
+                }
+            };
+
+        let api = loader_api_for(&mod_info.name, *api_version);
+        LOADER_STATE.lock().unwrap().loading_mod = Some(mod_info.name.clone());
+        let load_result = guard::guarded(|| unsafe { init_func(api) });
+        LOADER_STATE.lock().unwrap().loading_mod = None;
+
+        let reason = match load_result {
+            Ok(true) => continue,
+            Ok(false) => String::from("it failed to load."),
+            Err(fault) => format!("{dll_name} {fault} in CauldronMod_Load."),
+        };
+
+        let removed = with_registry(|registry| registry.remove_owned_by(&mod_info.name));
+        let removed_hooks = hooks::HOOKS.lock().unwrap().remove_owned_by(&mod_info.name);
+        let restored_patches = patches::PATCHES
+            .lock()
+            .unwrap()
+            .restore_owned_by(&mut unsafe { ProcessMemory::new() }, &mod_info.name);
+
+        log::error!(
+            "{} failed to load, removed {removed} registry entries, {removed_hooks} hooks and {restored_patches} patches it added: {reason}",
+            mod_info.name
+        );
+        skipped_mods.push((mod_info.name.clone(), reason));
+        failed_mods.insert(mod_info.name.clone());
     }
 
     log::info!("Mod loading complete.");
 
+    if !skipped_mods.is_empty() {
+        log::warn!("Skipped {} mods.", skipped_mods.len());
+        message_box(
+            "Mod Loading Warning",
+            format!(
+                "The following mods were not loaded:\n\n{}",
+                skipped_mods
+                    .iter()
+                    .map(|(name, reason)| format!("{name}: {reason}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+            .as_str(),
+            48u32, /* MB_OK | MB_ICONWARNING */
+        );
+    }
+
     std::mem::forget(loading_mods);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cauldron::registry::RegistryChange;
+
+    extern "C" fn double(x: u32) -> u32 {
+        x * 2
+    }
+
+    #[test]
+    fn invalid_mod_strings() {
+        let (namespace, name) = registry_key(c"tests/\xFF".as_ptr(), c"Name".as_ptr());
+        assert_eq!(namespace, "tests/\u{FFFD}");
+        assert_eq!(name, "Name");
+    }
+
+    // one test, as every mod shares the loader's registry
+    #[test]
+    fn registry_through_the_api() {
+        let api =
+            unsafe { &*(loader_api_for("tests", CAULDRON_API_VERSION) as *const CauldronApi) };
+        let function = double as extern "C" fn(u32) -> u32;
+
+        assert!(api.register_typed("tests/functions", "Math/Double", function));
+        assert!(api.register("tests/functions", "Math/Half", std::ptr::null()));
+        assert!(!api.register("tests/functions", "Math/Double", std::ptr::null()));
+
+        let queried = unsafe {
+            api.query_typed::<extern "C" fn(u32) -> u32>("tests/functions", "Math/Double")
+        };
+        assert_eq!(queried.map(|f| f(4)), Some(8));
+        let wrong = unsafe {
+            api.query_typed::<extern "C" fn(u64) -> u64>("tests/functions", "Math/Double")
+        };
+        assert!(wrong.is_none());
+
+        assert!(
+            api.namespaces()
+                .any(|namespace| namespace == "tests/functions")
+        );
+        let names: Vec<String> = api
+            .names("tests/functions")
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["Math/Double", "Math/Half"]);
+        assert_eq!(api.glob("tests/*Dou*").count(), 1);
+        assert_eq!(api.prefix("tests/functions/Math/").count(), 2);
+
+        assert!(api.unregister("tests/functions", "Math/Half"));
+        assert_eq!(api.prefix("tests/").count(), 1);
+
+        // late binding, the callback can use the registry itself
+        let seen = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let subscription = api
+            .subscribe("tests/late/*", {
+                let seen = seen.clone();
+                move |change, entry| {
+                    let queried = api.query(&entry.namespace, &entry.name).map(|p| p as usize);
+                    seen.lock()
+                        .unwrap()
+                        .push((change, entry.name.clone(), queried));
+                }
+            })
+            .unwrap();
+        assert!(api.register("tests/late", "Provider", 0x10 as *const c_void));
+        assert!(api.unregister("tests/late", "Provider"));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (
+                    RegistryChange::Registered,
+                    String::from("Provider"),
+                    Some(0x10)
+                ),
+                (RegistryChange::Removed, String::from("Provider"), None),
+            ]
+        );
+
+        assert!(subscription.unsubscribe());
+        assert!(api.register("tests/late", "Provider", 0x10 as *const c_void));
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn mid_hook_through_the_ledger() {
+        use cauldron::mem::{cave::CodeCave, mid_hook::MidHook};
+
+        let api =
+            unsafe { &*(loader_api_for("tests", CAULDRON_API_VERSION) as *const CauldronApi) };
+        let other =
+            unsafe { &*(loader_api_for("other", CAULDRON_API_VERSION) as *const CauldronApi) };
+
+        // mov rax, rcx; add rax, rcx; ret
+        let code = [0x48, 0x89, 0xC8, 0x48, 0x01, 0xC8, 0xC3];
+        let mut function =
+            CodeCave::allocate_near_address(double as *const () as usize, 0x1000).unwrap();
+        function.write(0, &code).unwrap();
+        let call: extern "win64" fn(u64) -> u64 =
+            unsafe { std::mem::transmute(function.address()) };
+
+        let hook = unsafe {
+            MidHook::install_through(api, function.address(), |context| context.rcx += 1)
+        }
+        .unwrap();
+        assert_eq!(call(3), 8);
+
+        // the jump is in the ledger, so another mod can't patch over it
+        let target = function.address() as *mut c_void;
+        assert!(unsafe { other.patch(target, &code[..2], &[0x90; 2]) }.is_none());
+
+        hook.remove().unwrap();
+        assert_eq!(call(3), 6);
+    }
+}
@@ -0,0 +1,66 @@
+//! The process-wide patch ledger, see [cauldron::mem::patch].
+
+use crate::api_owner;
+use cauldron::CauldronApi;
+use cauldron::mem::patch::{PatchLedger, ProcessMemory};
+use once_cell::sync::Lazy;
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+pub(crate) static PATCHES: Lazy<Mutex<PatchLedger>> =
+    Lazy::new(|| Mutex::new(PatchLedger::default()));
+
+pub extern "C" fn loader_patch_apply_impl(
+    api: *const CauldronApi,
+    address: *mut c_void,
+    expected: *const u8,
+    data: *const u8,
+    len: usize,
+) -> u64 {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to patch {address:#X?} with an unknown api table.");
+        return 0;
+    };
+
+    let expected = unsafe { std::slice::from_raw_parts(expected, len) };
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    // the mod vouches for the address by calling CauldronApi::patch, which is unsafe
+    match PATCHES.lock().unwrap().apply(
+        &mut unsafe { ProcessMemory::new() },
+        &owner,
+        address as usize,
+        expected,
+        data,
+    ) {
+        Ok(id) => id,
+        Err(e) => {
+            log::error!("{owner} failed to patch {address:#X?}: {e}");
+            0
+        }
+    }
+}
+
+pub extern "C" fn loader_patch_restore_impl(api: *const CauldronApi, id: u64) -> bool {
+    let Some(owner) = api_owner(api) else {
+        log::error!("Tried to restore patch {id} with an unknown api table.");
+        return false;
+    };
+
+    let mut patches = PATCHES.lock().unwrap();
+    if patches
+        .owner(id)
+        .is_some_and(|patch_owner| patch_owner != owner)
+    {
+        log::error!("{owner} tried to restore patch {id}, which isn't theirs.");
+        return false;
+    }
+
+    // only restores bytes the ledger patched
+    match patches.restore(&mut unsafe { ProcessMemory::new() }, id) {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("{owner} failed to restore patch {id}: {e}");
+            false
+        }
+    }
+}
@@ -0,0 +1,681 @@
+//! Named pointers mods share with each other, and which mod registered each of them.
+//!
+//! Every name holds a stack of entries, at most one per mod. The top entry is the one queried, a
+//! mod can [replace](Registry::replace) it with a higher priority than it was registered or
+//! replaced with. Unregistering an entry uncovers the one it replaced, if any.
+//!
+//! Entries can carry a type descriptor, see [cauldron::registry]. Typed queries and replacements
+//! of typed entries fail if the descriptors differ, untyped entries match anything.
+//!
+//! Mods can [subscribe](Registry::subscribe) to the names matching a glob pattern. Whenever what
+//! a matching name is queried as changes, a [Notification] is queued, which the loader sends once
+//! it's done with the registry.
+
+use cauldron::registry::{ChangeCallback, RegistryChange, RegistryEntry, glob};
+use std::collections::HashMap;
+use std::ffi::c_void;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum RegistryError {
+    /// Already registered, by the mod named, or an unknown one.
+    Registered(Option<String>),
+    /// Replaced with an equal or higher priority by another mod.
+    Outranked {
+        owner: Option<String>,
+        priority: i32,
+    },
+    NotRegistered,
+    NotSubscribed,
+    /// Nothing is registered under the name.
+    Missing,
+    /// Registered by the mod named with another descriptor.
+    TypeMismatch {
+        owner: Option<String>,
+        registered: String,
+        expected: String,
+    },
+}
+
+/// Who registered an entry, registrations made outside of `CauldronMod_Load` through the
+/// unversioned api table can't be told apart.
+fn owner_name(owner: &Option<String>) -> &str {
+    owner.as_deref().unwrap_or("an unknown mod")
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistryError::Registered(owner) => {
+                write!(f, "it's already registered by {}", owner_name(owner))
+            }
+            RegistryError::Outranked { owner, priority } => write!(
+                f,
+                "it's been replaced by {} with priority {priority}",
+                owner_name(owner)
+            ),
+            RegistryError::NotRegistered => f.write_str("the mod hasn't registered it"),
+            RegistryError::NotSubscribed => f.write_str("the mod has no such subscription"),
+            RegistryError::Missing => f.write_str("nothing is registered under it"),
+            RegistryError::TypeMismatch {
+                owner,
+                registered,
+                expected,
+            } => write!(
+                f,
+                "{} registered it as `{registered}`, not `{expected}`",
+                owner_name(owner)
+            ),
+        }
+    }
+}
+
+struct Entry {
+    owner: Option<String>,
+    ptr: *const c_void,
+    priority: i32,
+    descriptor: Option<String>,
+}
+
+impl Entry {
+    /// Fails if both this entry and `expected` are typed, with different descriptors.
+    fn check(&self, expected: Option<&str>) -> Result<(), RegistryError> {
+        match (self.descriptor.as_deref(), expected) {
+            (Some(registered), Some(expected)) if registered != expected => {
+                Err(RegistryError::TypeMismatch {
+                    owner: self.owner.clone(),
+                    registered: registered.to_owned(),
+                    expected: expected.to_owned(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Subscription {
+    id: u64,
+    owner: String,
+    pattern: String,
+    callback: ChangeCallback,
+    context: *mut c_void,
+}
+
+/// A change to send to a subscriber.
+pub(crate) struct Notification {
+    /// The subscription it's for, and the mod that subscribed.
+    pub(crate) subscription: u64,
+    pub(crate) owner: String,
+    pub(crate) callback: ChangeCallback,
+    pub(crate) context: *mut c_void,
+    pub(crate) change: RegistryChange,
+    /// The entry as it's queried now, with a null `ptr` if it was removed.
+    pub(crate) entry: RegistryEntry,
+}
+
+#[derive(Default)]
+pub(crate) struct Registry {
+    /// By namespace and name, the top of each stack is the last entry.
+    entries: HashMap<(String, String), Vec<Entry>>,
+    subscriptions: Vec<Subscription>,
+    last_subscription: u64,
+    notifications: Vec<Notification>,
+}
+
+unsafe impl Send for Registry {}
+unsafe impl Sync for Registry {}
+
+impl Registry {
+    pub(crate) fn query(&self, namespace: &str, name: &str) -> Option<*const c_void> {
+        let key = (namespace.to_owned(), name.to_owned());
+        self.entries.get(&key)?.last().map(|entry| entry.ptr)
+    }
+
+    /// Every namespace with entries, sorted.
+    pub(crate) fn namespaces(&self) -> Vec<String> {
+        let mut namespaces: Vec<String> = self
+            .entries
+            .keys()
+            .map(|(namespace, _)| namespace.clone())
+            .collect();
+        namespaces.sort();
+        namespaces.dedup();
+        namespaces
+    }
+
+    /// The queried entries whose `namespace/name` [matches](glob) `pattern`, sorted.
+    pub(crate) fn glob(&self, pattern: &str) -> Vec<RegistryEntry> {
+        self.matching(|namespace, name| glob(pattern, &format!("{namespace}/{name}")))
+    }
+
+    /// The queried entries in `namespace`, sorted by name.
+    pub(crate) fn names(&self, namespace: &str) -> Vec<RegistryEntry> {
+        self.matching(|entry_namespace, _| entry_namespace == namespace)
+    }
+
+    fn matching(&self, filter: impl Fn(&str, &str) -> bool) -> Vec<RegistryEntry> {
+        let mut entries: Vec<RegistryEntry> = self
+            .entries
+            .iter()
+            .filter(|((namespace, name), _)| filter(namespace, name))
+            .filter_map(|((namespace, name), stack)| {
+                Some(RegistryEntry {
+                    namespace: namespace.clone(),
+                    name: name.clone(),
+                    ptr: stack.last()?.ptr,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        entries
+    }
+
+    /// [query](Registry::query) an entry expected to be `descriptor`.
+    pub(crate) fn query_typed(
+        &self,
+        namespace: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<*const c_void, RegistryError> {
+        let key = (namespace.to_owned(), name.to_owned());
+        let entry = self
+            .entries
+            .get(&key)
+            .and_then(|stack| stack.last())
+            .ok_or(RegistryError::Missing)?;
+        entry.check(Some(descriptor))?;
+        Ok(entry.ptr)
+    }
+
+    /// Add a new entry with priority 0, fails if `name` is already registered.
+    pub(crate) fn register(
+        &mut self,
+        owner: Option<&str>,
+        namespace: &str,
+        name: &str,
+        ptr: *const c_void,
+        descriptor: Option<&str>,
+    ) -> Result<(), RegistryError> {
+        let stack = self
+            .entries
+            .entry((namespace.to_owned(), name.to_owned()))
+            .or_default();
+        if let Some(top) = stack.last() {
+            return Err(RegistryError::Registered(top.owner.clone()));
+        }
+
+        stack.push(Entry {
+            owner: owner.map(str::to_owned),
+            ptr,
+            priority: 0,
+            descriptor: descriptor.map(str::to_owned),
+        });
+        self.notify(namespace, name, None);
+        Ok(())
+    }
+
+    /// Put `ptr` on top of `name`, fails if another mod's entry is there with an equal or higher
+    /// priority, or a different descriptor. Any entry `owner` already had underneath is dropped.
+    /// Returns who had the entry that was replaced, if there was one.
+    pub(crate) fn replace(
+        &mut self,
+        owner: &str,
+        namespace: &str,
+        name: &str,
+        ptr: *const c_void,
+        priority: i32,
+        descriptor: Option<&str>,
+    ) -> Result<Option<Option<String>>, RegistryError> {
+        let before = self.query(namespace, name);
+        let stack = self
+            .entries
+            .entry((namespace.to_owned(), name.to_owned()))
+            .or_default();
+        let replaced = match stack.last() {
+            Some(top) if top.owner.as_deref() != Some(owner) && top.priority >= priority => {
+                return Err(RegistryError::Outranked {
+                    owner: top.owner.clone(),
+                    priority: top.priority,
+                });
+            }
+            Some(top) => {
+                top.check(descriptor)?;
+                Some(top.owner.clone())
+            }
+            None => None,
+        };
+
+        stack.retain(|entry| entry.owner.as_deref() != Some(owner));
+        stack.push(Entry {
+            owner: Some(owner.to_owned()),
+            ptr,
+            priority,
+            descriptor: descriptor.map(str::to_owned),
+        });
+        self.notify(namespace, name, before);
+        Ok(replaced)
+    }
+
+    /// Remove `owner`'s entry for `name`, uncovering the one it replaced.
+    pub(crate) fn unregister(
+        &mut self,
+        owner: &str,
+        namespace: &str,
+        name: &str,
+    ) -> Result<(), RegistryError> {
+        let before = self.query(namespace, name);
+        let key = (namespace.to_owned(), name.to_owned());
+        let stack = self
+            .entries
+            .get_mut(&key)
+            .ok_or(RegistryError::NotRegistered)?;
+        let position = stack
+            .iter()
+            .position(|entry| entry.owner.as_deref() == Some(owner))
+            .ok_or(RegistryError::NotRegistered)?;
+        stack.remove(position);
+
+        if stack.is_empty() {
+            self.entries.remove(&key);
+        }
+        self.notify(namespace, name, before);
+        Ok(())
+    }
+
+    /// Remove every entry and subscription `owner` has, returns the number of removed entries.
+    pub(crate) fn remove_owned_by(&mut self, owner: &str) -> usize {
+        self.subscriptions
+            .retain(|subscription| subscription.owner != owner);
+
+        let owned: Vec<((String, String), Option<*const c_void>)> = self
+            .entries
+            .iter()
+            .filter(|(_, stack)| stack.iter().any(|e| e.owner.as_deref() == Some(owner)))
+            .map(|(key, stack)| (key.clone(), stack.last().map(|entry| entry.ptr)))
+            .collect();
+
+        let mut removed = 0;
+        for ((namespace, name), before) in owned {
+            let key = (namespace, name);
+            let Some(stack) = self.entries.get_mut(&key) else {
+                continue;
+            };
+            let count = stack.len();
+            stack.retain(|entry| entry.owner.as_deref() != Some(owner));
+            removed += count - stack.len();
+            if stack.is_empty() {
+                self.entries.remove(&key);
+            }
+            self.notify(&key.0, &key.1, before);
+        }
+        removed
+    }
+
+    /// Call `callback` with `context` whenever what a name matching the [glob] `pattern` is
+    /// queried as changes. Every matching entry there already is gets a
+    /// [Registered](RegistryChange::Registered) notification straight away. Returns the
+    /// subscription's id, never 0.
+    pub(crate) fn subscribe(
+        &mut self,
+        owner: &str,
+        pattern: &str,
+        callback: ChangeCallback,
+        context: *mut c_void,
+    ) -> u64 {
+        self.last_subscription += 1;
+        let id = self.last_subscription;
+        self.subscriptions.push(Subscription {
+            id,
+            owner: owner.to_owned(),
+            pattern: pattern.to_owned(),
+            callback,
+            context,
+        });
+
+        for entry in self.glob(pattern) {
+            self.notifications.push(Notification {
+                subscription: id,
+                owner: owner.to_owned(),
+                callback,
+                context,
+                change: RegistryChange::Registered,
+                entry,
+            });
+        }
+        id
+    }
+
+    pub(crate) fn unsubscribe(&mut self, owner: &str, id: u64) -> Result<(), RegistryError> {
+        let position = self
+            .subscriptions
+            .iter()
+            .position(|subscription| subscription.id == id && subscription.owner == owner)
+            .ok_or(RegistryError::NotSubscribed)?;
+        self.subscriptions.remove(position);
+        Ok(())
+    }
+
+    /// The notifications queued since the last call, to send once the registry is unlocked.
+    pub(crate) fn take_notifications(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    /// Queue notifications for `name` if what it's queried as isn't `before` anymore.
+    fn notify(&mut self, namespace: &str, name: &str, before: Option<*const c_void>) {
+        let after = self.query(namespace, name);
+        let change = match (before, after) {
+            (None, Some(_)) => RegistryChange::Registered,
+            (Some(before), Some(after)) if before != after => RegistryChange::Replaced,
+            (Some(_), None) => RegistryChange::Removed,
+            _ => return,
+        };
+
+        let path = format!("{namespace}/{name}");
+        for subscription in &self.subscriptions {
+            if glob(&subscription.pattern, &path) {
+                self.notifications.push(Notification {
+                    subscription: subscription.id,
+                    owner: subscription.owner.clone(),
+                    callback: subscription.callback,
+                    context: subscription.context,
+                    change,
+                    entry: RegistryEntry {
+                        namespace: namespace.to_owned(),
+                        name: name.to_owned(),
+                        ptr: after.unwrap_or(std::ptr::null()),
+                    },
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::c_char;
+
+    fn ptr(value: usize) -> *const c_void {
+        value as *const c_void
+    }
+
+    #[test]
+    fn register_and_query() {
+        let mut registry = Registry::default();
+        registry
+            .register(Some("a"), "ns", "f", ptr(1), None)
+            .unwrap();
+        registry.register(None, "ns", "g", ptr(2), None).unwrap();
+
+        assert_eq!(registry.query("ns", "f"), Some(ptr(1)));
+        assert_eq!(registry.query("ns", "g"), Some(ptr(2)));
+        assert_eq!(registry.query("ns", "h"), None);
+        assert_eq!(
+            registry
+                .register(Some("b"), "ns", "g", ptr(3), None)
+                .unwrap_err()
+                .to_string(),
+            "it's already registered by an unknown mod"
+        );
+
+        let duplicate = registry.register(Some("b"), "ns", "f", ptr(3), None);
+        assert_eq!(
+            duplicate,
+            Err(RegistryError::Registered(Some(String::from("a"))))
+        );
+        assert_eq!(
+            duplicate.unwrap_err().to_string(),
+            "it's already registered by a"
+        );
+        assert_eq!(registry.query("ns", "f"), Some(ptr(1)));
+    }
+
+    #[test]
+    fn replace_by_priority() {
+        let mut registry = Registry::default();
+        registry
+            .register(Some("a"), "ns", "f", ptr(1), None)
+            .unwrap();
+
+        assert_eq!(
+            registry.replace("b", "ns", "f", ptr(2), 0, None),
+            Err(RegistryError::Outranked {
+                owner: Some(String::from("a")),
+                priority: 0
+            })
+        );
+        assert_eq!(
+            registry.replace("b", "ns", "f", ptr(2), 5, None),
+            Ok(Some(Some(String::from("a"))))
+        );
+        assert_eq!(registry.query("ns", "f"), Some(ptr(2)));
+
+        assert!(registry.replace("c", "ns", "f", ptr(3), 5, None).is_err());
+        registry.replace("c", "ns", "f", ptr(3), 10, None).unwrap();
+        assert_eq!(registry.query("ns", "f"), Some(ptr(3)));
+
+        // the owner of the top entry can always replace it
+        registry.replace("c", "ns", "f", ptr(4), 1, None).unwrap();
+        assert_eq!(registry.query("ns", "f"), Some(ptr(4)));
+
+        // nothing to replace
+        assert_eq!(registry.replace("d", "ns", "g", ptr(5), 0, None), Ok(None));
+    }
+
+    #[test]
+    fn unregister_uncovers() {
+        let mut registry = Registry::default();
+        registry
+            .register(Some("a"), "ns", "f", ptr(1), None)
+            .unwrap();
+        registry.replace("b", "ns", "f", ptr(2), 1, None).unwrap();
+        registry.replace("c", "ns", "f", ptr(3), 2, None).unwrap();
+
+        assert_eq!(
+            registry.unregister("d", "ns", "f"),
+            Err(RegistryError::NotRegistered)
+        );
+        registry.unregister("b", "ns", "f").unwrap();
+        assert_eq!(registry.query("ns", "f"), Some(ptr(3)));
+        registry.unregister("c", "ns", "f").unwrap();
+        assert_eq!(registry.query("ns", "f"), Some(ptr(1)));
+        registry.unregister("a", "ns", "f").unwrap();
+        assert_eq!(registry.query("ns", "f"), None);
+        assert!(registry.entries.is_empty());
+    }
+
+    #[test]
+    fn remove_owned() {
+        let mut registry = Registry::default();
+        registry
+            .register(Some("a"), "ns", "f", ptr(1), None)
+            .unwrap();
+        registry
+            .register(Some("a"), "ns", "g", ptr(2), None)
+            .unwrap();
+        registry.replace("b", "ns", "g", ptr(3), 1, None).unwrap();
+        registry
+            .register(Some("b"), "ns", "h", ptr(4), None)
+            .unwrap();
+
+        assert_eq!(registry.remove_owned_by("b"), 2);
+        assert_eq!(registry.query("ns", "g"), Some(ptr(2)));
+        assert_eq!(registry.query("ns", "h"), None);
+        assert_eq!(registry.remove_owned_by("b"), 0);
+    }
+
+    #[test]
+    fn typed() {
+        const FN: &str = "extern \"C\" fn(u32) -> *mut c_void";
+        const OTHER: &str = "extern \"C\" fn()";
+
+        let mut registry = Registry::default();
+        registry
+            .register(Some("a"), "ns", "f", ptr(1), Some(FN))
+            .unwrap();
+        registry
+            .register(Some("a"), "ns", "g", ptr(2), None)
+            .unwrap();
+
+        assert_eq!(registry.query_typed("ns", "f", FN), Ok(ptr(1)));
+        let mismatch = registry.query_typed("ns", "f", OTHER).unwrap_err();
+        assert_eq!(
+            mismatch.to_string(),
+            format!("a registered it as `{FN}`, not `{OTHER}`")
+        );
+        assert_eq!(
+            registry.query_typed("ns", "h", FN),
+            Err(RegistryError::Missing)
+        );
+
+        // untyped entries can't be checked
+        assert_eq!(registry.query_typed("ns", "g", OTHER), Ok(ptr(2)));
+
+        assert!(matches!(
+            registry.replace("b", "ns", "f", ptr(3), 1, Some(OTHER)),
+            Err(RegistryError::TypeMismatch { .. })
+        ));
+        registry.replace("b", "ns", "f", ptr(3), 1, None).unwrap();
+        registry
+            .replace("c", "ns", "f", ptr(4), 2, Some(FN))
+            .unwrap();
+        assert_eq!(registry.query_typed("ns", "f", FN), Ok(ptr(4)));
+    }
+
+    #[test]
+    fn listing() {
+        let mut registry = Registry::default();
+        registry
+            .register(Some("a"), "game/functions", "Player/Update", ptr(1), None)
+            .unwrap();
+        registry
+            .register(Some("a"), "game/functions", "Player/Draw", ptr(2), None)
+            .unwrap();
+        registry
+            .register(Some("a"), "game/functions", "World/Tick", ptr(3), None)
+            .unwrap();
+        registry
+            .register(Some("a"), "game/variables", "Player", ptr(4), None)
+            .unwrap();
+        registry
+            .replace("b", "game/functions", "World/Tick", ptr(5), 1, None)
+            .unwrap();
+
+        assert_eq!(registry.namespaces(), ["game/functions", "game/variables"]);
+
+        let names: Vec<_> = registry
+            .names("game/functions")
+            .into_iter()
+            .map(|entry| (entry.name, entry.ptr))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (String::from("Player/Draw"), ptr(2)),
+                (String::from("Player/Update"), ptr(1)),
+                (String::from("World/Tick"), ptr(5)),
+            ]
+        );
+        assert!(registry.names("game").is_empty());
+
+        let paths: Vec<_> = registry
+            .glob("game/*Player*")
+            .iter()
+            .map(RegistryEntry::path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "game/functions/Player/Draw",
+                "game/functions/Player/Update",
+                "game/variables/Player"
+            ]
+        );
+        assert_eq!(registry.glob("game/functions/W*").len(), 1);
+    }
+
+    extern "C" fn ignore(
+        _context: *mut c_void,
+        _change: RegistryChange,
+        _namespace: *const c_char,
+        _name: *const c_char,
+        _ptr: *const c_void,
+    ) {
+    }
+
+    fn changes(registry: &mut Registry) -> Vec<(usize, RegistryChange, String, *const c_void)> {
+        registry
+            .take_notifications()
+            .into_iter()
+            .map(|n| (n.context as usize, n.change, n.entry.path(), n.entry.ptr))
+            .collect()
+    }
+
+    #[test]
+    fn notifications() {
+        use RegistryChange::*;
+
+        let mut registry = Registry::default();
+        registry
+            .register(Some("a"), "ns", "f", ptr(1), None)
+            .unwrap();
+        let exact = registry.subscribe("b", "ns/f", ignore, std::ptr::dangling_mut::<c_void>());
+        let prefix = registry.subscribe("c", "ns/*", ignore, 2 as *mut c_void);
+        assert_ne!(exact, prefix);
+
+        // what's already there
+        assert_eq!(
+            changes(&mut registry),
+            [
+                (1, Registered, String::from("ns/f"), ptr(1)),
+                (2, Registered, String::from("ns/f"), ptr(1)),
+            ]
+        );
+
+        registry
+            .register(Some("a"), "ns", "g", ptr(2), None)
+            .unwrap();
+        registry
+            .register(Some("a"), "other", "f", ptr(3), None)
+            .unwrap();
+        assert_eq!(
+            changes(&mut registry),
+            [(2, Registered, String::from("ns/g"), ptr(2))]
+        );
+
+        registry.replace("d", "ns", "f", ptr(4), 1, None).unwrap();
+        registry.unregister("d", "ns", "f").unwrap();
+        assert_eq!(
+            changes(&mut registry),
+            [
+                (1, Replaced, String::from("ns/f"), ptr(4)),
+                (2, Replaced, String::from("ns/f"), ptr(4)),
+                (1, Replaced, String::from("ns/f"), ptr(1)),
+                (2, Replaced, String::from("ns/f"), ptr(1)),
+            ]
+        );
+
+        // failed registrations change nothing
+        assert!(
+            registry
+                .register(Some("d"), "ns", "f", ptr(5), None)
+                .is_err()
+        );
+        assert!(changes(&mut registry).is_empty());
+
+        assert_eq!(
+            registry.unsubscribe("c", exact),
+            Err(RegistryError::NotSubscribed)
+        );
+        registry.unsubscribe("b", exact).unwrap();
+        assert_eq!(registry.remove_owned_by("a"), 3);
+        let mut removed = changes(&mut registry);
+        removed.sort_by(|a, b| a.2.cmp(&b.2));
+        assert_eq!(
+            removed,
+            [
+                (2, Removed, String::from("ns/f"), std::ptr::null()),
+                (2, Removed, String::from("ns/g"), std::ptr::null()),
+            ]
+        );
+    }
+}
@@ -13,7 +13,7 @@ pub(crate) fn message_box(title: &str, text: &str, icon: u32) {
 }
 
 #[cfg(not(windows))]
-pub(crate) fn message_box(title: &str, text: &str, icon: u32) {
+pub(crate) fn message_box(_title: &str, _text: &str, _icon: u32) {
     unimplemented!()
 }
 
@@ -30,6 +30,6 @@ pub(crate) fn alloc_console(title: &str) {
 }
 
 #[cfg(not(windows))]
-pub(crate) fn alloc_console(title: &str) {
+pub(crate) fn alloc_console(_title: &str) {
     unimplemented!()
 }
@@ -0,0 +1,131 @@
+//! Procedural macros for `cauldron`, use them through its re-exports.
+
+use cauldron_pattern::{PatternByte, code_pattern, parse_escapes, parse_pattern};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{Lit, LitStr, Token};
+
+enum SigInput {
+    Ida(LitStr),
+    Code(Lit, LitStr),
+}
+
+impl Parse for SigInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let pattern: Lit = input.parse()?;
+        if input.is_empty() {
+            return match pattern {
+                Lit::Str(pattern) => Ok(SigInput::Ida(pattern)),
+                other => Err(syn::Error::new(
+                    other.span(),
+                    "expected an IDA-style pattern string, or bytes and a mask",
+                )),
+            };
+        }
+
+        input.parse::<Token![,]>()?;
+        let mask: LitStr = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+        Ok(SigInput::Code(pattern, mask))
+    }
+}
+
+fn parse_input(input: &SigInput) -> Result<Vec<PatternByte>, (Span, String)> {
+    let (bytes, span) = match input {
+        SigInput::Ida(pattern) => (parse_pattern(&pattern.value()), pattern.span()),
+        SigInput::Code(bytes, mask) => {
+            let values = match bytes {
+                Lit::ByteStr(bytes) => bytes.value(),
+                Lit::Str(bytes) => {
+                    parse_escapes(&bytes.value()).map_err(|e| (bytes.span(), e.to_string()))?
+                }
+                other => {
+                    return Err((
+                        other.span(),
+                        String::from("expected a byte string or a string of \\x escapes"),
+                    ));
+                }
+            };
+            (code_pattern(&values, &mask.value()), mask.span())
+        }
+    };
+
+    match bytes {
+        Ok(bytes) if bytes.is_empty() => Err((span, String::from("pattern is empty"))),
+        Ok(bytes) => Ok(bytes),
+        Err(e) => Err((span, e.to_string())),
+    }
+}
+
+/// A signature checked at compile time, expands to a
+/// `&'static [cauldron::mem::pattern::PatternByte]`.
+///
+/// Takes either an IDA-style pattern, `sig!("48 8B 0D ? ? ? ? 4?")`, or code-style bytes and a
+/// mask, `sig!(b"\x48\x8B\x0D\x00", "xxx?")` or `sig!(r"\x48\x8B\x0D\x00", "xxx?")`.
+#[proc_macro]
+pub fn sig(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as SigInput);
+    let bytes = match parse_input(&input) {
+        Ok(bytes) => bytes,
+        Err((span, message)) => {
+            return syn::Error::new(span, format!("invalid signature: {message}"))
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let bytes = bytes.iter().map(|PatternByte { value, mask }| {
+        quote! { ::cauldron::mem::pattern::PatternByte { value: #value, mask: #mask } }
+    });
+    quote! {
+        {
+            const SIGNATURE: &[::cauldron::mem::pattern::PatternByte] = &[#(#bytes),*];
+            SIGNATURE
+        }
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Vec<PatternByte>, String> {
+        parse_input(&syn::parse_str(input).unwrap()).map_err(|(_, e)| e)
+    }
+
+    #[test]
+    fn inputs() {
+        assert_eq!(
+            parse(r#""48 ?B""#),
+            parse_pattern("48 ?B").map_err(|e| e.to_string())
+        );
+        assert_eq!(
+            parse(r#"b"\x48\x8B", "x?""#),
+            code_pattern(b"\x48\x8B", "x?").map_err(|e| e.to_string())
+        );
+        assert_eq!(
+            parse(r#"r"\x48\x8B", "x?""#),
+            code_pattern(b"\x48\x8B", "x?").map_err(|e| e.to_string())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse(r#""48 8G""#),
+            Err(String::from("invalid character 'G' at position 4"))
+        );
+        assert_eq!(parse(r#"" ""#), Err(String::from("pattern is empty")));
+        assert_eq!(
+            parse(r#"r"\x48x8B", "xx""#),
+            Err(String::from("invalid \\x escape at position 4"))
+        );
+        assert_eq!(
+            parse(r#"b"\x48", "xx""#),
+            Err(String::from("mask is 2 long but the pattern has 1 bytes"))
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Byte pattern parsing, shared by `cauldron::mem::pattern` at runtime and `sig!` at compile time.
+//!
+//! Two formats are supported:
+//! - IDA-style, e.g. `48 8B ? ?? 4? 05`, where `?` and `??` match any byte and a `?` in one half of
+//!   a byte matches any nibble. Bytes can be written without spaces, e.g. `488B??05`.
+//! - Code-style, e.g. `\x48\x8B\x00\x05` with the mask `xx?x`, where `?` in the mask matches any
+//!   byte.
+
+use thiserror::Error;
+
+/// A byte in a pattern, which matches any byte equal to `value` under `mask`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PatternByte {
+    pub value: u8,
+    pub mask: u8,
+}
+
+impl PatternByte {
+    pub const WILDCARD: Self = PatternByte {
+        value: 0x00,
+        mask: 0x00,
+    };
+
+    pub fn exact(value: u8) -> Self {
+        PatternByte { value, mask: 0xFF }
+    }
+
+    pub fn matches(&self, byte: u8) -> bool {
+        byte & self.mask == self.value
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.mask == 0xFF
+    }
+
+    pub fn is_wildcard(&self) -> bool {
+        self.mask == 0x00
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum ParseError {
+    #[error("invalid character {character:?} at position {position}")]
+    InvalidCharacter { position: usize, character: char },
+    #[error("odd number of digits ({length}) in the byte at position {position}")]
+    OddLength { position: usize, length: usize },
+    #[error("invalid \\x escape at position {position}")]
+    InvalidEscape { position: usize },
+    #[error("invalid mask character {character:?} at position {position}")]
+    InvalidMask { position: usize, character: char },
+    #[error("mask is {mask} long but the pattern has {bytes} bytes")]
+    MaskLength { bytes: usize, mask: usize },
+}
+
+/// Parse an IDA-style pattern.
+pub fn parse_pattern(pattern: &str) -> Result<Vec<PatternByte>, ParseError> {
+    let mut bytes = Vec::new();
+    let mut chars = pattern.char_indices().peekable();
+
+    while let Some(&(start, _)) = chars.peek() {
+        let mut token = Vec::new();
+        while let Some(&(position, c)) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            token.push((position, c));
+            chars.next();
+        }
+
+        match token.as_slice() {
+            [] => {
+                chars.next();
+            }
+            [(_, '?')] => bytes.push(PatternByte::WILDCARD),
+            _ if token.len() % 2 == 1 => {
+                return Err(ParseError::OddLength {
+                    position: start,
+                    length: token.len(),
+                });
+            }
+            _ => {
+                for pair in token.chunks(2) {
+                    let high = parse_nibble(pair[0])?;
+                    let low = parse_nibble(pair[1])?;
+                    bytes.push(PatternByte {
+                        value: (high.0 << 4) | low.0,
+                        mask: (high.1 << 4) | low.1,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// `(value, mask)` of a hex digit or `?`.
+fn parse_nibble((position, c): (usize, char)) -> Result<(u8, u8), ParseError> {
+    if c == '?' {
+        return Ok((0x0, 0x0));
+    }
+
+    match c.to_digit(16) {
+        Some(value) => Ok((value as u8, 0xF)),
+        None => Err(ParseError::InvalidCharacter {
+            position,
+            character: c,
+        }),
+    }
+}
+
+/// Parse a code-style pattern, `bytes` written as `\x48\x8B` escapes and `mask` made of `x`
+/// (match) and `?` (any byte).
+pub fn parse_code_pattern(bytes: &str, mask: &str) -> Result<Vec<PatternByte>, ParseError> {
+    code_pattern(&parse_escapes(bytes)?, mask)
+}
+
+/// Parse `\x48\x8B` escapes written out in a string.
+pub fn parse_escapes(bytes: &str) -> Result<Vec<u8>, ParseError> {
+    let mut values = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let position = bytes.len() - rest.len();
+        let escape = rest
+            .strip_prefix("\\x")
+            .and_then(|r| r.get(..2))
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        match escape {
+            Some(value) => {
+                values.push(value);
+                rest = &rest[4..];
+            }
+            None => return Err(ParseError::InvalidEscape { position }),
+        }
+    }
+    Ok(values)
+}
+
+/// Combine raw bytes with a code-style mask, e.g. `b"\x48\x8B\x00"` and `xx?`.
+pub fn code_pattern(bytes: &[u8], mask: &str) -> Result<Vec<PatternByte>, ParseError> {
+    let mask_length = mask.chars().count();
+    if bytes.len() != mask_length {
+        return Err(ParseError::MaskLength {
+            bytes: bytes.len(),
+            mask: mask_length,
+        });
+    }
+
+    bytes
+        .iter()
+        .zip(mask.char_indices())
+        .map(|(&value, (position, c))| match c {
+            'x' | 'X' => Ok(PatternByte::exact(value)),
+            '?' => Ok(PatternByte::WILDCARD),
+            _ => Err(ParseError::InvalidMask {
+                position,
+                character: c,
+            }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact(value: u8) -> PatternByte {
+        PatternByte::exact(value)
+    }
+
+    const ANY: PatternByte = PatternByte::WILDCARD;
+
+    #[test]
+    fn ida() {
+        assert_eq!(
+            parse_pattern("48 8B ? ?? 05").unwrap(),
+            vec![exact(0x48), exact(0x8B), ANY, ANY, exact(0x05)]
+        );
+        assert_eq!(
+            parse_pattern("  48\t8b  ").unwrap(),
+            vec![exact(0x48), exact(0x8B)]
+        );
+        assert_eq!(parse_pattern(" ").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn unspaced() {
+        assert_eq!(
+            parse_pattern("488B??05").unwrap(),
+            vec![exact(0x48), exact(0x8B), ANY, exact(0x05)]
+        );
+    }
+
+    #[test]
+    fn nibbles() {
+        let pattern = parse_pattern("4? ?B").unwrap();
+        assert_eq!(
+            pattern,
+            vec![
+                PatternByte {
+                    value: 0x40,
+                    mask: 0xF0
+                },
+                PatternByte {
+                    value: 0x0B,
+                    mask: 0x0F
+                },
+            ]
+        );
+        assert!(pattern[0].matches(0x48));
+        assert!(pattern[0].matches(0x4F));
+        assert!(!pattern[0].matches(0x58));
+        assert!(pattern[1].matches(0x8B));
+        assert!(!pattern[1].matches(0x8C));
+    }
+
+    #[test]
+    fn ida_errors() {
+        assert_eq!(
+            parse_pattern("48 8G"),
+            Err(ParseError::InvalidCharacter {
+                position: 4,
+                character: 'G'
+            })
+        );
+        assert_eq!(
+            parse_pattern("48 8B 5 C3"),
+            Err(ParseError::OddLength {
+                position: 6,
+                length: 1
+            })
+        );
+        assert_eq!(
+            parse_pattern("48 ???"),
+            Err(ParseError::OddLength {
+                position: 3,
+                length: 3
+            })
+        );
+        assert_eq!(
+            parse_pattern("48 8G").unwrap_err().to_string(),
+            "invalid character 'G' at position 4"
+        );
+    }
+
+    #[test]
+    fn code() {
+        assert_eq!(
+            parse_code_pattern(r"\x48\x8B\x00\x05", "xx?x").unwrap(),
+            vec![exact(0x48), exact(0x8B), ANY, exact(0x05)]
+        );
+        assert_eq!(
+            code_pattern(b"\x48\x8B\x00", "xx?").unwrap(),
+            vec![exact(0x48), exact(0x8B), ANY]
+        );
+    }
+
+    #[test]
+    fn code_errors() {
+        assert_eq!(
+            parse_code_pattern(r"\x48\x8", "xx"),
+            Err(ParseError::InvalidEscape { position: 4 })
+        );
+        assert_eq!(
+            parse_code_pattern(r"\x48x8B", "xx"),
+            Err(ParseError::InvalidEscape { position: 4 })
+        );
+        assert_eq!(
+            parse_code_pattern(r"\x48\x8B", "x"),
+            Err(ParseError::MaskLength { bytes: 2, mask: 1 })
+        );
+        assert_eq!(
+            code_pattern(b"\x48\x8B", "x."),
+            Err(ParseError::InvalidMask {
+                position: 1,
+                character: '.'
+            })
+        );
+    }
+}
@@ -0,0 +1,682 @@
+//! Mod dependency resolution.
+//!
+//! Validates the versions and dependency constraints of every discovered mod and produces a load
+//! order in which each mod comes after everything it depends on.
+
+use cauldron::mod_info::SafeCauldronModInfo;
+use semver::{Version, VersionReq};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+
+/// A problem found while resolving the mod set.
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq)]
+pub enum ResolveError {
+    #[error(
+        "{name} has an invalid version string, \"{version}\", must be semver compliant: {error}"
+    )]
+    InvalidVersion {
+        name: String,
+        version: String,
+        error: String,
+    },
+
+    #[error(
+        "{name} has an invalid dependency version constraint for {dependency}: \"{constraint}\": {error}"
+    )]
+    InvalidConstraint {
+        name: String,
+        dependency: String,
+        constraint: String,
+        error: String,
+    },
+
+    #[error("{name} is provided by more than one mod.")]
+    DuplicateMod { name: String },
+
+    #[error("{name} requires {dependency} {constraint} but it is not present.")]
+    MissingDependency {
+        name: String,
+        dependency: String,
+        constraint: VersionReq,
+    },
+
+    #[error("{name} depends on {dependency} {constraint} but {dependency} v{found} is present.")]
+    VersionMismatch {
+        name: String,
+        dependency: String,
+        constraint: VersionReq,
+        found: Version,
+    },
+
+    #[error("circular dependencies: {}", .path.join(" -> "))]
+    Cycle {
+        /// Mod names along the cycle, the first name is repeated at the end.
+        path: Vec<String>,
+    },
+}
+
+/// Why a mod was left out of the load order.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SkipReason {
+    /// The mod itself failed to resolve.
+    Failed(Vec<ResolveError>),
+    /// A required dependency of the mod was skipped.
+    Dependency(String),
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Failed(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{error}")?;
+                }
+                Ok(())
+            }
+            SkipReason::Dependency(dependency) => {
+                write!(f, "its dependency {dependency} was skipped.")
+            }
+        }
+    }
+}
+
+/// A mod that was left out of the load order.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SkippedMod {
+    /// Index into the resolved mods.
+    pub index: usize,
+    pub reason: SkipReason,
+}
+
+/// The outcome of [resolve].
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    /// Indices into the resolved mods, in load order.
+    pub order: Vec<usize>,
+    /// Every problem found while resolving, empty if all mods resolved cleanly.
+    pub errors: Vec<ResolveError>,
+    /// Mods left out of [`order`](Resolution::order), sorted by index.
+    pub skipped: Vec<SkippedMod>,
+}
+
+impl Resolution {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Resolve a load order for `mods`.
+///
+/// The running game is treated as a pseudo-mod named `game` so mods can depend on a game version.
+///
+/// A mod with any [ResolveError] is skipped, along with every mod that requires it. Optional
+/// dependencies on a skipped mod are treated as if the mod wasn't present.
+pub fn resolve(game: &str, game_version: &Version, mods: &[SafeCauldronModInfo]) -> Resolution {
+    let mut errors = Vec::new();
+    let mut skipped: Vec<Option<SkipReason>> = vec![None; mods.len()];
+
+    // `None` is the game itself.
+    let mut owners: HashMap<&str, Option<usize>> = HashMap::new();
+    let mut versions: HashMap<&str, Version> = HashMap::new();
+
+    owners.insert(game, None);
+    versions.insert(game, game_version.clone());
+
+    let mut duplicates: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, mod_info) in mods.iter().enumerate() {
+        let name = mod_info.name.as_str();
+        if let Some(owner) = owners.get(name) {
+            duplicates
+                .entry(name)
+                .or_insert_with(|| owner.iter().copied().collect())
+                .push(index);
+            continue;
+        }
+
+        owners.insert(name, Some(index));
+        match Version::parse(&mod_info.version) {
+            Ok(version) => {
+                versions.insert(name, version);
+            }
+            Err(e) => fail(
+                &mut errors,
+                &mut skipped,
+                &[index],
+                ResolveError::InvalidVersion {
+                    name: name.to_owned(),
+                    version: mod_info.version.clone(),
+                    error: e.to_string(),
+                },
+            ),
+        }
+    }
+
+    // there's no telling which copy is the right one, so none of them are loaded
+    for (name, indices) in duplicates {
+        fail(
+            &mut errors,
+            &mut skipped,
+            &indices,
+            ResolveError::DuplicateMod {
+                name: name.to_owned(),
+            },
+        );
+    }
+
+    // depends_on[a] contains b if a must be loaded after b.
+    let mut depends_on: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); mods.len()];
+    let mut requires: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); mods.len()];
+    for (index, mod_info) in mods.iter().enumerate() {
+        if owners.get(mod_info.name.as_str()) != Some(&Some(index)) {
+            continue;
+        }
+
+        for dep in &mod_info.dependencies {
+            let constraint = match &dep.version {
+                None => VersionReq::STAR,
+                Some(constraint) => match VersionReq::parse(constraint) {
+                    Ok(constraint) => constraint,
+                    Err(e) => {
+                        fail(
+                            &mut errors,
+                            &mut skipped,
+                            &[index],
+                            ResolveError::InvalidConstraint {
+                                name: mod_info.name.clone(),
+                                dependency: dep.name.clone(),
+                                constraint: constraint.clone(),
+                                error: e.to_string(),
+                            },
+                        );
+                        continue;
+                    }
+                },
+            };
+
+            let Some(owner) = owners.get(dep.name.as_str()) else {
+                if !dep.optional {
+                    fail(
+                        &mut errors,
+                        &mut skipped,
+                        &[index],
+                        ResolveError::MissingDependency {
+                            name: mod_info.name.clone(),
+                            dependency: dep.name.clone(),
+                            constraint,
+                        },
+                    );
+                }
+                continue;
+            };
+
+            // present optional dependencies must still satisfy their constraint
+            if let Some(found) = versions.get(dep.name.as_str())
+                && !constraint.matches(found)
+            {
+                fail(
+                    &mut errors,
+                    &mut skipped,
+                    &[index],
+                    ResolveError::VersionMismatch {
+                        name: mod_info.name.clone(),
+                        dependency: dep.name.clone(),
+                        constraint,
+                        found: found.clone(),
+                    },
+                );
+            }
+
+            if let Some(owner) = owner {
+                depends_on[index].insert(*owner);
+                if !dep.optional {
+                    requires[index].insert(*owner);
+                }
+            }
+        }
+    }
+
+    // each pass either orders every remaining mod or fails at least one more on a cycle
+    let order = loop {
+        skip_dependents(mods, &requires, &mut skipped);
+
+        let disabled: Vec<bool> = skipped.iter().map(Option::is_some).collect();
+        let order = topological_order(&depends_on, &disabled);
+        if order.len() == disabled.iter().filter(|d| !**d).count() {
+            break order;
+        }
+
+        let mut ordered = disabled;
+        for &index in &order {
+            ordered[index] = true;
+        }
+        for path in find_cycles(&depends_on, &ordered) {
+            fail(
+                &mut errors,
+                &mut skipped,
+                &path[1..],
+                ResolveError::Cycle {
+                    path: path.iter().map(|&i| mods[i].name.clone()).collect(),
+                },
+            );
+        }
+    };
+
+    let skipped = skipped
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, reason)| reason.map(|reason| SkippedMod { index, reason }))
+        .collect();
+
+    Resolution {
+        order,
+        errors,
+        skipped,
+    }
+}
+
+/// Record `error` against every mod in `indices`.
+fn fail(
+    errors: &mut Vec<ResolveError>,
+    skipped: &mut [Option<SkipReason>],
+    indices: &[usize],
+    error: ResolveError,
+) {
+    for &index in indices {
+        match &mut skipped[index] {
+            Some(SkipReason::Failed(errors)) => errors.push(error.clone()),
+            reason => *reason = Some(SkipReason::Failed(vec![error.clone()])),
+        }
+    }
+    errors.push(error);
+}
+
+/// Skip every mod that requires a skipped mod, directly or transitively.
+fn skip_dependents(
+    mods: &[SafeCauldronModInfo],
+    requires: &[BTreeSet<usize>],
+    skipped: &mut [Option<SkipReason>],
+) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..mods.len() {
+            if skipped[index].is_some() {
+                continue;
+            }
+
+            if let Some(&dep) = requires[index].iter().find(|&&dep| skipped[dep].is_some()) {
+                skipped[index] = Some(SkipReason::Dependency(mods[dep].name.clone()));
+                changed = true;
+            }
+        }
+    }
+}
+
+/// Kahn's algorithm, ties are broken by index so the input order is kept where possible.
+fn topological_order(depends_on: &[BTreeSet<usize>], disabled: &[bool]) -> Vec<usize> {
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); depends_on.len()];
+    let mut remaining: Vec<usize> = vec![0; depends_on.len()];
+    for (index, deps) in depends_on.iter().enumerate() {
+        if disabled[index] {
+            continue;
+        }
+        for &dep in deps.iter().filter(|&&dep| !disabled[dep]) {
+            remaining[index] += 1;
+            dependents[dep].push(index);
+        }
+    }
+
+    let mut ready: BTreeSet<usize> = (0..depends_on.len())
+        .filter(|&i| !disabled[i] && remaining[i] == 0)
+        .collect();
+
+    let mut order = Vec::with_capacity(depends_on.len());
+    while let Some(index) = ready.pop_first() {
+        order.push(index);
+        for &dependent in &dependents[index] {
+            remaining[dependent] -= 1;
+            if remaining[dependent] == 0 {
+                ready.insert(dependent);
+            }
+        }
+    }
+
+    order
+}
+
+/// Find the cycles among the mods that couldn't be ordered.
+///
+/// Each cycle is returned as a path that starts and ends on the same mod.
+fn find_cycles(depends_on: &[BTreeSet<usize>], ordered: &[bool]) -> Vec<Vec<usize>> {
+    #[derive(Copy, Clone, Eq, PartialEq)]
+    enum Mark {
+        Unvisited,
+        OnStack,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        depends_on: &[BTreeSet<usize>],
+        marks: &mut [Mark],
+        stack: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<usize>>,
+    ) {
+        marks[index] = Mark::OnStack;
+        stack.push(index);
+
+        for &dep in &depends_on[index] {
+            match marks[dep] {
+                Mark::Unvisited => visit(dep, depends_on, marks, stack, cycles),
+                Mark::OnStack => {
+                    let start = stack.iter().position(|&i| i == dep).unwrap();
+                    let mut path = stack[start..].to_vec();
+                    path.push(dep);
+                    cycles.push(path);
+                }
+                Mark::Done => {}
+            }
+        }
+
+        stack.pop();
+        marks[index] = Mark::Done;
+    }
+
+    // ordered and skipped mods can't be part of a cycle
+    let mut marks: Vec<Mark> = (0..depends_on.len())
+        .map(|i| {
+            if ordered[i] {
+                Mark::Done
+            } else {
+                Mark::Unvisited
+            }
+        })
+        .collect();
+
+    let mut cycles = Vec::new();
+    let mut stack = Vec::new();
+    for index in 0..depends_on.len() {
+        if marks[index] == Mark::Unvisited {
+            visit(index, depends_on, &mut marks, &mut stack, &mut cycles);
+        }
+    }
+
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cauldron::mod_info::SafeCauldronModDependency;
+
+    fn game_version() -> Version {
+        Version::new(1, 5, 80)
+    }
+
+    fn mod_info(
+        name: &str,
+        version: &str,
+        deps: &[(&str, Option<&str>, bool)],
+    ) -> SafeCauldronModInfo {
+        SafeCauldronModInfo {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            display_name: None,
+            description: None,
+            homepage_url: None,
+            source_url: None,
+            issue_tracker_url: None,
+            authors: Vec::new(),
+            dependencies: deps
+                .iter()
+                .map(|(name, version, optional)| SafeCauldronModDependency {
+                    name: (*name).to_owned(),
+                    version: version.map(str::to_owned),
+                    optional: *optional,
+                })
+                .collect(),
+        }
+    }
+
+    fn names(mods: &[SafeCauldronModInfo], resolution: &Resolution) -> Vec<String> {
+        resolution
+            .order
+            .iter()
+            .map(|&i| mods[i].name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn independent_mods_keep_input_order() {
+        let mods = vec![
+            mod_info("c", "1.0.0", &[]),
+            mod_info("a", "1.0.0", &[]),
+            mod_info("b", "1.0.0", &[]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert!(resolution.is_ok());
+        assert_eq!(names(&mods, &resolution), ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn transitive_chain() {
+        // a -> b -> c, given in the worst possible order for a pairwise sort
+        let mods = vec![
+            mod_info("a", "1.0.0", &[("b", None, false)]),
+            mod_info("x", "1.0.0", &[]),
+            mod_info("b", "1.0.0", &[("c", None, false)]),
+            mod_info("c", "1.0.0", &[]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert!(resolution.is_ok());
+        assert_eq!(names(&mods, &resolution), ["x", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn diamond() {
+        let mods = vec![
+            mod_info(
+                "top",
+                "1.0.0",
+                &[("left", None, false), ("right", None, false)],
+            ),
+            mod_info("left", "1.0.0", &[("base", None, false)]),
+            mod_info("right", "1.0.0", &[("base", None, false)]),
+            mod_info("base", "1.0.0", &[]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert!(resolution.is_ok());
+        assert_eq!(names(&mods, &resolution), ["base", "left", "right", "top"]);
+    }
+
+    #[test]
+    fn game_dependency() {
+        let mods = vec![
+            mod_info("ok", "1.0.0", &[("hfw", Some(">=1.5.80"), false)]),
+            mod_info("old", "1.0.0", &[("hfw", Some("<1.5.0"), false)]),
+            mod_info("other", "1.0.0", &[("hzd", None, false)]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert_eq!(
+            resolution.errors,
+            [
+                ResolveError::VersionMismatch {
+                    name: "old".into(),
+                    dependency: "hfw".into(),
+                    constraint: VersionReq::parse("<1.5.0").unwrap(),
+                    found: game_version(),
+                },
+                ResolveError::MissingDependency {
+                    name: "other".into(),
+                    dependency: "hzd".into(),
+                    constraint: VersionReq::STAR,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_dependencies() {
+        let mods = vec![
+            mod_info("a", "1.0.0", &[("missing", None, true), ("b", None, true)]),
+            mod_info("b", "1.0.0", &[]),
+            mod_info("c", "1.0.0", &[("b", Some("^2"), true)]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert_eq!(names(&mods, &resolution), ["b", "a"]);
+        assert_eq!(
+            resolution.errors,
+            [ResolveError::VersionMismatch {
+                name: "c".into(),
+                dependency: "b".into(),
+                constraint: VersionReq::parse("^2").unwrap(),
+                found: Version::new(1, 0, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn invalid_versions() {
+        let mods = vec![
+            mod_info("a", "one", &[]),
+            mod_info("b", "1.0.0", &[("a", Some("not a constraint"), false)]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert_eq!(resolution.errors.len(), 2);
+        assert!(matches!(
+            &resolution.errors[0],
+            ResolveError::InvalidVersion { name, .. } if name == "a"
+        ));
+        assert!(matches!(
+            &resolution.errors[1],
+            ResolveError::InvalidConstraint { name, dependency, .. } if name == "b" && dependency == "a"
+        ));
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let mods = vec![
+            mod_info("a", "1.0.0", &[]),
+            mod_info("a", "2.0.0", &[]),
+            mod_info("a", "3.0.0", &[]),
+            mod_info("hfw", "1.0.0", &[]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert!(resolution.order.is_empty());
+        assert_eq!(
+            resolution.errors,
+            [
+                ResolveError::DuplicateMod { name: "a".into() },
+                ResolveError::DuplicateMod { name: "hfw".into() },
+            ]
+        );
+        assert_eq!(
+            resolution
+                .skipped
+                .iter()
+                .map(|s| s.index)
+                .collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn cycle_path() {
+        let mods = vec![
+            mod_info("a", "1.0.0", &[("b", None, false)]),
+            mod_info("b", "1.0.0", &[("c", None, false)]),
+            mod_info("c", "1.0.0", &[("a", None, false)]),
+            mod_info("d", "1.0.0", &[("a", None, false)]),
+            mod_info("e", "1.0.0", &[("b", None, true)]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert_eq!(names(&mods, &resolution), ["e"]);
+        assert_eq!(resolution.skipped.len(), 4);
+        assert_eq!(
+            resolution.skipped[3],
+            SkippedMod {
+                index: 3,
+                reason: SkipReason::Dependency("a".into()),
+            }
+        );
+        assert_eq!(
+            resolution.errors,
+            [ResolveError::Cycle {
+                path: vec!["a".into(), "b".into(), "c".into(), "a".into()],
+            }]
+        );
+        assert_eq!(
+            resolution.errors[0].to_string(),
+            "circular dependencies: a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn self_dependency() {
+        let mods = vec![mod_info("a", "1.0.0", &[("a", None, false)])];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert!(resolution.order.is_empty());
+        assert_eq!(
+            resolution.errors,
+            [ResolveError::Cycle {
+                path: vec!["a".into(), "a".into()],
+            }]
+        );
+    }
+
+    #[test]
+    fn skip_dependents() {
+        let mods = vec![
+            mod_info("base", "1.0.0", &[("hfw", Some("<1.0.0"), false)]),
+            mod_info("middle", "1.0.0", &[("base", None, false)]),
+            mod_info("top", "1.0.0", &[("middle", None, false)]),
+            mod_info("soft", "1.0.0", &[("middle", None, true)]),
+            mod_info("unrelated", "1.0.0", &[]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert_eq!(names(&mods, &resolution), ["soft", "unrelated"]);
+        assert_eq!(resolution.errors.len(), 1);
+        assert_eq!(
+            resolution.skipped,
+            [
+                SkippedMod {
+                    index: 0,
+                    reason: SkipReason::Failed(resolution.errors.clone()),
+                },
+                SkippedMod {
+                    index: 1,
+                    reason: SkipReason::Dependency("base".into()),
+                },
+                SkippedMod {
+                    index: 2,
+                    reason: SkipReason::Dependency("middle".into()),
+                },
+            ]
+        );
+        assert_eq!(
+            resolution.skipped[2].reason.to_string(),
+            "its dependency middle was skipped."
+        );
+    }
+
+    #[test]
+    fn optional_cycle() {
+        // the cycle is only closed by an optional dependency, both mods are still skipped
+        let mods = vec![
+            mod_info("a", "1.0.0", &[("b", None, true)]),
+            mod_info("b", "1.0.0", &[("a", None, false)]),
+            mod_info("c", "1.0.0", &[("a", None, true)]),
+        ];
+        let resolution = resolve("hfw", &game_version(), &mods);
+        assert_eq!(names(&mods, &resolution), ["c"]);
+        assert_eq!(
+            resolution.errors,
+            [ResolveError::Cycle {
+                path: vec!["a".into(), "b".into(), "a".into()],
+            }]
+        );
+    }
+}
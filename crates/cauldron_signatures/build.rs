@@ -0,0 +1,52 @@
+//! Checks `signatures.toml` when building, so a mistake in it breaks the build instead of the game.
+
+use cauldron_game_detection::Game;
+use semver::VersionReq;
+use toml::{Table, Value};
+
+fn main() {
+    println!("cargo:rerun-if-changed=signatures.toml");
+
+    let source = std::fs::read_to_string("signatures.toml").unwrap();
+    if let Err(error) = check(&source) {
+        panic!("invalid signatures.toml: {error}");
+    }
+}
+
+/// The checks `Database::parse` makes when the database is loaded.
+fn check(source: &str) -> Result<(), String> {
+    let file: Table = toml::from_str(source).map_err(|e| e.to_string())?;
+    let tables = file.get("game").and_then(Value::as_array);
+    for table in tables.into_iter().flatten() {
+        let game = table
+            .get("game")
+            .and_then(Value::as_str)
+            .ok_or("a [[game]] table has no game")?;
+        Game::from_code(game).ok_or_else(|| format!("unknown game \"{game}\""))?;
+        let versions = table
+            .get("versions")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("a {game} table has no versions"))?;
+        VersionReq::parse(versions)
+            .map_err(|e| format!("invalid versions for {game}, \"{versions}\": {e}"))?;
+
+        let addresses = table.get("addresses").and_then(Value::as_table);
+        for (name, entry) in addresses.into_iter().flatten() {
+            match entry.get("signature").map(Value::as_str) {
+                Some(Some(signature)) => match cauldron_pattern::parse_pattern(signature) {
+                    Ok(bytes) if bytes.is_empty() => {
+                        return Err(format!("{name} has an empty signature"));
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Err(format!("{name} has an invalid signature: {e}")),
+                },
+                Some(None) => return Err(format!("{name}'s signature isn't a string")),
+                None if entry.get("rva").is_none() => {
+                    return Err(format!("{name} has neither a signature nor an rva"));
+                }
+                None => {}
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+//! Signature matches found on earlier launches, so they don't have to be scanned for again.
+//!
+//! Matches are kept per build of the game's executable in `cauldron/cache/offsets-<hash>.json`,
+//! where `<hash>` is a [fingerprint] of the executable's headers. A game update changes the
+//! fingerprint, so matches from another build are never used. A cached match is only trusted if
+//! its signature still matches there.
+
+use cauldron::mem::address::Rva;
+use cauldron::mem::module::Module;
+use cauldron::mem::scan::Pattern;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Where the caches are kept, relative to the game's directory.
+pub const CACHE_DIR: &str = "cauldron/cache";
+
+const FILE_PREFIX: &str = "offsets-";
+const FILE_EXTENSION: &str = "json";
+
+/// A hash of `data`, 64-bit FNV-1a as hex.
+pub fn fingerprint(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xCBF2_9CE4_8422_2325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01B3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Signature matches in one build of the game's executable.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OffsetCache {
+    pub fingerprint: String,
+    /// The rva of each signature's match, by the name of its address.
+    pub matches: BTreeMap<String, u32>,
+}
+
+impl OffsetCache {
+    pub fn new(fingerprint: impl Into<String>) -> Self {
+        OffsetCache {
+            fingerprint: fingerprint.into(),
+            matches: BTreeMap::new(),
+        }
+    }
+
+    /// Where the cache for `fingerprint` is kept in `dir`.
+    pub fn path(dir: &Path, fingerprint: &str) -> PathBuf {
+        dir.join(format!("{FILE_PREFIX}{fingerprint}.{FILE_EXTENSION}"))
+    }
+
+    /// The cache for `fingerprint` in `dir`, empty if there's none or it can't be read.
+    pub fn load(dir: &Path, fingerprint: &str) -> Self {
+        std::fs::read_to_string(Self::path(dir, fingerprint))
+            .ok()
+            .and_then(|json| serde_json::from_str::<OffsetCache>(&json).ok())
+            .filter(|cache| cache.fingerprint == fingerprint)
+            .unwrap_or_else(|| Self::new(fingerprint))
+    }
+
+    /// Write the cache to `dir`, removing the caches of every other build.
+    pub fn save(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let path = Self::path(dir, &self.fingerprint);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+
+        for file in std::fs::read_dir(dir)?.flatten() {
+            let other = file.path();
+            let stale = other != path
+                && other.extension().is_some_and(|e| e == FILE_EXTENSION)
+                && other
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(FILE_PREFIX));
+            if stale {
+                std::fs::remove_file(other)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The cached match for `name` in `module`, if `pattern` still matches there.
+    pub fn get(&self, name: &str, module: &Module, pattern: &Pattern) -> Option<Rva> {
+        let rva = module.rva(*self.matches.get(name)?).ok()?;
+        let window = &module.image()[rva.get() as usize..];
+        pattern.matches(window).then_some(rva)
+    }
+
+    /// Keep `rva` as the match for `name`, returns whether it changed.
+    pub fn insert(&mut self, name: &str, rva: Rva) -> bool {
+        self.matches.insert(name.to_owned(), rva.get()) != Some(rva.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("cauldron_signatures-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn fingerprints() {
+        assert_eq!(fingerprint(b""), "cbf29ce484222325");
+        assert_eq!(fingerprint(b"a"), "af63dc4c8601ec8c");
+        assert_ne!(fingerprint(b"MZ\x90\x00"), fingerprint(b"MZ\x90\x01"));
+    }
+
+    // never read from
+    const GAME: Module = unsafe { Module::new("game.exe", 0x1_4000_0000, 0x1000) };
+
+    #[test]
+    fn verified_matches() {
+        static IMAGE: [u8; 6] = [0x90, 0x48, 0x8B, 0x05, 0x10, 0x20];
+        let module = unsafe { Module::new("game.exe", IMAGE.as_ptr() as usize, 6) };
+        let pattern = Pattern::new("48 8B ? 10").unwrap();
+        let rva = |offset| module.rva(offset).unwrap();
+
+        let mut cache = OffsetCache::new("abc");
+        assert_eq!(cache.get("A", &module, &pattern), None);
+        assert!(cache.insert("A", rva(1)));
+        assert!(!cache.insert("A", rva(1)));
+        assert_eq!(cache.get("A", &module, &pattern), Some(rva(1)));
+
+        // moved by a game update, or past the end of the image
+        cache.insert("A", rva(2));
+        assert_eq!(cache.get("A", &module, &pattern), None);
+        cache.matches.insert(String::from("A"), 0x1000);
+        assert_eq!(cache.get("A", &module, &pattern), None);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = temp_dir("save_and_load");
+        assert_eq!(OffsetCache::load(&dir, "old"), OffsetCache::new("old"));
+
+        let mut old = OffsetCache::new("old");
+        old.insert("A", GAME.rva(0x10).unwrap());
+        old.save(&dir).unwrap();
+        assert_eq!(OffsetCache::load(&dir, "old"), old);
+
+        // another build replaces it
+        let mut new = OffsetCache::new("new");
+        new.insert("A", GAME.rva(0x20).unwrap());
+        new.save(&dir).unwrap();
+        assert_eq!(OffsetCache::load(&dir, "new"), new);
+        assert!(!OffsetCache::path(&dir, "old").exists());
+
+        std::fs::write(OffsetCache::path(&dir, "new"), "{").unwrap();
+        assert_eq!(OffsetCache::load(&dir, "new"), OffsetCache::new("new"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,580 @@
+//! Named addresses in each game and version, and how to find them.
+//!
+//! Every address cauldron and libdecima need is listed in `signatures.toml`, which is checked and
+//! embedded at build time. Look them up with [address], which finds them in the running game.
+//!
+//! Matches in the game's executable are kept on disk, see [cache].
+
+pub mod cache;
+
+use cache::{CACHE_DIR, OffsetCache};
+use cauldron::mem::address::{AddressError, Va};
+use cauldron::mem::instruction::ResolveError;
+use cauldron::mem::module::Module;
+use cauldron::mem::offset::{Offset, PatternSearchError};
+use cauldron::mem::pe;
+use cauldron::mem::scan::{Pattern, SignatureSet};
+use cauldron_game_detection::Game;
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// The env var with the version of the executable named by
+/// [GAME_EXECUTABLE_VAR](cauldron::mem::image::GAME_EXECUTABLE_VAR), for offline signature tests.
+pub const GAME_VERSION_VAR: &str = "CAULDRON_GAME_VERSION";
+
+static EMBEDDED: OnceLock<Database> = OnceLock::new();
+static ACTIVE: OnceLock<Option<(Game, Version)>> = OnceLock::new();
+static RESOLVED: Mutex<BTreeMap<String, Result<Va, LookupError>>> = Mutex::new(BTreeMap::new());
+static OFFSET_CACHE: OnceLock<Option<Mutex<OffsetCache>>> = OnceLock::new();
+
+/// A problem with the database itself.
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq)]
+pub enum DatabaseError {
+    #[error("invalid database: {0}")]
+    Toml(String),
+
+    #[error("unknown game \"{0}\"")]
+    UnknownGame(String),
+
+    #[error("invalid versions for {game}, \"{versions}\": {error}")]
+    InvalidVersions {
+        game: String,
+        versions: String,
+        error: String,
+    },
+
+    #[error("{name} has an invalid signature: {error}")]
+    InvalidSignature { name: String, error: String },
+
+    #[error("{name} has neither a signature nor an rva")]
+    Unlocatable { name: String },
+}
+
+/// Why an address couldn't be found.
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum LookupError {
+    #[error("unable to detect the running game or version")]
+    UnknownGame,
+
+    #[error("{0} isn't known for any game")]
+    Unknown(String),
+
+    #[error("{name} isn't known for {game} v{version}")]
+    Unavailable {
+        name: String,
+        game: String,
+        version: Version,
+    },
+
+    #[error("{name}: {error}")]
+    Signature {
+        name: String,
+        error: PatternSearchError,
+    },
+
+    #[error("{name}: {error}")]
+    Address { name: String, error: AddressError },
+
+    #[error("{name}: {error}")]
+    Resolve { name: String, error: ResolveError },
+}
+
+/// What to do with an address once it's found.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Resolve {
+    /// Use it as is.
+    #[default]
+    Match,
+    /// Follow the rip-relative operand of the instruction there.
+    RipRelative,
+    /// Follow the relative call or jump there.
+    Branch,
+}
+
+/// How to find a named address.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// Scanned for if there's no [rva](Entry::rva).
+    pub signature: Option<String>,
+    /// Where the address is, relative to the start of its module.
+    pub rva: Option<u32>,
+    /// The module it's in, the game's executable if `None`.
+    pub module: Option<String>,
+    /// How far the address is from the match.
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(default)]
+    pub resolve: Resolve,
+}
+
+impl Entry {
+    /// Find this address in the running game.
+    pub fn locate(&self, name: &str) -> Result<Va, LookupError> {
+        self.locate_with(name, true)
+    }
+
+    /// [Entry::locate] without the [offset cache](cache), which is kept on disk.
+    pub fn locate_uncached(&self, name: &str) -> Result<Va, LookupError> {
+        self.locate_with(name, false)
+    }
+
+    fn locate_with(&self, name: &str, cached: bool) -> Result<Va, LookupError> {
+        let address_error = |error| LookupError::Address {
+            name: name.to_owned(),
+            error,
+        };
+        let module = match &self.module {
+            Some(module) => Module::find(module),
+            None => Module::game(),
+        }
+        .map_err(address_error)?;
+
+        let found = match (self.rva, &self.signature) {
+            (Some(rva), _) => module.rva(rva).map_err(address_error)?.to_va(),
+            (None, Some(signature)) => match self.module {
+                None if cached => find_in_game(&module, name, signature),
+                _ => scan(&module, signature),
+            }
+            .map_err(|error| LookupError::Signature {
+                name: name.to_owned(),
+                error,
+            })?,
+            (None, None) => {
+                return Err(LookupError::Signature {
+                    name: name.to_owned(),
+                    error: PatternSearchError::Empty,
+                });
+            }
+        };
+
+        let found = if self.offset < 0 {
+            found.checked_sub(self.offset.unsigned_abs() as usize)
+        } else {
+            found.checked_add(self.offset as usize)
+        }
+        .map_err(address_error)?;
+
+        match self.resolve {
+            Resolve::Match => Ok(found),
+            Resolve::RipRelative => found.rip_relative(),
+            Resolve::Branch => found.branch_target(),
+        }
+        .map_err(|error| LookupError::Resolve {
+            name: name.to_owned(),
+            error,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct DatabaseFile {
+    #[serde(default)]
+    game: Vec<TableFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TableFile {
+    game: String,
+    versions: String,
+    #[serde(default)]
+    addresses: BTreeMap<String, Entry>,
+}
+
+struct Table {
+    game: Game,
+    versions: VersionReq,
+    addresses: BTreeMap<String, Entry>,
+}
+
+/// Every known address, per game and version.
+pub struct Database {
+    tables: Vec<Table>,
+}
+
+impl Database {
+    pub fn parse(source: &str) -> Result<Self, DatabaseError> {
+        let file: DatabaseFile =
+            toml::from_str(source).map_err(|e| DatabaseError::Toml(e.to_string()))?;
+
+        let mut tables = Vec::new();
+        for table in file.game {
+            let game = Game::from_code(&table.game)
+                .ok_or_else(|| DatabaseError::UnknownGame(table.game.clone()))?;
+            let versions =
+                VersionReq::parse(&table.versions).map_err(|e| DatabaseError::InvalidVersions {
+                    game: table.game.clone(),
+                    versions: table.versions.clone(),
+                    error: e.to_string(),
+                })?;
+
+            for (name, entry) in &table.addresses {
+                match &entry.signature {
+                    Some(signature) => {
+                        Pattern::new(signature).map_err(|e| DatabaseError::InvalidSignature {
+                            name: name.clone(),
+                            error: e.to_string(),
+                        })?;
+                    }
+                    None if entry.rva.is_none() => {
+                        return Err(DatabaseError::Unlocatable { name: name.clone() });
+                    }
+                    None => {}
+                }
+            }
+
+            tables.push(Table {
+                game,
+                versions,
+                addresses: table.addresses,
+            });
+        }
+
+        Ok(Database { tables })
+    }
+
+    /// The database embedded in cauldron, which `build.rs` already checked.
+    pub fn embedded() -> &'static Database {
+        EMBEDDED.get_or_init(|| {
+            Database::parse(include_str!("../signatures.toml"))
+                .expect("signatures.toml is checked by build.rs")
+        })
+    }
+
+    /// How to find `name` in `version` of `game`.
+    pub fn entry(&self, game: &Game, version: &Version, name: &str) -> Option<&Entry> {
+        self.tables
+            .iter()
+            .filter(|table| table.game == *game && table.versions.matches(version))
+            .find_map(|table| table.addresses.get(name))
+    }
+
+    /// How to find `name` in every game and version that has it.
+    pub fn entries_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Entry> {
+        self.tables
+            .iter()
+            .filter_map(move |table| table.addresses.get(name))
+    }
+
+    /// Every address known for `version` of `game`.
+    pub fn entries(&self, game: &Game, version: &Version) -> BTreeMap<&str, &Entry> {
+        let mut entries = BTreeMap::new();
+        for table in self
+            .tables
+            .iter()
+            .filter(|table| table.game == *game && table.versions.matches(version))
+        {
+            for (name, entry) in &table.addresses {
+                entries.entry(name.as_str()).or_insert(entry);
+            }
+        }
+        entries
+    }
+
+    /// The signatures of every address in the game's executable that has to be scanned for, to
+    /// find them all in one pass.
+    pub fn signatures(&self, game: &Game, version: &Version) -> SignatureSet {
+        let mut signatures = SignatureSet::new();
+        for (name, entry) in self.entries(game, version) {
+            if let (None, None, Some(signature)) = (entry.rva, &entry.module, &entry.signature) {
+                signatures.add(name, signature.as_str()).unwrap();
+            }
+        }
+        signatures
+    }
+}
+
+/// The running game's offset cache, `None` if its module can't be read.
+///
+/// Builds are told apart by their [build headers](pe::build_headers) rather than their code, which
+/// is too big to hash on every launch and has been hooked by the time mods look addresses up.
+fn offset_cache() -> Option<&'static Mutex<OffsetCache>> {
+    OFFSET_CACHE
+        .get_or_init(|| {
+            let module = Module::game().ok()?;
+            let fingerprint = cache::fingerprint(&pe::build_headers(module.image()).ok()?);
+            Some(Mutex::new(OffsetCache::load(
+                Path::new(CACHE_DIR),
+                &fingerprint,
+            )))
+        })
+        .as_ref()
+}
+
+/// The cached match of `name` in the game's executable, if its signature still matches there.
+fn cached(module: &Module, name: &str, pattern: &Pattern) -> Option<Va> {
+    let rva = offset_cache()?.lock().unwrap().get(name, module, pattern)?;
+    Some(rva.to_va())
+}
+
+/// Keep matches in the game's executable for the next launch.
+fn remember<'a>(found: impl IntoIterator<Item = (&'a str, Va)>) {
+    let Some(cache) = offset_cache() else {
+        return;
+    };
+    let mut cache = cache.lock().unwrap();
+    let mut changed = false;
+    for (name, va) in found {
+        changed |= cache.insert(name, va.to_rva());
+    }
+    if changed && let Err(e) = cache.save(Path::new(CACHE_DIR)) {
+        log::warn!("Failed to save the offset cache: {e}");
+    }
+}
+
+/// The first match of `signature` in `module`.
+fn scan(module: &Module, signature: &str) -> Result<Va, PatternSearchError> {
+    Offset::from_module_signature(module, signature)?
+        .as_va_in(module)
+        .map_err(PatternSearchError::Module)
+}
+
+/// Find `signature` in the game's executable, where it was found on an earlier launch if it still
+/// matches there.
+fn find_in_game(module: &Module, name: &str, signature: &str) -> Result<Va, PatternSearchError> {
+    let pattern = Pattern::new(signature)?;
+    if let Some(va) = cached(module, name, &pattern) {
+        return Ok(va);
+    }
+
+    let found = scan(module, signature)?;
+    remember([(name, found)]);
+    Ok(found)
+}
+
+/// The running game and its version, detected once.
+pub fn active() -> Result<&'static (Game, Version), LookupError> {
+    ACTIVE
+        .get_or_init(cauldron_game_detection::detect_active)
+        .as_ref()
+        .ok_or(LookupError::UnknownGame)
+}
+
+/// Find `name` in the running game, results are kept.
+pub fn address(name: &str) -> Result<Va, LookupError> {
+    if let Some(resolved) = RESOLVED.lock().unwrap().get(name) {
+        return resolved.clone();
+    }
+
+    let (game, version) = active()?;
+    let resolved = match Database::embedded().entry(game, version, name) {
+        Some(entry) => entry.locate(name),
+        None => Err(LookupError::Unavailable {
+            name: name.to_owned(),
+            game: game.pretty_name(),
+            version: version.clone(),
+        }),
+    };
+    RESOLVED
+        .lock()
+        .unwrap()
+        .insert(name.to_owned(), resolved.clone());
+    resolved
+}
+
+/// Find `name` in the running game without detecting it or touching the disk, by trying how every
+/// game and version finds it until one works. Results aren't kept.
+///
+/// Unlike [address] this is fine to call while the loader lock is held, e.g. from `DllMain`.
+pub fn find_uncached(name: &str) -> Result<Va, LookupError> {
+    let mut result = Err(LookupError::Unknown(name.to_owned()));
+    for entry in Database::embedded().entries_named(name) {
+        result = entry.locate_uncached(name);
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// Find `names` in the running game up front. Signatures that weren't cached on an earlier launch
+/// are all scanned for in a single pass.
+///
+/// Only pass the names you use, others may have been patched over by whoever uses them, e.g. the
+/// loader's hook on `CoreLibrary_Initialize`, and would be rescanned for on every launch.
+pub fn prescan<'a>(
+    names: &[&'a str],
+) -> Result<BTreeMap<&'a str, Result<Va, LookupError>>, LookupError> {
+    let (game, version) = active()?;
+    let signatures = Database::embedded().signatures(game, version);
+
+    if let Ok(module) = Module::game() {
+        let mut uncached = SignatureSet::new();
+        for (name, signature) in signatures.iter().filter(|(name, _)| names.contains(name)) {
+            let pattern = Pattern::new(signature).unwrap();
+            if cached(&module, name, &pattern).is_none() {
+                uncached.add(name, signature).unwrap();
+            }
+        }
+        if !uncached.is_empty()
+            && let Ok(found) = Offset::from_module_signatures(&module, &uncached)
+        {
+            remember(found.iter().filter_map(|(name, offset)| {
+                Some((name.as_str(), offset.as_va_in(&module).ok()?))
+            }));
+        }
+    }
+
+    Ok(names.iter().map(|&name| (name, address(name))).collect())
+}
+
+/// The signatures of the running game, see [Database::signatures].
+pub fn signatures() -> Result<SignatureSet, LookupError> {
+    let (game, version) = active()?;
+    Ok(Database::embedded().signatures(game, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cauldron::mem::image::{FileImage, ImageSource};
+
+    const SOURCE: &str = r#"
+        [[game]]
+        game = "hfw"
+        versions = ">=1.5.80"
+
+        [game.addresses.New]
+        signature = "01 02"
+        resolve = "rip-relative"
+
+        [[game]]
+        game = "hfw"
+        versions = "*"
+
+        [game.addresses.New]
+        rva = 0x1000
+
+        [game.addresses.Old]
+        signature = "03 ? 04"
+        offset = -2
+
+        [[game]]
+        game = "hzd"
+        versions = "*"
+
+        [game.addresses.Other]
+        signature = "05"
+        module = "other.dll"
+    "#;
+
+    #[test]
+    fn lookup() {
+        let database = Database::parse(SOURCE).unwrap();
+        let hfw = Game::HorizonForbiddenWest;
+
+        let new = database
+            .entry(&hfw, &Version::new(1, 5, 80), "New")
+            .unwrap();
+        assert_eq!(new.signature.as_deref(), Some("01 02"));
+        assert_eq!(new.resolve, Resolve::RipRelative);
+
+        let old = database.entry(&hfw, &Version::new(1, 0, 0), "New").unwrap();
+        assert_eq!(old.rva, Some(0x1000));
+        assert_eq!(old.resolve, Resolve::Match);
+
+        let named: Vec<_> = database.entries_named("New").collect();
+        assert_eq!(named, [new, old]);
+        assert_eq!(database.entries_named("Missing").count(), 0);
+
+        let entry = database.entry(&hfw, &Version::new(1, 0, 0), "Old").unwrap();
+        assert_eq!(entry.offset, -2);
+        assert!(
+            database
+                .entry(&Game::HorizonZeroDawn, &Version::new(1, 0, 0), "Old")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn entries_and_signatures() {
+        let database = Database::parse(SOURCE).unwrap();
+        let hfw = Game::HorizonForbiddenWest;
+
+        let entries = database.entries(&hfw, &Version::new(1, 5, 80));
+        assert_eq!(entries.keys().copied().collect::<Vec<_>>(), ["New", "Old"]);
+        assert_eq!(entries["New"].signature.as_deref(), Some("01 02"));
+
+        // fixed rvas and other modules aren't scanned for in the executable
+        let signatures = database.signatures(&hfw, &Version::new(1, 0, 0));
+        assert_eq!(signatures.iter().collect::<Vec<_>>(), [("Old", "03 ?? 04")]);
+        let signatures = database.signatures(&Game::HorizonZeroDawn, &Version::new(1, 0, 0));
+        assert!(signatures.is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        let table = |game: &str, versions: &str, address: &str| {
+            Database::parse(&format!(
+                "[[game]]\ngame = \"{game}\"\nversions = \"{versions}\"\n[game.addresses.A]\n{address}"
+            ))
+            .err()
+        };
+
+        assert_eq!(
+            table("gta", "*", "rva = 1"),
+            Some(DatabaseError::UnknownGame(String::from("gta")))
+        );
+        assert!(matches!(
+            table("hfw", "one", "rva = 1"),
+            Some(DatabaseError::InvalidVersions { .. })
+        ));
+        assert!(matches!(
+            table("hfw", "*", "signature = \"XY\""),
+            Some(DatabaseError::InvalidSignature { name, .. }) if name == "A"
+        ));
+        assert_eq!(
+            table("hfw", "*", "offset = 1"),
+            Some(DatabaseError::Unlocatable {
+                name: String::from("A")
+            })
+        );
+        assert!(matches!(
+            table("hfw", "*", "rva = 1\nresolve = \"sideways\""),
+            Some(DatabaseError::Toml(_))
+        ));
+        assert_eq!(table("hfw", "*", "rva = 1"), None);
+    }
+
+    #[test]
+    fn embedded() {
+        let database = Database::embedded();
+        let entries = database.entries(&Game::HorizonForbiddenWest, &Version::new(1, 5, 80));
+        for name in [
+            "CoreLibrary_Initialize",
+            "ExportedSymbols",
+            "ExportedSymbols::import",
+            "FactoryManager",
+            "GGString::init",
+            "GGString::drop",
+        ] {
+            assert!(entries.contains_key(name), "{name}");
+        }
+    }
+
+    /// Checks the embedded signatures against the executable named by `CAULDRON_GAME_EXECUTABLE`,
+    /// whose version is `CAULDRON_GAME_VERSION`.
+    #[test]
+    fn signatures_match_the_game() {
+        let Some(image) = FileImage::from_env() else {
+            return;
+        };
+        let path = std::env::var(cauldron::mem::image::GAME_EXECUTABLE_VAR).unwrap();
+        let file_name = std::path::Path::new(&path).file_name().unwrap();
+        let game = Game::from_executable(file_name.to_str().unwrap())
+            .unwrap_or_else(|| panic!("{path} isn't a supported game"));
+        let version = std::env::var(GAME_VERSION_VAR)
+            .unwrap_or_else(|_| panic!("{GAME_VERSION_VAR} has to be set too"));
+        let version = Version::parse(&version).unwrap();
+
+        let signatures = Database::embedded().signatures(&game, &version);
+        if let Err(report) = image.unwrap().check(&signatures) {
+            panic!("signatures don't match the game's executable:\n{report}");
+        }
+    }
+}
@@ -1,16 +1,32 @@
 use cauldron::CauldronApi;
 use cauldron::log::init_mod_logger;
-use cauldron::mem::offset::Offset;
 use cauldron::prelude::{CauldronModDependency, CauldronModInfo};
 use libdecima_core::types::core::exported_symbols::{ExportedSymbolKind, ExportedSymbols};
 use std::ffi::c_void;
 
+/// Finds every address libdecima_core uses up front, see [cauldron_signatures::prescan].
+fn prescan() {
+    match cauldron_signatures::prescan(libdecima_core::ADDRESSES) {
+        Ok(addresses) => {
+            for (name, address) in addresses {
+                match address {
+                    Ok(va) => log::debug!("Found {name} at {va}."),
+                    Err(e) => log::warn!("Failed to find {name}: {e}"),
+                }
+            }
+        }
+        Err(e) => log::error!("Failed to scan for signatures: {e}"),
+    }
+}
+
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
-pub unsafe extern "C" fn CauldronMod_Load(loader_api: *const CauldronApi) -> bool {
+pub unsafe extern "C-unwind" fn CauldronMod_Load(loader_api: *const CauldronApi) -> bool {
     let loader = unsafe { &*loader_api };
     init_mod_logger(loader).expect("libdecima: failed to initialize mod logger.");
 
+    prescan();
+
     let mut atom_count: u32 = 0;
     let mut enum_count: u32 = 0;
     let mut class_count: u32 = 0;
@@ -23,17 +39,17 @@ pub unsafe extern "C" fn CauldronMod_Load(loader_api: *const CauldronApi) -> boo
     let mut pointer_count: u32 = 0;
     let mut source_file_count: u32 = 0;
 
-    if let Ok(offset) = Offset::from_signature("48 89 5C 24 ? 57 48 83 EC ? 48 8D 7A ? 89 4C 24 ?")
-    {
-        let offset = offset.as_ptr::<c_void>();
-        loader.register("libdecima/engine/functions", "Importer", offset);
+    if let Ok(va) = ExportedSymbols::importer_address() {
+        let importer: extern "C" fn(u32, *mut ExportedSymbols) -> *mut c_void =
+            unsafe { std::mem::transmute(va.as_ptr::<c_void>()) };
+        loader.register_typed("libdecima/engine/functions", "Importer", importer);
     }
 
     let symbols = ExportedSymbols::get().expect("libdecima: failed to get exported symbols");
-    loader.register(
+    loader.register_typed(
         "libdecima/engine/variables",
         "ExportedSymbols",
-        symbols as *const ExportedSymbols as *mut ExportedSymbols as *mut c_void,
+        symbols as *const ExportedSymbols as *mut ExportedSymbols,
     );
     for group in symbols.groups.as_slice() {
         let group = unsafe { &**group };
@@ -108,9 +124,11 @@ pub unsafe extern "C" fn CauldronMod_Load(loader_api: *const CauldronApi) -> boo
     true
 }
 
+cauldron::export_api_version!(1);
+
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
-pub unsafe extern "C" fn CauldronMod_Info() -> *const CauldronModInfo {
+pub unsafe extern "C-unwind" fn CauldronMod_Info() -> *const CauldronModInfo {
     let info = Box::new(
         CauldronModInfo::builder("libdecima", env!("CARGO_PKG_VERSION"))
             .dependency(CauldronModDependency::new("hfw", Some(">=1.5.80"), false))
@@ -1,2 +1,11 @@
 pub mod macros;
 pub mod types;
+
+/// Every address libdecima_core looks up, for [prescan](cauldron_signatures::prescan).
+pub const ADDRESSES: &[&str] = &[
+    "ExportedSymbols",
+    "ExportedSymbols::import",
+    "FactoryManager",
+    "GGString::init",
+    "GGString::drop",
+];
@@ -62,13 +62,16 @@ macro_rules! gen_with_vtbl {
 
 #[macro_export]
 macro_rules! impl_instance {
-    ($name:ident, $signature:literal, $instruction_length:literal) => {
+    ($name:ident) => {
         impl $name {
+            /// Where the game keeps its instance pointer.
+            pub fn instance_address()
+            -> Result<::cauldron::mem::address::Va, ::cauldron_signatures::LookupError> {
+                ::cauldron_signatures::address(stringify!($name))
+            }
+
             pub fn get_instance() -> Option<&'static $name> {
-                let ptr = ::cauldron::mem::offset::Offset::from_signature($signature)
-                    .unwrap()
-                    .as_relative($instruction_length)
-                    .as_ptr::<*mut $name>();
+                let ptr = Self::instance_address().ok()?.as_ptr::<*mut $name>();
                 if !ptr.is_null() {
                     let ptr = unsafe { *ptr };
                     if !ptr.is_null() {
@@ -80,9 +83,6 @@ macro_rules! impl_instance {
             }
         }
     };
-    ($name:ident, $signature:literal) => {
-        impl_instance!($name, $signature, 7);
-    };
 }
 
 #[cfg(test)]
@@ -3,7 +3,8 @@ use crate::types::p_core::ggstring::GGString;
 use crate::types::p_core::hashmap::HashMap;
 use crate::{assert_size, gen_with_vtbl};
 use bitflags::bitflags;
-use cauldron::mem::offset::Offset;
+use cauldron::mem::address::Va;
+use cauldron_signatures::LookupError;
 use libdecima_rtti::RTTIWithName;
 use libdecima_rtti::sys::DecimaRTTI;
 use std::ffi::{CStr, c_char, c_void};
@@ -277,12 +278,21 @@ pub struct ExportedSymbols {
     pub type_symbols: HashMap<GGString, *mut ExportedSymbol>,
 }
 
+cauldron::registry_type!(ExportedSymbols);
+
 impl ExportedSymbols {
+    /// Where the game's symbols are.
+    pub fn address() -> Result<Va, LookupError> {
+        cauldron_signatures::address("ExportedSymbols")
+    }
+
+    /// The game's function that imports a symbol, see [ExportedSymbols::import_symbol].
+    pub fn importer_address() -> Result<Va, LookupError> {
+        cauldron_signatures::address("ExportedSymbols::import")
+    }
+
     pub fn get() -> Option<&'static ExportedSymbols> {
-        let ptr = Offset::from_signature("48 63 05 ? ? ? ? 4D 8B 3E")
-            .unwrap()
-            .as_relative(7)
-            .as_ptr::<ExportedSymbols>();
+        let ptr = Self::address().ok()?.as_ptr::<ExportedSymbols>();
         if !ptr.is_null() {
             let instance = unsafe { &*ptr };
             return Some(instance);
@@ -294,18 +304,12 @@ impl ExportedSymbols {
         match Self::get() {
             None => None,
             Some(symbols) => {
-                let Ok(importer) =
-                    Offset::from_signature("48 89 5C 24 ? 57 48 83 EC 20 48 8D 7A ? 89 4C 24")
-                else {
+                let Ok(importer) = Self::importer_address() else {
                     return None;
                 };
 
-                let importer: extern "C" fn(u32, *mut ExportedSymbols) -> *mut c_void = unsafe {
-                    std::mem::transmute(
-                        importer
-                            .as_ptr::<extern "C" fn(u32, *mut ExportedSymbols) -> *mut c_void>(),
-                    )
-                };
+                let importer: extern "C" fn(u32, *mut ExportedSymbols) -> *mut c_void =
+                    unsafe { std::mem::transmute(importer.as_ptr::<c_void>()) };
 
                 let ptr = importer(
                     symbol,
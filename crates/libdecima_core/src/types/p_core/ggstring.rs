@@ -1,6 +1,6 @@
 use crate::assert_size;
-use cauldron::mem::offset::Offset;
-use std::ffi::c_char;
+use cauldron_signatures::LookupError;
+use std::ffi::{c_char, c_void};
 
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -21,15 +21,13 @@ pub struct GGString {
     pub data: *const c_char,
 }
 
-impl GGString {
-    fn internal_init(&self, data: *const c_char, size: usize) {
-        let func = unsafe {
-            *Offset::from_signature("48 89 5C 24 08 48 89 6C 24 10 48 89 74 24 18 57 48 83 EC 20 48 8B 01 48 8B EA 49 63 F8 48 8B F1 45 85 C0")
-                .unwrap()
-                .as_ptr::<extern "C" fn(*mut GGString, *const c_char, usize /* size_t */)>()
-        };
+type InitFn = extern "C" fn(*mut GGString, *const c_char, usize /* size_t */);
 
-        func(self as *const Self as *mut Self, data, size);
+impl GGString {
+    /// The game's string constructor, strings can't be created without it.
+    fn init_func() -> Result<InitFn, LookupError> {
+        let func = cauldron_signatures::address("GGString::init")?;
+        Ok(unsafe { std::mem::transmute::<*mut c_void, InitFn>(func.as_ptr::<c_void>()) })
     }
 
     fn internal_data(&self) -> &StringData {
@@ -44,12 +42,15 @@ impl GGString {
         self.internal_data().length
     }
 
-    pub fn new() -> Self {
-        let string = Self {
+    /// An empty string, fails if the game's `GGString::init` wasn't found.
+    pub fn new() -> Result<Self, LookupError> {
+        // found first, so a string the game never initialized is never dropped
+        let init = Self::init_func()?;
+        let mut string = Self {
             data: std::ptr::null(),
         };
-        string.internal_init(std::ptr::null(), 0);
-        string
+        init(&mut string, std::ptr::null(), 0);
+        Ok(string)
     }
 
     pub fn as_string(&self) -> String {
@@ -60,11 +61,15 @@ impl GGString {
 
 impl Drop for GGString {
     fn drop(&mut self) {
-        let func = Offset::from_signature(
-            "40 53 48 83 EC 20 48 8B 19 48 8D 05 ? ? ? ? 48 83 EB 10 48 3B D8",
-        )
-        .unwrap()
-        .as_ptr::<extern "C" fn(*mut GGString)>();
-        unsafe { (*func)(self as *const Self as *mut Self) };
+        // leaking the string is better than crashing when the game can't free it
+        let Ok(func) = cauldron_signatures::address("GGString::drop") else {
+            return;
+        };
+        let func = unsafe {
+            std::mem::transmute::<*mut c_void, extern "C" fn(*mut GGString)>(
+                func.as_ptr::<c_void>(),
+            )
+        };
+        func(self as *const Self as *mut Self);
     }
 }
@@ -4,6 +4,7 @@ use crate::exported_types::{
     ExportedCompoundOrderedAttribute, ExportedContainerData, ExportedDataRef, ExportedEnumValue,
     ExportedPointerData, ExportedType, ExportedTypeRef,
 };
+use cauldron::mem::module::Module;
 use libdecima_core::types::core::factory_manager::FactoryManager;
 use libdecima_core::types::core::rtti::{DecimaRTTI, DecimaRTTIKind, RTTIWithValues};
 use libdecima_core::types::core::rtti::{RTTIWithAliases, RTTIWithName};
@@ -46,26 +47,26 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                 ExportedType::Atom {
                     id: a.rtti_base.id,
                     factory_flags: a.rtti_base.flags.bits(),
-                    ptr: ty as *const DecimaRTTI as u32,
+                    ptr: rva(ty as *const DecimaRTTI as usize),
                     size: a.size,
                     alignment: a.alignment,
                     simple: a.simple,
                     type_name: a.symbol_name(),
                     base_type: ExportedTypeRef {
                         type_name: unsafe { &*a.parent_type }.symbol_name(),
-                        ptr: a.parent_type as u32,
+                        ptr: rva(a.parent_type as usize),
                     },
-                    func_ptr_from_string: a.func_from_string as u32,
-                    func_ptr_to_string: a.func_to_string as u32,
-                    func_ptr_unk30: a.func_unk30 as u32,
-                    func_ptr_copy: a.func_copy as u32,
-                    func_ptr_equals: a.func_equals as u32,
-                    func_ptr_constructor: a.func_constructor as u32,
-                    func_ptr_destructor: a.func_destructor as u32,
-                    func_ptr_get_serialized_size: a.func_get_serialized_size as u32,
-                    func_ptr_check_range: a.func_check_range as u32,
+                    func_ptr_from_string: rva(a.func_from_string as usize),
+                    func_ptr_to_string: rva(a.func_to_string as usize),
+                    func_ptr_unk30: rva(a.func_unk30 as usize),
+                    func_ptr_copy: rva(a.func_copy as usize),
+                    func_ptr_equals: rva(a.func_equals as usize),
+                    func_ptr_constructor: rva(a.func_constructor as usize),
+                    func_ptr_destructor: rva(a.func_destructor as usize),
+                    func_ptr_get_serialized_size: rva(a.func_get_serialized_size as usize),
+                    func_ptr_check_range: rva(a.func_check_range as usize),
 
-                    ptr_optimized_type: a.optimized_type as u32,
+                    ptr_optimized_type: rva(a.optimized_type as usize),
                 }
             }
             DecimaRTTIKind::Pointer => {
@@ -75,24 +76,24 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                 ExportedType::Pointer {
                     id: p.rtti_base.id,
                     factory_flags: p.rtti_base.flags.bits(),
-                    ptr: ty as *const DecimaRTTI as u32,
+                    ptr: rva(ty as *const DecimaRTTI as usize),
                     has_pointers: p.has_pointers,
                     type_name: p.symbol_name(),
                     item_type: ExportedTypeRef {
                         type_name: unsafe { &*p.item_type }.symbol_name(),
-                        ptr: p.item_type as u32,
+                        ptr: rva(p.item_type as usize),
                     },
                     pointer: ExportedPointerData {
                         type_name: pd.symbol_name(),
-                        ptr: p.pointer_type as u32,
+                        ptr: rva(p.pointer_type as usize),
                         size: pd.size,
                         alignment: pd.alignment,
 
-                        func_ptr_constructor: pd.func_constructor as u32,
-                        func_ptr_destructor: pd.func_destructor as u32,
-                        func_ptr_get: pd.func_get as u32,
-                        func_ptr_set: pd.func_set as u32,
-                        func_ptr_copy: pd.func_copy as u32,
+                        func_ptr_constructor: rva(pd.func_constructor as usize),
+                        func_ptr_destructor: rva(pd.func_destructor as usize),
+                        func_ptr_get: rva(pd.func_get as usize),
+                        func_ptr_set: rva(pd.func_set as usize),
+                        func_ptr_copy: rva(pd.func_copy as usize),
                     },
                 }
             }
@@ -103,38 +104,38 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                 ExportedType::Container {
                     id: c.rtti_base.id,
                     factory_flags: c.rtti_base.flags.bits(),
-                    ptr: ty as *const DecimaRTTI as u32,
+                    ptr: rva(ty as *const DecimaRTTI as usize),
 
                     has_pointers: c.has_pointers,
                     type_name: c.symbol_name(),
 
                     item_type: ExportedTypeRef {
                         type_name: unsafe { &*c.item_type }.symbol_name(),
-                        ptr: c.item_type as u32,
+                        ptr: rva(c.item_type as usize),
                     },
                     container: ExportedContainerData {
                         type_name: cd.symbol_name(),
-                        ptr: c.container_type as u32,
+                        ptr: rva(c.container_type as usize),
                         size: cd.size,
                         alignment: cd.alignment,
                         simple: cd.simple,
                         associative: cd.associative,
 
-                        func_ptr_constructor: cd.func_constructor as u32,
-                        func_ptr_destructor: cd.func_deconstructor as u32,
-                        func_ptr_resize: cd.func_resize as u32,
-                        func_ptr_remove: cd.func_remove as u32,
-                        func_ptr_length: cd.func_length as u32,
-                        func_ptr_iter_start: cd.func_iterator_start as u32,
-                        func_ptr_iter_end: cd.func_iterator_end as u32,
-                        func_ptr_iter_next: cd.func_iterator_next as u32,
-                        func_ptr_iter_deref: cd.func_iterator_deref as u32,
-                        func_ptr_iter_valid: cd.func_iterator_validate as u32,
-                        func_ptr_add_item: cd.func_add_item as u32,
-                        func_ptr_add_empty: cd.func_add_empty as u32,
-                        func_ptr_clear: cd.func_clear as u32,
-                        func_ptr_to_string: cd.func_to_string as u32,
-                        func_ptr_from_string: cd.func_from_string as u32,
+                        func_ptr_constructor: rva(cd.func_constructor as usize),
+                        func_ptr_destructor: rva(cd.func_deconstructor as usize),
+                        func_ptr_resize: rva(cd.func_resize as usize),
+                        func_ptr_remove: rva(cd.func_remove as usize),
+                        func_ptr_length: rva(cd.func_length as usize),
+                        func_ptr_iter_start: rva(cd.func_iterator_start as usize),
+                        func_ptr_iter_end: rva(cd.func_iterator_end as usize),
+                        func_ptr_iter_next: rva(cd.func_iterator_next as usize),
+                        func_ptr_iter_deref: rva(cd.func_iterator_deref as usize),
+                        func_ptr_iter_valid: rva(cd.func_iterator_validate as usize),
+                        func_ptr_add_item: rva(cd.func_add_item as usize),
+                        func_ptr_add_empty: rva(cd.func_add_empty as usize),
+                        func_ptr_clear: rva(cd.func_clear as usize),
+                        func_ptr_to_string: rva(cd.func_to_string as usize),
+                        func_ptr_from_string: rva(cd.func_from_string as usize),
                     },
                 }
             }
@@ -144,13 +145,13 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                 ExportedType::Enum {
                     id: e.rtti_base.id,
                     factory_flags: e.rtti_base.flags.bits(),
-                    ptr: ty as *const DecimaRTTI as u32,
+                    ptr: rva(ty as *const DecimaRTTI as usize),
                     size: e.size,
                     alignment: e.alignment,
                     is_flags: ty.kind == DecimaRTTIKind::FlagsEnum,
                     type_name: e.symbol_name(),
                     values: ExportedDataRef {
-                        ptr: e.values as u32,
+                        ptr: rva(e.values as usize),
                         data: e
                             .values()
                             .iter()
@@ -169,7 +170,7 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                 ExportedType::Compound {
                     id: c.rtti_base.id,
                     factory_flags: c.rtti_base.flags.bits(),
-                    ptr: ty as *const DecimaRTTI as u32,
+                    ptr: rva(ty as *const DecimaRTTI as usize),
 
                     version: c.version,
                     size: c.size,
@@ -180,7 +181,7 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                     } else {
                         Some(ExportedTypeRef {
                             type_name: unsafe { &*c.next_type }.symbol_name(),
-                            ptr: c.next_type as u32,
+                            ptr: rva(c.next_type as usize),
                         })
                     },
                     previous_type: if c.previous_type.is_null() {
@@ -188,26 +189,26 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                     } else {
                         Some(ExportedTypeRef {
                             type_name: unsafe { &*c.previous_type }.symbol_name(),
-                            ptr: c.previous_type as u32,
+                            ptr: rva(c.previous_type as usize),
                         })
                     },
 
                     bases: ExportedDataRef {
-                        ptr: c.bases as u32,
+                        ptr: rva(c.bases as usize),
                         data: c
                             .bases()
                             .iter()
                             .map(|base| ExportedCompoundBase {
                                 base: ExportedTypeRef {
                                     type_name: unsafe { &*base.base_type }.symbol_name(),
-                                    ptr: base.base_type as u32,
+                                    ptr: rva(base.base_type as usize),
                                 },
                                 offset: base.offset,
                             })
                             .collect(),
                     },
                     attributes: ExportedDataRef {
-                        ptr: c.attributes as u32,
+                        ptr: rva(c.attributes as usize),
                         data: c
                             .attributes()
                             .iter()
@@ -217,7 +218,7 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                                 } else {
                                     Some(ExportedTypeRef {
                                         type_name: unsafe { &*attr.attribute_type }.symbol_name(),
-                                        ptr: attr.attribute_type as u32,
+                                        ptr: rva(attr.attribute_type as usize),
                                     })
                                 },
                                 attribute_name: if attr.attribute_name.is_null() {
@@ -246,13 +247,13 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                                         CStr::from_ptr(attr.range_max).to_str().unwrap().to_string()
                                     })
                                 },
-                                func_ptr_get: attr.func_get as u32,
-                                func_ptr_set: attr.func_get as u32,
+                                func_ptr_get: rva(attr.func_get as usize),
+                                func_ptr_set: rva(attr.func_get as usize),
                             })
                             .collect(),
                     },
                     ordered_attributes: ExportedDataRef {
-                        ptr: c.ordered_attributes as u32,
+                        ptr: rva(c.ordered_attributes as usize),
                         data: c
                             .ordered_attributes()
                             .iter()
@@ -262,7 +263,7 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                                 } else {
                                     Some(ExportedTypeRef {
                                         type_name: unsafe { &*attr.attribute_type }.symbol_name(),
-                                        ptr: attr.attribute_type as u32,
+                                        ptr: rva(attr.attribute_type as usize),
                                     })
                                 },
                                 attribute_name: if attr.attribute_name.is_null() {
@@ -297,7 +298,7 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                                 } else {
                                     Some(ExportedTypeRef {
                                         type_name: unsafe { &*attr.parent }.symbol_name(),
-                                        ptr: attr.parent as u32,
+                                        ptr: rva(attr.parent as usize),
                                     })
                                 },
                                 group: if attr.group.is_null() {
@@ -308,27 +309,27 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                                     })
                                 },
 
-                                func_ptr_get: attr.func_get as u32,
-                                func_ptr_set: attr.func_get as u32,
+                                func_ptr_get: rva(attr.func_get as usize),
+                                func_ptr_set: rva(attr.func_get as usize),
                             })
                             .collect(),
                     },
                     message_handlers: ExportedDataRef {
-                        ptr: c.message_handlers as u32,
+                        ptr: rva(c.message_handlers as usize),
                         data: c
                             .message_handlers()
                             .iter()
                             .map(|handler| ExportedCompoundMessageHandler {
                                 message: ExportedTypeRef {
                                     type_name: unsafe { &*handler.message }.symbol_name(),
-                                    ptr: handler.message as u32,
+                                    ptr: rva(handler.message as usize),
                                 },
-                                func_ptr_handler: handler.handler as u32,
+                                func_ptr_handler: rva(handler.handler as usize),
                             })
                             .collect(),
                     },
                     message_order_entries: ExportedDataRef {
-                        ptr: c.message_order_entries as u32,
+                        ptr: rva(c.message_order_entries as usize),
                         data: c
                             .message_order_entries()
                             .iter()
@@ -336,11 +337,11 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                                 before: entry.before,
                                 message: ExportedTypeRef {
                                     type_name: unsafe { &*entry.message }.symbol_name(),
-                                    ptr: entry.message as u32,
+                                    ptr: rva(entry.message as usize),
                                 },
                                 compound: ExportedTypeRef {
                                     type_name: unsafe { &*entry.compound }.symbol_name(),
-                                    ptr: entry.compound as u32,
+                                    ptr: rva(entry.compound as usize),
                                 },
                             })
                             .collect(),
@@ -354,20 +355,20 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                                 message: ExportedTypeRef {
                                     type_name: unsafe { &*c.message_read_binary.message }
                                         .symbol_name(),
-                                    ptr: c.message_read_binary.message as u32,
+                                    ptr: rva(c.message_read_binary.message as usize),
                                 },
-                                func_ptr_handler: c.message_read_binary.handler as u32,
+                                func_ptr_handler: rva(c.message_read_binary.handler as usize),
                             },
                         })
                     },
 
-                    func_ptr_constructor: c.func_constructor as u32,
-                    func_ptr_destructor: c.func_destructor as u32,
-                    func_ptr_from_string: c.func_from_string as u32,
-                    func_ptr_unk0: c.func_unk0 as u32,
-                    func_ptr_to_string: c.func_to_string as u32,
-                    func_ptr_get_symbol_group: c.func_get_symbol_group as u32,
-                    ptr_optimized_type: c.optimized_type as u32,
+                    func_ptr_constructor: rva(c.func_constructor as usize),
+                    func_ptr_destructor: rva(c.func_destructor as usize),
+                    func_ptr_from_string: rva(c.func_from_string as usize),
+                    func_ptr_unk0: rva(c.func_unk0 as usize),
+                    func_ptr_to_string: rva(c.func_to_string as usize),
+                    func_ptr_get_symbol_group: rva(c.func_get_symbol_group as usize),
+                    ptr_optimized_type: rva(c.optimized_type as usize),
                     unk1: c.unk1,
                 }
             }
@@ -376,14 +377,14 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
                 ExportedType::Pod {
                     id: p.rtti_base.id,
                     factory_flags: p.rtti_base.flags.bits(),
-                    ptr: ty as *const DecimaRTTI as u32,
+                    ptr: rva(ty as *const DecimaRTTI as usize),
                     size: p.size,
                 }
             }
             DecimaRTTIKind::BitSetEnum => {
                 let e = ty.as_bitset_enum_unchecked();
                 ExportedType::BitSetEnum {
-                    ptr: ty as *const DecimaRTTI as u32,
+                    ptr: rva(ty as *const DecimaRTTI as usize),
                     type_name: e.symbol_name(),
                 }
             }
@@ -401,3 +402,8 @@ pub(crate) fn export_types() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// `address` relative to the game's module, `None` if it's null or points outside of it.
+fn rva(address: usize) -> Option<u32> {
+    Some(Module::game().ok()?.va(address).ok()?.to_rva().get())
+}
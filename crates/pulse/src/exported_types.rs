@@ -1,3 +1,9 @@
+//! What `cauldron/types.json` holds, an [ExportedType] for every RTTI type.
+//!
+//! Every `ptr` and `func_ptr_*` is an rva in the game's executable, or `null` if the pointer is
+//! null or points outside of the executable, e.g. at RTTI the game built on the heap. Dumps from
+//! before rvas held the low 32 bits of the absolute address instead.
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -6,7 +12,7 @@ pub enum ExportedType {
     Atom {
         id: u32,
         factory_flags: u8,
-        ptr: u32,
+        ptr: Option<u32>,
 
         size: u16,
         alignment: u8,
@@ -14,22 +20,22 @@ pub enum ExportedType {
         type_name: String,
         base_type: ExportedTypeRef,
 
-        func_ptr_from_string: u32,
-        func_ptr_to_string: u32,
-        func_ptr_unk30: u32,
-        func_ptr_copy: u32,
-        func_ptr_equals: u32,
-        func_ptr_constructor: u32,
-        func_ptr_destructor: u32,
-        func_ptr_get_serialized_size: u32,
-        func_ptr_check_range: u32,
-
-        ptr_optimized_type: u32,
+        func_ptr_from_string: Option<u32>,
+        func_ptr_to_string: Option<u32>,
+        func_ptr_unk30: Option<u32>,
+        func_ptr_copy: Option<u32>,
+        func_ptr_equals: Option<u32>,
+        func_ptr_constructor: Option<u32>,
+        func_ptr_destructor: Option<u32>,
+        func_ptr_get_serialized_size: Option<u32>,
+        func_ptr_check_range: Option<u32>,
+
+        ptr_optimized_type: Option<u32>,
     },
     Pointer {
         id: u32,
         factory_flags: u8,
-        ptr: u32,
+        ptr: Option<u32>,
 
         has_pointers: bool, // todo(py): probably not needed
         type_name: String,
@@ -40,7 +46,7 @@ pub enum ExportedType {
     Container {
         id: u32,
         factory_flags: u8,
-        ptr: u32,
+        ptr: Option<u32>,
 
         has_pointers: bool,
         type_name: String,
@@ -51,7 +57,7 @@ pub enum ExportedType {
     Enum {
         id: u32,
         factory_flags: u8,
-        ptr: u32,
+        ptr: Option<u32>,
 
         size: u8,
         alignment: u8,
@@ -62,7 +68,7 @@ pub enum ExportedType {
     Compound {
         id: u32,
         factory_flags: u8,
-        ptr: u32,
+        ptr: Option<u32>,
 
         version: u16,
         size: u32,
@@ -79,24 +85,24 @@ pub enum ExportedType {
 
         message_read_binary: Option<ExportedCompoundMessageReadBinary>,
 
-        func_ptr_constructor: u32,
-        func_ptr_destructor: u32,
-        func_ptr_from_string: u32,
-        func_ptr_unk0: u32,
-        func_ptr_to_string: u32,
-        func_ptr_get_symbol_group: u32,
-        ptr_optimized_type: u32,
+        func_ptr_constructor: Option<u32>,
+        func_ptr_destructor: Option<u32>,
+        func_ptr_from_string: Option<u32>,
+        func_ptr_unk0: Option<u32>,
+        func_ptr_to_string: Option<u32>,
+        func_ptr_get_symbol_group: Option<u32>,
+        ptr_optimized_type: Option<u32>,
 
         unk1: u32,
     },
     Pod {
         id: u32,
         factory_flags: u8,
-        ptr: u32,
+        ptr: Option<u32>,
         size: u32,
     },
     BitSetEnum {
-        ptr: u32,
+        ptr: Option<u32>,
         type_name: String,
     },
 }
@@ -105,12 +111,12 @@ pub enum ExportedType {
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ExportedTypeRef {
     pub type_name: String,
-    pub ptr: u32,
+    pub ptr: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ExportedDataRef<T> {
-    pub ptr: u32,
+    pub ptr: Option<u32>,
     pub data: T,
 }
 
@@ -124,41 +130,41 @@ pub struct ExportedEnumValue {
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ExportedPointerData {
     pub type_name: String,
-    pub ptr: u32,
+    pub ptr: Option<u32>,
     pub size: u16,
     pub alignment: u8,
 
-    pub func_ptr_constructor: u32,
-    pub func_ptr_destructor: u32,
-    pub func_ptr_get: u32,
-    pub func_ptr_set: u32,
-    pub func_ptr_copy: u32,
+    pub func_ptr_constructor: Option<u32>,
+    pub func_ptr_destructor: Option<u32>,
+    pub func_ptr_get: Option<u32>,
+    pub func_ptr_set: Option<u32>,
+    pub func_ptr_copy: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ExportedContainerData {
     pub type_name: String,
-    pub ptr: u32,
+    pub ptr: Option<u32>,
     pub size: u16,
     pub alignment: u8,
     pub simple: bool,
     pub associative: bool,
 
-    pub func_ptr_constructor: u32,
-    pub func_ptr_destructor: u32,
-    pub func_ptr_resize: u32,
-    pub func_ptr_remove: u32,
-    pub func_ptr_length: u32,
-    pub func_ptr_iter_start: u32,
-    pub func_ptr_iter_end: u32,
-    pub func_ptr_iter_next: u32,
-    pub func_ptr_iter_deref: u32,
-    pub func_ptr_iter_valid: u32,
-    pub func_ptr_add_item: u32,
-    pub func_ptr_add_empty: u32,
-    pub func_ptr_clear: u32,
-    pub func_ptr_to_string: u32,
-    pub func_ptr_from_string: u32,
+    pub func_ptr_constructor: Option<u32>,
+    pub func_ptr_destructor: Option<u32>,
+    pub func_ptr_resize: Option<u32>,
+    pub func_ptr_remove: Option<u32>,
+    pub func_ptr_length: Option<u32>,
+    pub func_ptr_iter_start: Option<u32>,
+    pub func_ptr_iter_end: Option<u32>,
+    pub func_ptr_iter_next: Option<u32>,
+    pub func_ptr_iter_deref: Option<u32>,
+    pub func_ptr_iter_valid: Option<u32>,
+    pub func_ptr_add_item: Option<u32>,
+    pub func_ptr_add_empty: Option<u32>,
+    pub func_ptr_clear: Option<u32>,
+    pub func_ptr_to_string: Option<u32>,
+    pub func_ptr_from_string: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -175,8 +181,8 @@ pub struct ExportedCompoundAttribute {
     pub flags: u16,
     pub range_min: Option<String>,
     pub range_max: Option<String>,
-    pub func_ptr_get: u32,
-    pub func_ptr_set: u32,
+    pub func_ptr_get: Option<u32>,
+    pub func_ptr_set: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -191,14 +197,14 @@ pub struct ExportedCompoundOrderedAttribute {
     pub parent: Option<ExportedTypeRef>,
     pub group: Option<String>,
 
-    pub func_ptr_get: u32,
-    pub func_ptr_set: u32,
+    pub func_ptr_get: Option<u32>,
+    pub func_ptr_set: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ExportedCompoundMessageHandler {
     pub message: ExportedTypeRef,
-    pub func_ptr_handler: u32,
+    pub func_ptr_handler: Option<u32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
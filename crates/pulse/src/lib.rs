@@ -12,7 +12,7 @@ use libdecima_core::types::core::rtti::{DecimaRTTIKind, RTTIWithName};
 
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
-pub unsafe extern "C" fn CauldronMod_Load(loader_api: *const CauldronApi) -> bool {
+pub unsafe extern "C-unwind" fn CauldronMod_Load(loader_api: *const CauldronApi) -> bool {
     let loader = unsafe { &*loader_api };
     init_mod_logger(loader).expect("pulse: failed to initialize mod logger.");
 
@@ -52,9 +52,11 @@ pub unsafe extern "C" fn CauldronMod_Load(loader_api: *const CauldronApi) -> boo
     true
 }
 
+cauldron::export_api_version!(1);
+
 #[unsafe(no_mangle)]
 #[allow(non_snake_case)]
-pub unsafe extern "C" fn CauldronMod_Info() -> *const CauldronModInfo {
+pub unsafe extern "C-unwind" fn CauldronMod_Info() -> *const CauldronModInfo {
     let info = Box::new(
         CauldronModInfo::builder("pulse", env!("CARGO_PKG_VERSION"))
             .display_name("Pulse")